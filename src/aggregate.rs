@@ -0,0 +1,441 @@
+use crate::bound_expr::BoundExpr;
+use crate::db_header::TextEncoding;
+use crate::error::{ReaderError, Result};
+use crate::spill::{row_size, SpillFile};
+use crate::value::{Affinity, Collation, Value};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+
+/* how much the groups of a GROUP BY hold in memory before new groups are spilled to disk */
+pub const DEFAULT_GROUP_MEMORY: usize = 64 * 1024 * 1024;
+
+/* integers this large lose precision as a double, so they are added in two parts */
+const EXACT_DOUBLE_LIMIT: i64 = 4503599627370496;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AggregateFunction {
+    Count,
+    Sum,
+    Total,
+    Avg,
+    Min,
+    Max,
+    GroupConcat,
+}
+
+impl AggregateFunction {
+    /* None for names that aren't aggregates, min and max with several arguments are scalar */
+    pub fn from_name(name: &str, arg_count: usize) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "count" => Some(AggregateFunction::Count),
+            "sum" => Some(AggregateFunction::Sum),
+            "total" => Some(AggregateFunction::Total),
+            "avg" => Some(AggregateFunction::Avg),
+            "min" if arg_count <= 1 => Some(AggregateFunction::Min),
+            "max" if arg_count <= 1 => Some(AggregateFunction::Max),
+            "group_concat" | "string_agg" => Some(AggregateFunction::GroupConcat),
+            _ => None,
+        }
+    }
+
+    /* only count takes a *, and count() is count(*) */
+    pub fn accepts(&self, arg_count: usize, star: bool) -> bool {
+        match self {
+            AggregateFunction::Count => arg_count <= 1,
+            AggregateFunction::GroupConcat => !star && (1..=2).contains(&arg_count),
+            _ => !star && arg_count == 1,
+        }
+    }
+}
+
+/* an aggregate call in a query, with its arguments bound to the table row */
+#[derive(Debug, Clone, PartialEq)]
+pub struct AggregateCall {
+    pub function: AggregateFunction,
+    pub args: Vec<BoundExpr>,
+    pub distinct: bool,
+    /* how min, max and DISTINCT compare text, the argument column's collation */
+    pub collation: Collation,
+}
+
+/* a value as a hashable key, equal whenever the values compare equal under the collation */
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum KeyPart {
+    Null,
+    Integer(i64),
+    /* the bits of a real that isn't a whole number, whole ones are integers */
+    Real(u64),
+    Text(String),
+    Blob(Vec<u8>),
+}
+
+impl KeyPart {
+    pub fn new(value: &Value, collation: Collation) -> Self {
+        match value {
+            Value::Null => KeyPart::Null,
+            Value::Integer(int) => KeyPart::Integer(*int),
+            Value::Real(real)
+                if real.fract() == 0.0
+                    && (-9223372036854775808.0..9223372036854775808.0).contains(real) =>
+            {
+                KeyPart::Integer(*real as i64)
+            }
+            Value::Real(real) => KeyPart::Real(real.to_bits()),
+            Value::Text(text) => KeyPart::Text(match collation {
+                Collation::Binary => text.clone(),
+                Collation::NoCase => text.to_ascii_lowercase(),
+                Collation::RTrim => text.trim_end_matches(' ').to_string(),
+            }),
+            Value::Blob(blob) => KeyPart::Blob(blob.clone()),
+        }
+    }
+}
+
+/*
+    sum(), total() and avg() add integers exactly until one overflows or a non-integer turns
+    up, then switch to Kahan-Babuska-Neumaier summation of doubles, like sqlite does
+*/
+#[derive(Debug, Clone, Default)]
+struct Sum {
+    count: i64,
+    int: i64,
+    real: f64,
+    error: f64,
+    approximate: bool,
+    overflow: bool,
+}
+
+impl Sum {
+    fn step(&mut self, value: &Value) {
+        /* text that looks like a number counts as that number, like sqlite3_value_numeric_type */
+        let value = value.clone().apply_affinity(Affinity::Numeric);
+        self.count += 1;
+        match value {
+            Value::Integer(int) if !self.approximate => match self.int.checked_add(int) {
+                Some(sum) => self.int = sum,
+                None => {
+                    self.overflow = true;
+                    self.start_approximate();
+                    self.add_integer(int);
+                }
+            },
+            Value::Integer(int) => self.add_integer(int),
+            value => {
+                if !self.approximate {
+                    self.start_approximate();
+                } else {
+                    self.overflow = false;
+                }
+                self.add_real(value.to_real());
+            }
+        }
+    }
+
+    fn start_approximate(&mut self) {
+        self.approximate = true;
+        self.real = 0.0;
+        self.error = 0.0;
+        self.add_integer(self.int);
+    }
+
+    fn add_integer(&mut self, int: i64) {
+        if int <= -EXACT_DOUBLE_LIMIT || int >= EXACT_DOUBLE_LIMIT {
+            let small = int % 16384;
+            self.add_real((int - small) as f64);
+            self.add_real(small as f64);
+        } else {
+            self.add_real(int as f64);
+        }
+    }
+
+    fn add_real(&mut self, real: f64) {
+        let sum = self.real + real;
+        if self.real.abs() > real.abs() {
+            self.error += (self.real - sum) + real;
+        } else {
+            self.error += (real - sum) + self.real;
+        }
+        self.real = sum;
+    }
+
+    fn total(&self) -> f64 {
+        if !self.approximate {
+            return self.int as f64;
+        }
+        if self.error.is_infinite() {
+            self.real
+        } else {
+            self.real + self.error
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum State {
+    Count(i64),
+    Sum(Sum),
+    /* the min() or max() so far */
+    Extreme(Option<Value>),
+    Concat(Option<String>),
+}
+
+/* the running state of one aggregate call over the rows of one group */
+#[derive(Debug, Clone)]
+pub struct Accumulator {
+    state: State,
+    /* the arguments seen so far, for DISTINCT calls */
+    seen: Option<HashSet<KeyPart>>,
+}
+
+impl Accumulator {
+    pub fn new(call: &AggregateCall) -> Self {
+        let state = match call.function {
+            AggregateFunction::Count => State::Count(0),
+            AggregateFunction::Sum | AggregateFunction::Total | AggregateFunction::Avg => {
+                State::Sum(Sum::default())
+            }
+            AggregateFunction::Min | AggregateFunction::Max => State::Extreme(None),
+            AggregateFunction::GroupConcat => State::Concat(None),
+        };
+        Self {
+            state,
+            seen: call.distinct.then(HashSet::new),
+        }
+    }
+
+    /*
+        folds a row in. returns roughly how many bytes the state grew by, and whether a min() or
+        max() took the row's value
+    */
+    pub fn step(
+        &mut self,
+        call: &AggregateCall,
+        row: &[Value],
+        encoding: TextEncoding,
+    ) -> Result<(usize, bool)> {
+        /* count(*) counts every row, everything else skips NULL arguments */
+        let value = match call.args.first() {
+            Some(arg) => arg.eval(row, encoding)?,
+            None => {
+                if let State::Count(count) = &mut self.state {
+                    *count += 1;
+                }
+                return Ok((0, false));
+            }
+        };
+        if value.is_null() {
+            return Ok((0, false));
+        }
+        let mut grown = 0;
+        if let Some(seen) = self.seen.as_mut() {
+            if !seen.insert(KeyPart::new(&value, call.collation)) {
+                return Ok((0, false));
+            }
+            grown += row_size(std::slice::from_ref(&value));
+        }
+
+        match &mut self.state {
+            State::Count(count) => *count += 1,
+            State::Sum(sum) => sum.step(&value),
+            State::Extreme(extreme) => {
+                /* ties keep the earlier value */
+                let wanted = match call.function {
+                    AggregateFunction::Min => Ordering::Less,
+                    _ => Ordering::Greater,
+                };
+                let replace = extreme.as_ref().map_or(true, |current| {
+                    value.compare_collated(current, call.collation, encoding) == wanted
+                });
+                if replace {
+                    *extreme = Some(value);
+                    return Ok((grown, true));
+                }
+            }
+            State::Concat(concat) => {
+                let text = value.to_string();
+                grown += text.len();
+                match concat {
+                    Some(concat) => {
+                        /* the separator is the current row's, "," when there is none */
+                        let separator = match call.args.get(1) {
+                            Some(separator) => separator.eval(row, encoding)?.to_string(),
+                            None => ",".to_string(),
+                        };
+                        grown += separator.len();
+                        concat.push_str(&separator);
+                        concat.push_str(&text);
+                    }
+                    None => *concat = Some(text),
+                }
+            }
+        }
+        Ok((grown, false))
+    }
+
+    pub fn finish(&self, call: &AggregateCall) -> Result<Value> {
+        Ok(match (&self.state, call.function) {
+            (State::Count(count), _) => Value::Integer(*count),
+            (State::Sum(sum), AggregateFunction::Total) => Value::Real(sum.total()),
+            (State::Sum(sum), _) if sum.count == 0 => Value::Null,
+            (State::Sum(sum), AggregateFunction::Avg) => {
+                Value::Real(sum.total() / sum.count as f64)
+            }
+            (State::Sum(sum), _) if sum.overflow => return Err(ReaderError::IntegerOverflow),
+            (State::Sum(sum), _) if sum.approximate => Value::Real(sum.total()),
+            (State::Sum(sum), _) => Value::Integer(sum.int),
+            (State::Extreme(extreme), _) => extreme.clone().unwrap_or(Value::Null),
+            (State::Concat(concat), _) => concat.clone().map_or(Value::Null, Value::Text),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Group {
+    /* the row bare columns are read from */
+    row: Vec<Value>,
+    accumulators: Vec<Accumulator>,
+}
+
+/*
+    groups rows by their key values and runs the aggregate calls over each group. once the
+    groups outgrow the memory budget, rows of groups that aren't held yet are spilled to disk
+    and grouped in another pass after these groups are done
+*/
+pub struct Grouper {
+    keys: Vec<(BoundExpr, Collation)>,
+    calls: Vec<AggregateCall>,
+    /* without GROUP BY every row is one group, which exists even when there are no rows */
+    single_group: bool,
+    /* the joined row's column count, the aggregate results follow the columns */
+    width: usize,
+    /* the only min() or max() call, when there is exactly one. bare columns read its row */
+    extreme: Option<usize>,
+    encoding: TextEncoding,
+    memory_budget: usize,
+    groups: HashMap<Vec<KeyPart>, Group>,
+    bytes: usize,
+    overflow: Option<SpillFile>,
+}
+
+impl Grouper {
+    pub fn new(
+        keys: Vec<(BoundExpr, Collation)>,
+        calls: Vec<AggregateCall>,
+        width: usize,
+        encoding: TextEncoding,
+        memory_budget: usize,
+    ) -> Self {
+        let extremes: Vec<usize> = calls
+            .iter()
+            .enumerate()
+            .filter(|(_, call)| {
+                matches!(
+                    call.function,
+                    AggregateFunction::Min | AggregateFunction::Max
+                )
+            })
+            .map(|(slot, _)| slot)
+            .collect();
+        Self {
+            single_group: keys.is_empty(),
+            extreme: (extremes.len() == 1).then(|| extremes[0]),
+            keys,
+            calls,
+            width,
+            encoding,
+            memory_budget,
+            groups: HashMap::new(),
+            bytes: 0,
+            overflow: None,
+        }
+    }
+
+    pub fn push(&mut self, row: Vec<Value>) -> Result<()> {
+        let key = self
+            .keys
+            .iter()
+            .map(|(expr, collation)| Ok(KeyPart::new(&expr.eval(&row, self.encoding)?, *collation)))
+            .collect::<Result<Vec<_>>>()?;
+
+        if let Some(group) = self.groups.get_mut(&key) {
+            self.bytes += step(group, &self.calls, self.extreme, row, self.encoding)?;
+            return Ok(());
+        }
+        if self.groups.is_empty() || self.bytes < self.memory_budget {
+            let mut group = Group {
+                row: row.clone(),
+                accumulators: self.calls.iter().map(Accumulator::new).collect(),
+            };
+            self.bytes +=
+                row_size(&row) + step(&mut group, &self.calls, self.extreme, row, self.encoding)?;
+            self.groups.insert(key, group);
+            return Ok(());
+        }
+        let overflow = match self.overflow.as_mut() {
+            Some(overflow) => overflow,
+            None => self.overflow.insert(SpillFile::create()?),
+        };
+        overflow.write_row(&row)
+    }
+
+    /* emits each group as its bare row followed by the results of the aggregate calls */
+    pub fn finish(self, emit: &mut impl FnMut(Vec<Value>) -> Result<()>) -> Result<()> {
+        let mut grouper = self;
+        if grouper.single_group && grouper.groups.is_empty() {
+            let group = Group {
+                row: Vec::new(),
+                accumulators: grouper.calls.iter().map(Accumulator::new).collect(),
+            };
+            grouper.groups.insert(Vec::new(), group);
+        }
+
+        loop {
+            for (_, group) in grouper.groups.drain() {
+                let mut row = group.row;
+                row.resize(grouper.width, Value::Null);
+                for (accumulator, call) in group.accumulators.iter().zip(grouper.calls.iter()) {
+                    row.push(accumulator.finish(call)?);
+                }
+                emit(row)?;
+            }
+
+            let overflow = match grouper.overflow.take() {
+                Some(overflow) => overflow,
+                None => return Ok(()),
+            };
+            let mut next = Grouper::new(
+                grouper.keys.clone(),
+                grouper.calls.clone(),
+                grouper.width,
+                grouper.encoding,
+                grouper.memory_budget,
+            );
+            for row in overflow.into_reader()? {
+                next.push(row?)?;
+            }
+            grouper = next;
+        }
+    }
+}
+
+/* folds the row into the group's aggregates, returning how many bytes the group grew by */
+fn step(
+    group: &mut Group,
+    calls: &[AggregateCall],
+    extreme: Option<usize>,
+    row: Vec<Value>,
+    encoding: TextEncoding,
+) -> Result<usize> {
+    let mut grown = 0;
+    let mut new_extreme = false;
+    for (slot, (accumulator, call)) in group.accumulators.iter_mut().zip(calls).enumerate() {
+        let (bytes, took_value) = accumulator.step(call, &row, encoding)?;
+        grown += bytes;
+        new_extreme |= took_value && extreme == Some(slot);
+    }
+    /* bare columns come from the group's first row, or the row holding the min() or max() */
+    if new_extreme {
+        group.row = row;
+    }
+    Ok(grown)
+}
@@ -0,0 +1,537 @@
+use crate::parser::identifiers_match;
+use crate::value::{Affinity, Value};
+
+/* a parsed sql statement */
+#[derive(Debug, Clone, PartialEq)]
+pub enum Statement {
+    Select(Select),
+    CreateTable(CreateTable),
+    CreateIndex(CreateIndex),
+    CreateView(CreateView),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Select {
+    /* SELECT DISTINCT, which outputs each row only once */
+    pub distinct: bool,
+    pub columns: Vec<ResultColumn>,
+    /* the tables of the FROM clause, each joined to the ones before it */
+    pub from: Vec<TableReference>,
+    pub filter: Option<Expr>,
+    pub group_by: Vec<Expr>,
+    pub having: Option<Expr>,
+    pub order_by: Vec<OrderingTerm>,
+    pub limit: Option<Expr>,
+    pub offset: Option<Expr>,
+}
+
+/* table-name [[AS] alias] in a FROM clause, with how it joins the tables before it */
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableReference {
+    pub table_name: String,
+    pub alias: Option<String>,
+    /* Inner with no constraint for the first table */
+    pub join: JoinKind,
+    pub constraint: Option<JoinConstraint>,
+}
+
+/* comma joins and CROSS JOIN are inner joins without a constraint */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinKind {
+    Inner,
+    /* LEFT [OUTER] JOIN, which pads a row without matches with NULLs */
+    Left,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum JoinConstraint {
+    On(Expr),
+    /* the named columns are equal in both tables, and output once */
+    Using(Vec<String>),
+}
+
+/* one ORDER BY term: expr [COLLATE name] [ASC | DESC] [NULLS FIRST | NULLS LAST] */
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderingTerm {
+    pub expr: Expr,
+    pub collation: Option<String>,
+    pub descending: bool,
+    /* None leaves NULLs where the direction puts them, first for ASC and last for DESC */
+    pub nulls_first: Option<bool>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResultColumn {
+    /* every column of every table, in declaration order */
+    Star,
+    /* table.*, every column of one table */
+    TableStar(String),
+    /* expr [[AS] alias] */
+    Expr { expr: Expr, alias: Option<String> },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Literal(Value),
+    /* a column name, qualified by a table name or alias as table.column */
+    Column {
+        table: Option<String>,
+        name: String,
+    },
+    Unary {
+        op: UnaryOp,
+        expr: Box<Expr>,
+    },
+    Binary {
+        op: BinaryOp,
+        left: Box<Expr>,
+        right: Box<Expr>,
+    },
+    /* expr [NOT] LIKE pattern [ESCAPE escape], or GLOB which takes no escape */
+    Like {
+        expr: Box<Expr>,
+        pattern: Box<Expr>,
+        escape: Option<Box<Expr>>,
+        glob: bool,
+        negated: bool,
+    },
+    In {
+        expr: Box<Expr>,
+        list: Vec<Expr>,
+        negated: bool,
+    },
+    Between {
+        expr: Box<Expr>,
+        low: Box<Expr>,
+        high: Box<Expr>,
+        negated: bool,
+    },
+    /* CASE [operand] WHEN .. THEN .. [ELSE ..] END, each WHEN compared to the operand if any */
+    Case {
+        operand: Option<Box<Expr>>,
+        branches: Vec<(Expr, Expr)>,
+        otherwise: Option<Box<Expr>>,
+    },
+    Cast {
+        expr: Box<Expr>,
+        type_name: String,
+    },
+    /* expr COLLATE name, which leaves the value alone and only changes how it compares */
+    Collate {
+        expr: Box<Expr>,
+        collation: String,
+    },
+    /* star is set for count(*) style calls, which take no arguments */
+    Function {
+        name: String,
+        args: Vec<Expr>,
+        star: bool,
+        /* count(DISTINCT x) style calls, which only see each distinct argument once */
+        distinct: bool,
+    },
+}
+
+impl Expr {
+    /*
+        a copy with each unqualified column reference `resolve` returns Some for replaced by
+        what it returns
+    */
+    pub fn replace_columns(&self, resolve: &impl Fn(&str) -> Option<Expr>) -> Expr {
+        let replace = |expr: &Expr| expr.replace_columns(resolve);
+        let replace_boxed = |expr: &Expr| Box::new(expr.replace_columns(resolve));
+        match self {
+            Expr::Literal(_) => self.clone(),
+            Expr::Column { table: None, name } => resolve(name).unwrap_or_else(|| self.clone()),
+            Expr::Column { .. } => self.clone(),
+            Expr::Unary { op, expr } => Expr::Unary {
+                op: *op,
+                expr: replace_boxed(expr),
+            },
+            Expr::Binary { op, left, right } => Expr::Binary {
+                op: *op,
+                left: replace_boxed(left),
+                right: replace_boxed(right),
+            },
+            Expr::Like {
+                expr,
+                pattern,
+                escape,
+                glob,
+                negated,
+            } => Expr::Like {
+                expr: replace_boxed(expr),
+                pattern: replace_boxed(pattern),
+                escape: escape.as_deref().map(replace_boxed),
+                glob: *glob,
+                negated: *negated,
+            },
+            Expr::In {
+                expr,
+                list,
+                negated,
+            } => Expr::In {
+                expr: replace_boxed(expr),
+                list: list.iter().map(replace).collect(),
+                negated: *negated,
+            },
+            Expr::Between {
+                expr,
+                low,
+                high,
+                negated,
+            } => Expr::Between {
+                expr: replace_boxed(expr),
+                low: replace_boxed(low),
+                high: replace_boxed(high),
+                negated: *negated,
+            },
+            Expr::Case {
+                operand,
+                branches,
+                otherwise,
+            } => Expr::Case {
+                operand: operand.as_deref().map(replace_boxed),
+                branches: branches
+                    .iter()
+                    .map(|(when, then)| (replace(when), replace(then)))
+                    .collect(),
+                otherwise: otherwise.as_deref().map(replace_boxed),
+            },
+            Expr::Cast { expr, type_name } => Expr::Cast {
+                expr: replace_boxed(expr),
+                type_name: type_name.clone(),
+            },
+            Expr::Collate { expr, collation } => Expr::Collate {
+                expr: replace_boxed(expr),
+                collation: collation.clone(),
+            },
+            Expr::Function {
+                name,
+                args,
+                star,
+                distinct,
+            } => Expr::Function {
+                name: name.clone(),
+                args: args.iter().map(replace).collect(),
+                star: *star,
+                distinct: *distinct,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    Not,
+    Negate,
+    /* a no-op on the value, but the operand loses its column affinity */
+    Plus,
+    BitNot,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    And,
+    Or,
+    Eq,
+    NotEq,
+    /* IS and IS NOT treat NULL as an ordinary value that equals itself */
+    Is,
+    IsNot,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+    Concat,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+    BitAnd,
+    BitOr,
+    ShiftLeft,
+    ShiftRight,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ColumnConstraint {
+    PrimaryKey {
+        descending: bool,
+        autoincrement: bool,
+    },
+    NotNull,
+    Unique,
+    Check(String),
+    Default(String),
+    Collate(String),
+    References(String),
+    Generated {
+        expr: String,
+        stored: bool,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnDef {
+    pub name: String,
+    pub type_name: Option<String>,
+    pub constraints: Vec<ColumnConstraint>,
+}
+
+impl ColumnDef {
+    pub fn affinity(&self) -> Affinity {
+        Affinity::from_type_name(self.type_name.as_deref())
+    }
+
+    pub fn is_primary_key(&self) -> bool {
+        self.constraints
+            .iter()
+            .any(|constraint| matches!(constraint, ColumnConstraint::PrimaryKey { .. }))
+    }
+
+    pub fn not_null(&self) -> bool {
+        self.constraints
+            .iter()
+            .any(|constraint| matches!(constraint, ColumnConstraint::NotNull))
+    }
+
+    /* the declared COLLATE, which comparisons and ORDER BY on the column default to */
+    pub fn collation(&self) -> Option<&str> {
+        self.constraints
+            .iter()
+            .find_map(|constraint| match constraint {
+                ColumnConstraint::Collate(name) => Some(name.as_str()),
+                _ => None,
+            })
+    }
+
+    /* Some(stored) for generated columns */
+    pub fn generated(&self) -> Option<bool> {
+        self.constraints
+            .iter()
+            .find_map(|constraint| match constraint {
+                ColumnConstraint::Generated { stored, .. } => Some(*stored),
+                _ => None,
+            })
+    }
+
+    /* the text of the expression a generated column is computed from */
+    pub fn generation(&self) -> Option<&str> {
+        self.constraints
+            .iter()
+            .find_map(|constraint| match constraint {
+                ColumnConstraint::Generated { expr, .. } => Some(expr.as_str()),
+                _ => None,
+            })
+    }
+
+    /* the DEFAULT as written, without the parentheses around an expression */
+    pub fn default_value(&self) -> Option<&str> {
+        self.constraints
+            .iter()
+            .find_map(|constraint| match constraint {
+                ColumnConstraint::Default(value) => Some(value.as_str()),
+                _ => None,
+            })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TableConstraint {
+    PrimaryKey(Vec<IndexedColumn>),
+    Unique(Vec<IndexedColumn>),
+    Check(String),
+    ForeignKey {
+        columns: Vec<String>,
+        foreign_table: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreateTable {
+    pub table_name: String,
+    pub columns: Vec<ColumnDef>,
+    pub constraints: Vec<TableConstraint>,
+    pub without_rowid: bool,
+    pub strict: bool,
+}
+
+impl CreateTable {
+    /*
+        the column that stores the rowid itself: declared exactly INTEGER and the sole
+        PRIMARY KEY, except the INTEGER PRIMARY KEY DESC quirk, which stays an ordinary column
+    */
+    pub fn rowid_alias(&self) -> Option<usize> {
+        if self.without_rowid {
+            return None;
+        }
+        let is_integer = |column: &ColumnDef| {
+            column
+                .type_name
+                .as_deref()
+                .is_some_and(|type_name| type_name.eq_ignore_ascii_case("INTEGER"))
+        };
+
+        if let Some(pos) = self.columns.iter().position(ColumnDef::is_primary_key) {
+            let column = &self.columns[pos];
+            let descending = column.constraints.iter().any(|constraint| {
+                matches!(
+                    constraint,
+                    ColumnConstraint::PrimaryKey {
+                        descending: true,
+                        ..
+                    }
+                )
+            });
+            return (is_integer(column) && !descending).then_some(pos);
+        }
+
+        self.constraints
+            .iter()
+            .find_map(|constraint| match constraint {
+                TableConstraint::PrimaryKey(columns) if columns.len() == 1 => self
+                    .columns
+                    .iter()
+                    .position(|column| identifiers_match(&column.name, &columns[0].name))
+                    .filter(|pos| is_integer(&self.columns[*pos])),
+                _ => None,
+            })
+    }
+
+    /* the PRIMARY KEY columns, in the order the key lists them */
+    pub fn primary_key(&self) -> Vec<usize> {
+        if let Some(pos) = self.columns.iter().position(ColumnDef::is_primary_key) {
+            return vec![pos];
+        }
+        self.constraints
+            .iter()
+            .find_map(|constraint| match constraint {
+                TableConstraint::PrimaryKey(columns) => Some(
+                    columns
+                        .iter()
+                        .filter_map(|key| self.column_position(&key.name))
+                        .collect(),
+                ),
+                _ => None,
+            })
+            .unwrap_or_default()
+    }
+
+    fn column_position(&self, name: &str) -> Option<usize> {
+        self.columns
+            .iter()
+            .position(|column| identifiers_match(&column.name, name))
+    }
+
+    /*
+        the keys of the indexes sqlite creates for the PRIMARY KEY and UNIQUE constraints, in
+        the N order of their sqlite_autoindex_<table>_<N> names: column constraints as the
+        columns come, then the table constraints. a key repeating an earlier one gets no index,
+        and neither does a PRIMARY KEY that is the row_id alias
+    */
+    pub fn autoindexes(&self) -> Vec<Vec<IndexedColumn>> {
+        let mut keys = Vec::new();
+        for column in self.columns.iter() {
+            for constraint in column.constraints.iter() {
+                let (primary, descending) = match constraint {
+                    ColumnConstraint::PrimaryKey { descending, .. } => (true, *descending),
+                    ColumnConstraint::Unique => (false, false),
+                    _ => continue,
+                };
+                let key = vec![IndexedColumn {
+                    name: column.name.clone(),
+                    collation: None,
+                    descending,
+                }];
+                keys.push((primary, key));
+            }
+        }
+        for constraint in self.constraints.iter() {
+            match constraint {
+                TableConstraint::PrimaryKey(key) => keys.push((true, key.clone())),
+                TableConstraint::Unique(key) => keys.push((false, key.clone())),
+                _ => {}
+            }
+        }
+
+        let alias = self.rowid_alias();
+        let mut indexes: Vec<Vec<IndexedColumn>> = Vec::new();
+        for (primary, key) in keys {
+            let is_alias = primary && alias.is_some();
+            let repeated = indexes.iter().any(|index| {
+                index.len() == key.len()
+                    && index.iter().zip(key.iter()).all(|(left, right)| {
+                        identifiers_match(&left.name, &right.name)
+                            && self.key_collation(left) == self.key_collation(right)
+                    })
+            });
+            if !is_alias && !repeated {
+                indexes.push(key);
+            }
+        }
+        indexes
+    }
+
+    /* the collation name a key column sorts by, its own or else the table column's */
+    fn key_collation(&self, key: &IndexedColumn) -> String {
+        key.collation
+            .as_deref()
+            .or_else(|| {
+                self.column_position(&key.name)
+                    .and_then(|pos| self.columns[pos].collation())
+            })
+            .unwrap_or("BINARY")
+            .to_ascii_uppercase()
+    }
+
+    /*
+        where each column sits in the stored record. columns are stored in declaration order,
+        except VIRTUAL generated columns which aren't stored at all. a WITHOUT ROWID table's
+        record is its index entry, which starts with the PRIMARY KEY columns
+    */
+    pub fn record_positions(&self) -> Vec<Option<usize>> {
+        let mut order = if self.without_rowid {
+            self.primary_key()
+        } else {
+            Vec::new()
+        };
+        for (pos, column) in self.columns.iter().enumerate() {
+            if column.generated() != Some(false) && !order.contains(&pos) {
+                order.push(pos);
+            }
+        }
+
+        let mut positions = vec![None; self.columns.len()];
+        for (record_pos, pos) in order.into_iter().enumerate() {
+            positions[pos] = Some(record_pos);
+        }
+        positions
+    }
+}
+
+/* a column of an index key, which can sort under its own collation or in descending order */
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexedColumn {
+    pub name: String,
+    pub collation: Option<String>,
+    pub descending: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreateIndex {
+    pub index_name: String,
+    pub table_name: String,
+    pub columns: Vec<IndexedColumn>,
+    pub unique: bool,
+    /* the WHERE of a partial index, which only holds the rows matching it */
+    pub condition: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreateView {
+    pub view_name: String,
+    /* the optional column names given after the view name */
+    pub columns: Vec<String>,
+    pub select: Select,
+}
@@ -0,0 +1,676 @@
+use crate::aggregate::{AggregateCall, AggregateFunction};
+use crate::ast::{BinaryOp, Expr, UnaryOp};
+use crate::db_header::TextEncoding;
+use crate::error::{ReaderError, Result};
+use crate::join::Source;
+use crate::pattern;
+use crate::scalar::{self, ScalarFunction};
+use crate::value::{Affinity, Collation, Value};
+use std::cmp::Ordering;
+
+/* an expression with its column names resolved to positions in the row of the FROM clause */
+#[derive(Debug, Clone, PartialEq)]
+pub enum BoundExpr {
+    Literal(Value),
+    Column(usize),
+    /*
+        each side is converted to the affinity beside it first, see datatype3 section 4.2. text
+        compares by the left column's collation, else the right one's, else BINARY
+    */
+    Compare {
+        op: BinaryOp,
+        left: Box<BoundExpr>,
+        left_affinity: Option<Affinity>,
+        right: Box<BoundExpr>,
+        right_affinity: Option<Affinity>,
+        collation: Collation,
+    },
+    Like {
+        expr: Box<BoundExpr>,
+        pattern: Box<BoundExpr>,
+        escape: Option<char>,
+        glob: bool,
+    },
+    And(Box<BoundExpr>, Box<BoundExpr>),
+    Or(Box<BoundExpr>, Box<BoundExpr>),
+    Not(Box<BoundExpr>),
+    /* arithmetic, || and the bitwise operators */
+    Binary {
+        op: BinaryOp,
+        left: Box<BoundExpr>,
+        right: Box<BoundExpr>,
+    },
+    Negate(Box<BoundExpr>),
+    BitNot(Box<BoundExpr>),
+    /* the THEN of the first WHEN that is true, a CASE operand is compared into each WHEN */
+    Case {
+        branches: Vec<(BoundExpr, BoundExpr)>,
+        otherwise: Option<Box<BoundExpr>>,
+    },
+    Cast {
+        expr: Box<BoundExpr>,
+        affinity: Affinity,
+    },
+    /* an explicit COLLATE, which wins over the collation of any column under it */
+    Collate {
+        expr: Box<BoundExpr>,
+        collation: Collation,
+    },
+    Function {
+        function: ScalarFunction,
+        args: Vec<BoundExpr>,
+        /* how nullif, min and max compare text */
+        collation: Collation,
+    },
+}
+
+impl BoundExpr {
+    pub fn bind(expr: &Expr, source: &Source) -> Result<Self> {
+        Self::bind_in(expr, source, &mut None)
+    }
+
+    /*
+        binds an expression of an aggregate query, where each aggregate call is added to
+        `aggregates` and reads its result from the row, after the columns of the tables
+    */
+    pub fn bind_aggregate(
+        expr: &Expr,
+        source: &Source,
+        aggregates: &mut Vec<AggregateCall>,
+    ) -> Result<Self> {
+        Self::bind_in(expr, source, &mut Some(aggregates))
+    }
+
+    fn bind_in(
+        expr: &Expr,
+        source: &Source,
+        aggregates: &mut Option<&mut Vec<AggregateCall>>,
+    ) -> Result<Self> {
+        match expr {
+            Expr::Literal(value) => Ok(BoundExpr::Literal(value.clone())),
+            Expr::Column { table, name } => {
+                Ok(BoundExpr::Column(source.resolve(table.as_deref(), name)?))
+            }
+            Expr::Unary {
+                op: UnaryOp::Not,
+                expr,
+            } => Ok(BoundExpr::Not(Box::new(Self::bind_in(
+                expr, source, aggregates,
+            )?))),
+            /* unary plus leaves the value alone, it only drops the column's affinity */
+            Expr::Unary {
+                op: UnaryOp::Plus,
+                expr,
+            } => Self::bind_in(expr, source, aggregates),
+            Expr::Unary {
+                op: UnaryOp::Negate,
+                expr,
+            } => Ok(BoundExpr::Negate(Box::new(Self::bind_in(
+                expr, source, aggregates,
+            )?))),
+            Expr::Unary {
+                op: UnaryOp::BitNot,
+                expr,
+            } => Ok(BoundExpr::BitNot(Box::new(Self::bind_in(
+                expr, source, aggregates,
+            )?))),
+            Expr::Binary {
+                op: BinaryOp::And,
+                left,
+                right,
+            } => Ok(BoundExpr::And(
+                Box::new(Self::bind_in(left, source, aggregates)?),
+                Box::new(Self::bind_in(right, source, aggregates)?),
+            )),
+            Expr::Binary {
+                op: BinaryOp::Or,
+                left,
+                right,
+            } => Ok(BoundExpr::Or(
+                Box::new(Self::bind_in(left, source, aggregates)?),
+                Box::new(Self::bind_in(right, source, aggregates)?),
+            )),
+            Expr::Binary { op, left, right } if is_comparison(*op) => {
+                Self::compare(*op, left, right, source, aggregates)
+            }
+            Expr::Binary { op, left, right } => Ok(BoundExpr::Binary {
+                op: *op,
+                left: Box::new(Self::bind_in(left, source, aggregates)?),
+                right: Box::new(Self::bind_in(right, source, aggregates)?),
+            }),
+            Expr::Case {
+                operand,
+                branches,
+                otherwise,
+            } => {
+                let branches = branches
+                    .iter()
+                    .map(|(when, then)| {
+                        let when = match operand {
+                            Some(operand) => {
+                                Self::compare(BinaryOp::Eq, operand, when, source, aggregates)?
+                            }
+                            None => Self::bind_in(when, source, aggregates)?,
+                        };
+                        Ok((when, Self::bind_in(then, source, aggregates)?))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                let otherwise = match otherwise {
+                    Some(otherwise) => {
+                        Some(Box::new(Self::bind_in(otherwise, source, aggregates)?))
+                    }
+                    None => None,
+                };
+                Ok(BoundExpr::Case {
+                    branches,
+                    otherwise,
+                })
+            }
+            Expr::Cast { expr, type_name } => Ok(BoundExpr::Cast {
+                expr: Box::new(Self::bind_in(expr, source, aggregates)?),
+                affinity: Affinity::from_type_name(Some(type_name)),
+            }),
+            Expr::Collate { expr, collation } => Ok(BoundExpr::Collate {
+                expr: Box::new(Self::bind_in(expr, source, aggregates)?),
+                collation: Collation::from_name(collation).ok_or_else(|| {
+                    ReaderError::Parse(format!("no such collation sequence: {}", collation))
+                })?,
+            }),
+            Expr::Like {
+                expr,
+                pattern,
+                escape,
+                glob,
+                negated,
+            } => {
+                let escape = match escape.as_deref() {
+                    Some(Expr::Literal(Value::Text(escape))) if escape.chars().count() == 1 => {
+                        escape.chars().next()
+                    }
+                    Some(_) => {
+                        return Err(ReaderError::Parse(
+                            "ESCAPE expression must be a single character".to_string(),
+                        ))
+                    }
+                    None => None,
+                };
+                let like = BoundExpr::Like {
+                    expr: Box::new(Self::bind_in(expr, source, aggregates)?),
+                    pattern: Box::new(Self::bind_in(pattern, source, aggregates)?),
+                    escape,
+                    glob: *glob,
+                };
+                Ok(negate(like, *negated))
+            }
+            /* x IN (a, b) is x = a OR x = b, NULLs and affinity included */
+            Expr::In {
+                expr,
+                list,
+                negated,
+            } => {
+                let mut any = BoundExpr::Literal(Value::Integer(0));
+                for (i, item) in list.iter().enumerate() {
+                    let equals = Self::compare(BinaryOp::Eq, expr, item, source, aggregates)?;
+                    any = match i {
+                        0 => equals,
+                        _ => BoundExpr::Or(Box::new(any), Box::new(equals)),
+                    };
+                }
+                Ok(negate(any, *negated))
+            }
+            Expr::Between {
+                expr,
+                low,
+                high,
+                negated,
+            } => {
+                let between = BoundExpr::And(
+                    Box::new(Self::compare(
+                        BinaryOp::GtEq,
+                        expr,
+                        low,
+                        source,
+                        aggregates,
+                    )?),
+                    Box::new(Self::compare(
+                        BinaryOp::LtEq,
+                        expr,
+                        high,
+                        source,
+                        aggregates,
+                    )?),
+                );
+                Ok(negate(between, *negated))
+            }
+            Expr::Function {
+                name,
+                args,
+                star,
+                distinct,
+            } => {
+                let function = match AggregateFunction::from_name(name, args.len()) {
+                    Some(function) => function,
+                    None => return Self::bind_scalar(name, args, *star, source, aggregates),
+                };
+                if !function.accepts(args.len(), *star) {
+                    return Err(ReaderError::Parse(format!(
+                        "wrong number of arguments to function {}()",
+                        name
+                    )));
+                }
+                let aggregates = match aggregates {
+                    Some(aggregates) => aggregates,
+                    None => {
+                        return Err(ReaderError::Parse(format!(
+                            "misuse of aggregate: {}()",
+                            name
+                        )))
+                    }
+                };
+                if *distinct && args.len() != 1 {
+                    return Err(ReaderError::Parse(
+                        "DISTINCT aggregates must have exactly one argument".to_string(),
+                    ));
+                }
+                /* an aggregate's arguments are evaluated per row, so they can't hold aggregates */
+                let args = args
+                    .iter()
+                    .map(|arg| Self::bind(arg, source))
+                    .collect::<Result<Vec<_>>>()?;
+                let collation = args
+                    .first()
+                    .map_or(Collation::Binary, |arg| arg.collation(source));
+                let call = AggregateCall {
+                    function,
+                    args,
+                    distinct: *distinct,
+                    collation,
+                };
+                /* the same call written twice is only computed once */
+                let slot = match aggregates.iter().position(|existing| *existing == call) {
+                    Some(slot) => slot,
+                    None => {
+                        aggregates.push(call);
+                        aggregates.len() - 1
+                    }
+                };
+                Ok(BoundExpr::Column(source.width() + slot))
+            }
+        }
+    }
+
+    fn bind_scalar(
+        name: &str,
+        args: &[Expr],
+        star: bool,
+        source: &Source,
+        aggregates: &mut Option<&mut Vec<AggregateCall>>,
+    ) -> Result<Self> {
+        let function = ScalarFunction::from_name(name)
+            .ok_or_else(|| ReaderError::Unsupported(format!("function {}()", name)))?;
+        if star || !function.accepts(args.len()) {
+            return Err(ReaderError::Parse(format!(
+                "wrong number of arguments to function {}()",
+                name
+            )));
+        }
+        let args = args
+            .iter()
+            .map(|arg| Self::bind_in(arg, source, aggregates))
+            .collect::<Result<Vec<_>>>()?;
+        /* the first argument with a collation decides, even when it compares by BINARY */
+        let collation = args
+            .iter()
+            .find_map(|arg| arg.own_collation(source))
+            .unwrap_or_default();
+        Ok(BoundExpr::Function {
+            function,
+            args,
+            collation,
+        })
+    }
+
+    fn compare(
+        op: BinaryOp,
+        left: &Expr,
+        right: &Expr,
+        source: &Source,
+        aggregates: &mut Option<&mut Vec<AggregateCall>>,
+    ) -> Result<Self> {
+        let (left_affinity, right_affinity) =
+            comparison_affinity(expr_affinity(left, source), expr_affinity(right, source));
+        let (left, right) = (
+            Self::bind_in(left, source, aggregates)?,
+            Self::bind_in(right, source, aggregates)?,
+        );
+        /* an explicit COLLATE on either side wins over the column collations */
+        let collation = left
+            .explicit_collation()
+            .or_else(|| right.explicit_collation())
+            .or_else(|| left.column_collation(source))
+            .or_else(|| right.column_collation(source))
+            .unwrap_or_default();
+        /* the comparison holds the collation, so the operands are left as the planner seeks them */
+        Ok(BoundExpr::Compare {
+            op,
+            left: Box::new(left.without_collate()),
+            left_affinity,
+            right: Box::new(right.without_collate()),
+            right_affinity,
+            collation,
+        })
+    }
+
+    /* left = right for two columns of the joined row, like a USING column pair compares */
+    pub fn columns_equal(left: usize, right: usize, source: &Source) -> Self {
+        let affinity = |pos: usize| source.column(pos).map(|column| column.affinity());
+        let (left_affinity, right_affinity) = comparison_affinity(affinity(left), affinity(right));
+        let (left, right) = (BoundExpr::Column(left), BoundExpr::Column(right));
+        let collation = left.collation(source);
+        BoundExpr::Compare {
+            op: BinaryOp::Eq,
+            left: Box::new(left),
+            left_affinity,
+            right: Box::new(right),
+            right_affinity,
+            collation,
+        }
+    }
+
+    /*
+        an expression compares and sorts by its COLLATE, else a column by its declared
+        collation, and anything else by BINARY
+    */
+    pub fn collation(&self, source: &Source) -> Collation {
+        self.own_collation(source).unwrap_or_default()
+    }
+
+    fn own_collation(&self, source: &Source) -> Option<Collation> {
+        self.explicit_collation()
+            .or_else(|| self.column_collation(source))
+    }
+
+    /* the collation a COLLATE names, looking through casts and into operands like sqlite */
+    fn explicit_collation(&self) -> Option<Collation> {
+        match self {
+            BoundExpr::Collate { collation, .. } => Some(*collation),
+            BoundExpr::Cast { expr, .. } | BoundExpr::Negate(expr) | BoundExpr::BitNot(expr) => {
+                expr.explicit_collation()
+            }
+            BoundExpr::Binary { left, right, .. } => left
+                .explicit_collation()
+                .or_else(|| right.explicit_collation()),
+            BoundExpr::Function { args, .. } => args.iter().find_map(Self::explicit_collation),
+            BoundExpr::Case {
+                branches,
+                otherwise,
+            } => branches
+                .iter()
+                .flat_map(|(when, then)| [when, then])
+                .chain(otherwise.as_deref())
+                .find_map(Self::explicit_collation),
+            _ => None,
+        }
+    }
+
+    fn without_collate(self) -> Self {
+        match self {
+            BoundExpr::Collate { expr, .. } => expr.without_collate(),
+            expr => expr,
+        }
+    }
+
+    /* the collation of a column, which a CAST keeps, BINARY when it declares none */
+    fn column_collation(&self, source: &Source) -> Option<Collation> {
+        match self {
+            BoundExpr::Column(pos) => Some(
+                source
+                    .column(*pos)
+                    .and_then(|column| column.collation())
+                    .and_then(Collation::from_name)
+                    .unwrap_or_default(),
+            ),
+            BoundExpr::Cast { expr, .. } => expr.column_collation(source),
+            _ => None,
+        }
+    }
+
+    /* adds the positions of the row values this reads to `columns` */
+    pub fn columns(&self, columns: &mut Vec<usize>) {
+        match self {
+            BoundExpr::Literal(_) => {}
+            BoundExpr::Column(pos) => columns.push(*pos),
+            BoundExpr::Compare { left, right, .. } => {
+                left.columns(columns);
+                right.columns(columns);
+            }
+            BoundExpr::Like { expr, pattern, .. } => {
+                expr.columns(columns);
+                pattern.columns(columns);
+            }
+            BoundExpr::And(left, right)
+            | BoundExpr::Or(left, right)
+            | BoundExpr::Binary { left, right, .. } => {
+                left.columns(columns);
+                right.columns(columns);
+            }
+            BoundExpr::Not(expr)
+            | BoundExpr::Negate(expr)
+            | BoundExpr::BitNot(expr)
+            | BoundExpr::Cast { expr, .. }
+            | BoundExpr::Collate { expr, .. } => expr.columns(columns),
+            BoundExpr::Case {
+                branches,
+                otherwise,
+            } => {
+                for (when, then) in branches {
+                    when.columns(columns);
+                    then.columns(columns);
+                }
+                if let Some(otherwise) = otherwise {
+                    otherwise.columns(columns);
+                }
+            }
+            BoundExpr::Function { args, .. } => {
+                for arg in args {
+                    arg.columns(columns);
+                }
+            }
+        }
+    }
+
+    /* rows only pass when the clause is true, NULL filters them out like false does */
+    pub fn matches(&self, row: &[Value], encoding: TextEncoding) -> Result<bool> {
+        Ok(self.eval(row, encoding)?.is_true() == Some(true))
+    }
+
+    /* errors are the ones evaluating can run into, like abs() of the smallest integer */
+    pub fn eval(&self, row: &[Value], encoding: TextEncoding) -> Result<Value> {
+        Ok(match self {
+            BoundExpr::Literal(value) => value.clone(),
+            BoundExpr::Column(pos) => row.get(*pos).cloned().unwrap_or(Value::Null),
+            BoundExpr::Compare {
+                op,
+                left,
+                left_affinity,
+                right,
+                right_affinity,
+                collation,
+            } => {
+                let convert = |value: Value, affinity: &Option<Affinity>| match affinity {
+                    Some(affinity) => value.apply_affinity(*affinity),
+                    None => value,
+                };
+                let left = convert(left.eval(row, encoding)?, left_affinity);
+                let right = convert(right.eval(row, encoding)?, right_affinity);
+                if left.is_null() || right.is_null() {
+                    let both_null = left.is_null() && right.is_null();
+                    return Ok(match op {
+                        BinaryOp::Is => Value::Integer(both_null as i64),
+                        BinaryOp::IsNot => Value::Integer(!both_null as i64),
+                        _ => Value::Null,
+                    });
+                }
+                let ordering = left.compare_collated(&right, *collation, encoding);
+                let result = match op {
+                    BinaryOp::Eq | BinaryOp::Is => ordering == Ordering::Equal,
+                    BinaryOp::NotEq | BinaryOp::IsNot => ordering != Ordering::Equal,
+                    BinaryOp::Lt => ordering == Ordering::Less,
+                    BinaryOp::LtEq => ordering != Ordering::Greater,
+                    BinaryOp::Gt => ordering == Ordering::Greater,
+                    BinaryOp::GtEq => ordering != Ordering::Less,
+                    _ => unreachable!("bound as another expression"),
+                };
+                Value::Integer(result as i64)
+            }
+            BoundExpr::Like {
+                expr,
+                pattern,
+                escape,
+                glob,
+            } => {
+                let (text, pattern) = (expr.eval(row, encoding)?, pattern.eval(row, encoding)?);
+                if text.is_null() || pattern.is_null() {
+                    return Ok(Value::Null);
+                }
+                let (text, pattern) = (text.to_string(), pattern.to_string());
+                let matched = if *glob {
+                    pattern::glob(&pattern, &text)
+                } else {
+                    pattern::like(&pattern, &text, *escape)
+                };
+                Value::Integer(matched as i64)
+            }
+            /* three-valued logic: false AND NULL is false, true OR NULL is true */
+            BoundExpr::And(left, right) => {
+                match (
+                    left.eval(row, encoding)?.is_true(),
+                    right.eval(row, encoding)?.is_true(),
+                ) {
+                    (Some(false), _) | (_, Some(false)) => Value::Integer(0),
+                    (Some(true), Some(true)) => Value::Integer(1),
+                    _ => Value::Null,
+                }
+            }
+            BoundExpr::Or(left, right) => {
+                match (
+                    left.eval(row, encoding)?.is_true(),
+                    right.eval(row, encoding)?.is_true(),
+                ) {
+                    (Some(true), _) | (_, Some(true)) => Value::Integer(1),
+                    (Some(false), Some(false)) => Value::Integer(0),
+                    _ => Value::Null,
+                }
+            }
+            BoundExpr::Not(expr) => match expr.eval(row, encoding)?.is_true() {
+                Some(result) => Value::Integer(!result as i64),
+                None => Value::Null,
+            },
+            BoundExpr::Binary { op, left, right } => {
+                scalar::binary(*op, &left.eval(row, encoding)?, &right.eval(row, encoding)?)
+            }
+            BoundExpr::Negate(expr) => scalar::negate(&expr.eval(row, encoding)?),
+            BoundExpr::BitNot(expr) => scalar::bit_not(&expr.eval(row, encoding)?),
+            /* only the branch taken is evaluated */
+            BoundExpr::Case {
+                branches,
+                otherwise,
+            } => {
+                for (when, then) in branches {
+                    if when.matches(row, encoding)? {
+                        return then.eval(row, encoding);
+                    }
+                }
+                match otherwise {
+                    Some(otherwise) => otherwise.eval(row, encoding)?,
+                    None => Value::Null,
+                }
+            }
+            BoundExpr::Cast { expr, affinity } => expr.eval(row, encoding)?.cast(*affinity),
+            BoundExpr::Collate { expr, .. } => expr.eval(row, encoding)?,
+            BoundExpr::Function {
+                function,
+                args,
+                collation,
+            } => {
+                if function.is_short_circuit() {
+                    for arg in args {
+                        let value = arg.eval(row, encoding)?;
+                        if !value.is_null() {
+                            return Ok(value);
+                        }
+                    }
+                    return Ok(Value::Null);
+                }
+                let args = args
+                    .iter()
+                    .map(|arg| arg.eval(row, encoding))
+                    .collect::<Result<Vec<_>>>()?;
+                function.call(&args, *collation, encoding)?
+            }
+        })
+    }
+}
+
+fn is_comparison(op: BinaryOp) -> bool {
+    matches!(
+        op,
+        BinaryOp::Eq
+            | BinaryOp::NotEq
+            | BinaryOp::Is
+            | BinaryOp::IsNot
+            | BinaryOp::Lt
+            | BinaryOp::LtEq
+            | BinaryOp::Gt
+            | BinaryOp::GtEq
+    )
+}
+
+fn negate(expr: BoundExpr, negated: bool) -> BoundExpr {
+    if negated {
+        BoundExpr::Not(Box::new(expr))
+    } else {
+        expr
+    }
+}
+
+/*
+    a column reference has its column's affinity, also under a COLLATE, and a CAST its type's.
+    other expressions have none
+*/
+fn expr_affinity(expr: &Expr, source: &Source) -> Option<Affinity> {
+    match expr {
+        Expr::Column { table, name } => source
+            .resolve(table.as_deref(), name)
+            .ok()
+            .and_then(|pos| source.column(pos))
+            .map(|column| column.affinity()),
+        Expr::Cast { type_name, .. } => Some(Affinity::from_type_name(Some(type_name))),
+        Expr::Collate { expr, .. } => expr_affinity(expr, source),
+        _ => None,
+    }
+}
+
+/* the affinity each operand of a comparison is converted to before comparing */
+fn comparison_affinity(
+    left: Option<Affinity>,
+    right: Option<Affinity>,
+) -> (Option<Affinity>, Option<Affinity>) {
+    let numeric = |affinity: Option<Affinity>| {
+        matches!(
+            affinity,
+            Some(Affinity::Integer | Affinity::Real | Affinity::Numeric)
+        )
+    };
+    if numeric(left) && !numeric(right) {
+        (None, Some(Affinity::Numeric))
+    } else if numeric(right) && !numeric(left) {
+        (Some(Affinity::Numeric), None)
+    } else if left == Some(Affinity::Text) && right.is_none() {
+        (None, Some(Affinity::Text))
+    } else if right == Some(Affinity::Text) && left.is_none() {
+        (Some(Affinity::Text), None)
+    } else {
+        (None, None)
+    }
+}
@@ -0,0 +1,622 @@
+use crate::error::{ReaderError, Result};
+use crate::page::{downcast, Cell, Page, Record, TableLeafCell};
+use crate::page_reader::PageReaderBuilder;
+use crate::page_source::PageSource;
+use crate::page_type::PageType;
+use crate::value::Value;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::rc::Rc;
+
+/* sqlite gives up on b-trees deeper than this, a sane file never gets close */
+const MAX_DEPTH: usize = 20;
+
+/* one level of the path from the root to the current entry */
+struct Frame {
+    page_no: u32,
+    page: Rc<Page>,
+    index: usize,
+}
+
+impl Frame {
+    fn is_leaf(&self) -> bool {
+        matches!(
+            self.page.page_header.page_type,
+            PageType::TblLeaf | PageType::IdxLeaf
+        )
+    }
+
+    fn cell_count(&self) -> usize {
+        self.page.cells.len()
+    }
+
+    /* child `index` is the left child of cell `index`, one past the last cell is the right pointer */
+    fn child_page_no(&self, index: usize) -> Option<u32> {
+        match index.cmp(&self.cell_count()) {
+            Ordering::Less => self.page.cells[index].left_child_page_no(),
+            Ordering::Equal => self.page.page_header.right_pointer,
+            Ordering::Greater => None,
+        }
+    }
+
+    /* like child_page_no, for an index that is known to be in range */
+    fn expect_child_page_no(&self, index: usize) -> Result<u32> {
+        self.child_page_no(index).ok_or_else(|| {
+            ReaderError::corrupt(self.page_no, 0, "interior page is missing a child")
+        })
+    }
+}
+
+/*
+    walks a b-tree in key order, holding only the pages on the path to the current entry.
+    interior frames point at the child being visited. index b-trees also store entries in
+    their interior cells, so an interior frame on top of the stack is positioned on its cell.
+*/
+struct BTreeWalker {
+    root_page_no: u32,
+    stack: Vec<Frame>,
+    interior_entries: bool,
+    /* the pages this walk has entered, a sane b-tree never reaches one twice */
+    visited: HashSet<u32>,
+}
+
+impl BTreeWalker {
+    fn new(root_page_no: u32, interior_entries: bool) -> Self {
+        Self {
+            root_page_no,
+            stack: Vec::new(),
+            interior_entries,
+            visited: HashSet::new(),
+        }
+    }
+
+    /* a first, last or seek starts a new walk from the root */
+    fn restart(&mut self) {
+        self.stack.clear();
+        self.visited.clear();
+    }
+
+    fn current(&self) -> Option<&Frame> {
+        self.stack.last()
+    }
+
+    /* fetches the next page down the current path, refusing loops and pages of the wrong kind */
+    fn enter<S: PageSource>(
+        &mut self,
+        builder: &mut PageReaderBuilder<S>,
+        page_no: u32,
+    ) -> Result<Rc<Page>> {
+        if self.stack.iter().any(|frame| frame.page_no == page_no) {
+            return Err(ReaderError::corrupt(
+                page_no,
+                0,
+                "b-tree page is its own ancestor",
+            ));
+        }
+        if self.stack.len() >= MAX_DEPTH {
+            return Err(ReaderError::corrupt(
+                page_no,
+                0,
+                format!("b-tree is deeper than {} levels", MAX_DEPTH),
+            ));
+        }
+        if !self.visited.insert(page_no) {
+            return Err(ReaderError::corrupt(
+                page_no,
+                0,
+                "b-tree page is reachable twice",
+            ));
+        }
+
+        let page = builder.fetch_page(page_no)?;
+        let is_index = matches!(
+            page.page_header.page_type,
+            PageType::IdxLeaf | PageType::IdxInt
+        );
+        if is_index != self.interior_entries {
+            return Err(ReaderError::corrupt(
+                page_no,
+                0,
+                "table and index pages are mixed in one b-tree",
+            ));
+        }
+        Ok(page)
+    }
+
+    fn first<S: PageSource>(&mut self, builder: &mut PageReaderBuilder<S>) -> Result<bool> {
+        self.restart();
+        self.descend_leftmost(self.root_page_no, builder)?;
+        self.settle(builder)
+    }
+
+    fn last<S: PageSource>(&mut self, builder: &mut PageReaderBuilder<S>) -> Result<bool> {
+        self.restart();
+        self.descend_rightmost(self.root_page_no, builder)?;
+        self.settle_back(builder)
+    }
+
+    fn next<S: PageSource>(&mut self, builder: &mut PageReaderBuilder<S>) -> Result<bool> {
+        let frame = match self.stack.last_mut() {
+            Some(frame) => frame,
+            None => return Ok(false),
+        };
+        frame.index += 1;
+        if !frame.is_leaf() {
+            /* was positioned on an interior index entry, continue with the subtree after it */
+            let child = frame.expect_child_page_no(frame.index)?;
+            self.descend_leftmost(child, builder)?;
+        }
+        self.settle(builder)
+    }
+
+    /*
+        steps to the entry before the current one. a walk goes one way, the pages behind a
+        reversed walk would be entered a second time
+    */
+    fn prev<S: PageSource>(&mut self, builder: &mut PageReaderBuilder<S>) -> Result<bool> {
+        let frame = match self.stack.last() {
+            Some(frame) => frame,
+            None => return Ok(false),
+        };
+        if !frame.is_leaf() {
+            /* was positioned on an interior index entry, the entries before it are its left child */
+            let child = frame.expect_child_page_no(frame.index)?;
+            self.descend_rightmost(child, builder)?;
+        }
+        self.settle_back(builder)
+    }
+
+    /* positions on the first entry for which `cmp` (entry against target) is not Less */
+    fn seek<S: PageSource, F>(
+        &mut self,
+        builder: &mut PageReaderBuilder<S>,
+        cmp: &F,
+    ) -> Result<bool>
+    where
+        F: Fn(&mut PageReaderBuilder<S>, &dyn Cell) -> Result<Ordering>,
+    {
+        self.restart();
+        let mut page_no = self.root_page_no;
+        loop {
+            let page = self.enter(builder, page_no)?;
+            let (mut low, mut high) = (0, page.cells.len());
+            while low < high {
+                let mid = (low + high) / 2;
+                if cmp(builder, page.cells[mid].as_ref())? == Ordering::Less {
+                    low = mid + 1;
+                } else {
+                    high = mid;
+                }
+            }
+            let frame = Frame {
+                page_no,
+                page,
+                index: low,
+            };
+            if frame.is_leaf() {
+                self.stack.push(frame);
+                return self.settle(builder);
+            }
+            page_no = frame.expect_child_page_no(frame.index)?;
+            self.stack.push(frame);
+        }
+    }
+
+    /* positions on the last entry for which `cmp` (entry against target) is not Greater */
+    fn seek_back<S: PageSource, F>(
+        &mut self,
+        builder: &mut PageReaderBuilder<S>,
+        cmp: &F,
+    ) -> Result<bool>
+    where
+        F: Fn(&mut PageReaderBuilder<S>, &dyn Cell) -> Result<Ordering>,
+    {
+        self.restart();
+        let mut page_no = self.root_page_no;
+        loop {
+            let page = self.enter(builder, page_no)?;
+            let (mut low, mut high) = (0, page.cells.len());
+            while low < high {
+                let mid = (low + high) / 2;
+                if cmp(builder, page.cells[mid].as_ref())? == Ordering::Greater {
+                    high = mid;
+                } else {
+                    low = mid + 1;
+                }
+            }
+            let frame = Frame {
+                page_no,
+                page,
+                index: low,
+            };
+            if frame.is_leaf() {
+                self.stack.push(frame);
+                return self.settle_back(builder);
+            }
+            page_no = frame.expect_child_page_no(frame.index)?;
+            self.stack.push(frame);
+        }
+    }
+
+    fn descend_leftmost<S: PageSource>(
+        &mut self,
+        page_no: u32,
+        builder: &mut PageReaderBuilder<S>,
+    ) -> Result<()> {
+        let mut page_no = page_no;
+        loop {
+            let frame = Frame {
+                page_no,
+                page: self.enter(builder, page_no)?,
+                index: 0,
+            };
+            if frame.is_leaf() {
+                self.stack.push(frame);
+                return Ok(());
+            }
+            page_no = frame.expect_child_page_no(0)?;
+            self.stack.push(frame);
+        }
+    }
+
+    /* a leaf frame comes out one past its last cell, for settle_back to step onto it */
+    fn descend_rightmost<S: PageSource>(
+        &mut self,
+        page_no: u32,
+        builder: &mut PageReaderBuilder<S>,
+    ) -> Result<()> {
+        let mut page_no = page_no;
+        loop {
+            let page = self.enter(builder, page_no)?;
+            let frame = Frame {
+                page_no,
+                index: page.cells.len(),
+                page,
+            };
+            if frame.is_leaf() {
+                self.stack.push(frame);
+                return Ok(());
+            }
+            page_no = frame.expect_child_page_no(frame.index)?;
+            self.stack.push(frame);
+        }
+    }
+
+    /*
+        settle walking backwards: a leaf frame sits one past the entry to step back onto, an
+        interior frame on the child it came back from
+    */
+    fn settle_back<S: PageSource>(&mut self, builder: &mut PageReaderBuilder<S>) -> Result<bool> {
+        while let Some(frame) = self.stack.last_mut() {
+            if frame.index == 0 {
+                self.stack.pop();
+                continue;
+            }
+            frame.index -= 1;
+            if frame.is_leaf() || self.interior_entries {
+                return Ok(true);
+            }
+            let child = frame.expect_child_page_no(frame.index)?;
+            self.descend_rightmost(child, builder)?;
+        }
+        Ok(false)
+    }
+
+    /* climbs out of exhausted pages until the top frame is positioned on an entry */
+    fn settle<S: PageSource>(&mut self, builder: &mut PageReaderBuilder<S>) -> Result<bool> {
+        while let Some(frame) = self.stack.last_mut() {
+            if frame.is_leaf() {
+                if frame.index < frame.cell_count() {
+                    return Ok(true);
+                }
+                self.stack.pop();
+                continue;
+            }
+
+            /* back from child `index` */
+            if self.interior_entries && frame.index < frame.cell_count() {
+                return Ok(true);
+            }
+            frame.index += 1;
+            match frame.child_page_no(frame.index) {
+                Some(child) => self.descend_leftmost(child, builder)?,
+                None => {
+                    self.stack.pop();
+                }
+            }
+        }
+        Ok(false)
+    }
+}
+
+/* a row of a table b-tree, its record decoded */
+#[derive(Debug, Clone)]
+pub struct TableRow {
+    pub row_id: i64,
+    pub record: Record,
+}
+
+pub struct TableCursor {
+    walker: BTreeWalker,
+    started: bool,
+}
+
+impl TableCursor {
+    pub fn new(root_page_no: u32) -> Self {
+        Self {
+            walker: BTreeWalker::new(root_page_no, false),
+            started: false,
+        }
+    }
+
+    pub fn first<S: PageSource>(
+        &mut self,
+        builder: &mut PageReaderBuilder<S>,
+    ) -> Result<Option<TableRow>> {
+        self.started = true;
+        self.walker.first(builder)?;
+        self.current(builder)
+    }
+
+    pub fn next<S: PageSource>(
+        &mut self,
+        builder: &mut PageReaderBuilder<S>,
+    ) -> Result<Option<TableRow>> {
+        if !self.started {
+            return self.first(builder);
+        }
+        self.walker.next(builder)?;
+        self.current(builder)
+    }
+
+    pub fn last<S: PageSource>(
+        &mut self,
+        builder: &mut PageReaderBuilder<S>,
+    ) -> Result<Option<TableRow>> {
+        self.started = true;
+        self.walker.last(builder)?;
+        self.current(builder)
+    }
+
+    /* positions on the row with the smallest row_id >= `row_id` */
+    pub fn seek<S: PageSource>(
+        &mut self,
+        builder: &mut PageReaderBuilder<S>,
+        row_id: i64,
+    ) -> Result<Option<TableRow>> {
+        self.started = true;
+        self.walker.seek(builder, &|_, cell: &dyn Cell| {
+            Ok(cell.row_id().unwrap_or(i64::MIN).cmp(&row_id))
+        })?;
+        self.current(builder)
+    }
+
+    /* the row before the current one, the last row for a cursor that hasn't started */
+    pub fn prev<S: PageSource>(
+        &mut self,
+        builder: &mut PageReaderBuilder<S>,
+    ) -> Result<Option<TableRow>> {
+        if !self.started {
+            return self.last(builder);
+        }
+        self.walker.prev(builder)?;
+        self.current(builder)
+    }
+
+    /* positions on the row with the largest row_id <= `row_id` */
+    pub fn seek_back<S: PageSource>(
+        &mut self,
+        builder: &mut PageReaderBuilder<S>,
+        row_id: i64,
+    ) -> Result<Option<TableRow>> {
+        self.started = true;
+        self.walker.seek_back(builder, &|_, cell: &dyn Cell| {
+            Ok(cell.row_id().unwrap_or(i64::MIN).cmp(&row_id))
+        })?;
+        self.current(builder)
+    }
+
+    /* decodes the record of the row the cursor is on */
+    pub fn current<S: PageSource>(
+        &self,
+        builder: &mut PageReaderBuilder<S>,
+    ) -> Result<Option<TableRow>> {
+        let Some(frame) = self.walker.current() else {
+            return Ok(None);
+        };
+        match downcast::<TableLeafCell>(frame.page.cells[frame.index].as_ref()) {
+            Some(cell) => Ok(Some(TableRow {
+                row_id: cell.row_id,
+                record: builder.read_record(&cell.payload)?,
+            })),
+            None => Ok(None),
+        }
+    }
+
+    /* number of rows, read off the leaf page headers without decoding any record */
+    pub fn count<S: PageSource>(&self, builder: &mut PageReaderBuilder<S>) -> Result<u64> {
+        let mut count = 0_u64;
+        let mut pages = vec![self.walker.root_page_no];
+        let mut visited = HashSet::new();
+        while let Some(page_no) = pages.pop() {
+            if !visited.insert(page_no) {
+                return Err(ReaderError::corrupt(
+                    page_no,
+                    0,
+                    "b-tree page is reachable twice",
+                ));
+            }
+            let page_header = builder.fetch_page_header(page_no)?;
+            match page_header.page_type {
+                PageType::TblLeaf => {
+                    count += page_header.cell_count as u64;
+                    continue;
+                }
+                PageType::TblInt => {}
+                _ => {
+                    return Err(ReaderError::corrupt(
+                        page_no,
+                        0,
+                        "table b-tree refers to a non-table page",
+                    ))
+                }
+            }
+            let page = builder.fetch_page(page_no)?;
+            pages.extend(
+                page.cells
+                    .iter()
+                    .filter_map(|cell| cell.left_child_page_no()),
+            );
+            pages.extend(page.page_header.right_pointer);
+        }
+        Ok(count)
+    }
+
+    pub fn iter<'a, S: PageSource>(
+        &'a mut self,
+        builder: &'a mut PageReaderBuilder<S>,
+    ) -> CursorIter<'a, Self, S> {
+        CursorIter {
+            cursor: self,
+            builder,
+        }
+    }
+}
+
+pub struct IndexCursor {
+    walker: BTreeWalker,
+    started: bool,
+}
+
+impl IndexCursor {
+    pub fn new(root_page_no: u32) -> Self {
+        Self {
+            walker: BTreeWalker::new(root_page_no, true),
+            started: false,
+        }
+    }
+
+    pub fn first<S: PageSource>(
+        &mut self,
+        builder: &mut PageReaderBuilder<S>,
+    ) -> Result<Option<Record>> {
+        self.started = true;
+        self.walker.first(builder)?;
+        self.current(builder)
+    }
+
+    pub fn next<S: PageSource>(
+        &mut self,
+        builder: &mut PageReaderBuilder<S>,
+    ) -> Result<Option<Record>> {
+        if !self.started {
+            return self.first(builder);
+        }
+        self.walker.next(builder)?;
+        self.current(builder)
+    }
+
+    pub fn last<S: PageSource>(
+        &mut self,
+        builder: &mut PageReaderBuilder<S>,
+    ) -> Result<Option<Record>> {
+        self.started = true;
+        self.walker.last(builder)?;
+        self.current(builder)
+    }
+
+    /*
+        positions on the first entry `cmp` doesn't order before the target. `cmp` compares an
+        entry's values against the target in index order, so it knows the index's collations
+        and which of its columns are descending
+    */
+    pub fn seek<S: PageSource>(
+        &mut self,
+        builder: &mut PageReaderBuilder<S>,
+        cmp: &dyn Fn(&[Value]) -> Ordering,
+    ) -> Result<Option<Record>> {
+        self.started = true;
+        self.walker.seek(builder, &|builder, cell: &dyn Cell| {
+            Ok(match cell.payload() {
+                Some(payload) => cmp(&builder.read_record(payload)?.rows),
+                None => cmp(&[]),
+            })
+        })?;
+        self.current(builder)
+    }
+
+    /* the entry before the current one, the last entry for a cursor that hasn't started */
+    pub fn prev<S: PageSource>(
+        &mut self,
+        builder: &mut PageReaderBuilder<S>,
+    ) -> Result<Option<Record>> {
+        if !self.started {
+            return self.last(builder);
+        }
+        self.walker.prev(builder)?;
+        self.current(builder)
+    }
+
+    /* positions on the last entry `cmp` doesn't order after the target, see seek */
+    pub fn seek_back<S: PageSource>(
+        &mut self,
+        builder: &mut PageReaderBuilder<S>,
+        cmp: &dyn Fn(&[Value]) -> Ordering,
+    ) -> Result<Option<Record>> {
+        self.started = true;
+        self.walker
+            .seek_back(builder, &|builder, cell: &dyn Cell| {
+                Ok(match cell.payload() {
+                    Some(payload) => cmp(&builder.read_record(payload)?.rows),
+                    None => cmp(&[]),
+                })
+            })?;
+        self.current(builder)
+    }
+
+    /* decodes the entry the cursor is on */
+    pub fn current<S: PageSource>(
+        &self,
+        builder: &mut PageReaderBuilder<S>,
+    ) -> Result<Option<Record>> {
+        let payload = self
+            .walker
+            .current()
+            .and_then(|frame| frame.page.cells[frame.index].payload());
+        payload
+            .map(|payload| builder.read_record(payload))
+            .transpose()
+    }
+
+    pub fn iter<'a, S: PageSource>(
+        &'a mut self,
+        builder: &'a mut PageReaderBuilder<S>,
+    ) -> CursorIter<'a, Self, S> {
+        CursorIter {
+            cursor: self,
+            builder,
+        }
+    }
+}
+
+/* borrows the builder for the length of a scan so a cursor can be used as an Iterator */
+pub struct CursorIter<'a, C, S: PageSource> {
+    cursor: &'a mut C,
+    builder: &'a mut PageReaderBuilder<S>,
+}
+
+impl<S: PageSource> Iterator for CursorIter<'_, TableCursor, S> {
+    type Item = Result<TableRow>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.cursor.next(self.builder).transpose()
+    }
+}
+
+impl<S: PageSource> Iterator for CursorIter<'_, IndexCursor, S> {
+    type Item = Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.cursor.next(self.builder).transpose()
+    }
+}
@@ -1,364 +1,679 @@
-use crate::page::{
-    downcast, Cell, IdxIntCell, IdxLeafCell, Page, SearchResult, TableIntCell, TableLeafCell,
-};
+use crate::ast::BinaryOp;
+use crate::bound_expr::BoundExpr;
+use crate::cursor::{IndexCursor, TableCursor, TableRow};
+use crate::db_header::TextEncoding;
+use crate::error::{ReaderError, Result};
+use crate::page::Record;
 use crate::page_reader::PageReaderBuilder;
-use crate::page_type::PageType;
-use crate::parser::QueryDetails;
-use anyhow::bail;
-use std::any::Any;
+use crate::page_source::PageSource;
+use crate::pattern;
+use crate::schema::{Index, Table};
+use crate::value::{Affinity, Collation, Value};
 use std::cmp::Ordering;
-use std::collections::{HashSet, VecDeque};
-use std::ops::Deref;
-use std::rc::Rc;
+use std::ops::ControlFlow;
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub struct Filter {
-    pub filter_col_pos: isize,
-    pub filter_value: FilterValue,
-}
+/* a LIKE prefix with more letters than this only seeks on the casings of its first few */
+const MAX_CASED_PREFIX_LETTERS: usize = 6;
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub enum FilterValue {
-    String(String),
-    Int(i64),
+/* how an index narrows the rows a filter can match */
+#[derive(Debug, Clone, PartialEq)]
+pub enum IndexLookup {
+    /* one seek per key, for = and IN */
+    Keys(Vec<FilterValue>),
+    /* the text keys starting with any of the prefixes */
+    Prefixes(Vec<String>),
+    /* every entry, for the order the index keeps its rows in */
+    All,
 }
 
-pub fn perform_index_scan(
-    root_index_page_cell: &dyn Cell,
-    root_leaf_page_cell: &dyn Cell,
-    builder: &mut PageReaderBuilder,
-    select_col_names: Vec<String>,
-    table_query_details: QueryDetails,
-    filter: &Filter,
-) {
-    let (_page_no, root_index_page) = fetch_table_first_page(root_index_page_cell, builder);
-    // println!("first index page no {:?}", _page_no);
-    let (_page_no, root_table_page) = fetch_table_first_page(root_leaf_page_cell, builder);
-    // println!("first table page no {:?}", _page_no);
-
-    let record_ids = fetch_indexed_rows(root_index_page, builder, &filter.filter_value);
-    // println!("{:?}", record_ids);
-    fetch_rows_with_id(
-        root_table_page,
-        record_ids,
-        builder,
-        select_col_names,
-        table_query_details,
-        filter,
-    );
+/* how the terms on a table's INTEGER PRIMARY KEY narrow its rows down by row_id */
+#[derive(Debug, Clone, PartialEq)]
+pub enum RowIdLookup {
+    /* one seek per row_id, for = and IN */
+    Keys(Vec<i64>),
+    /* the row_ids between the bounds, both inclusive, for <, > and BETWEEN */
+    Range { low: Option<i64>, high: Option<i64> },
 }
 
-pub fn perform_full_table_scan(
-    root_page_cell: &dyn Cell,
-    builder: &mut PageReaderBuilder,
-    select_col_names: Vec<String>,
-    table_query_details: QueryDetails,
-    filter: Filter,
-) {
-    let (page_no, page) = fetch_table_first_page(root_page_cell, builder);
-    let page_num_and_page: Vec<(u32, Page)> = fetch_all_leaves_for_table(page, builder, page_no);
-    let col_positions = get_column_position(select_col_names, &table_query_details.stmt.columns);
-    for page in &page_num_and_page {
-        let unique_rows_sub = fetch_table_data(&col_positions, page, &filter).unwrap();
-        unique_rows_sub.iter().for_each(|row| {
-            println!("{}", *row);
-        });
+impl BoundExpr {
+    /*
+        the row_ids this filter can be true for, going by its terms on the row_id alias at
+        `alias`. the rows of a lookup still go through the filter
+    */
+    pub fn rowid_lookup(&self, alias: usize) -> Option<RowIdLookup> {
+        match self {
+            BoundExpr::And(left, right) => {
+                match (left.rowid_lookup(alias), right.rowid_lookup(alias)) {
+                    (Some(RowIdLookup::Keys(keys)), _) | (_, Some(RowIdLookup::Keys(keys))) => {
+                        Some(RowIdLookup::Keys(keys))
+                    }
+                    (
+                        Some(RowIdLookup::Range { low, high }),
+                        Some(RowIdLookup::Range {
+                            low: other_low,
+                            high: other_high,
+                        }),
+                    ) => Some(RowIdLookup::Range {
+                        low: low.max(other_low),
+                        high: match (high, other_high) {
+                            (Some(high), Some(other_high)) => Some(high.min(other_high)),
+                            (high, other_high) => high.or(other_high),
+                        },
+                    }),
+                    (lookup, None) | (None, lookup) => lookup,
+                }
+            }
+            BoundExpr::Or(left, right) => {
+                match (left.rowid_lookup(alias)?, right.rowid_lookup(alias)?) {
+                    (RowIdLookup::Keys(mut keys), RowIdLookup::Keys(more)) => {
+                        keys.extend(more);
+                        Some(RowIdLookup::Keys(keys))
+                    }
+                    _ => None,
+                }
+            }
+            BoundExpr::Compare {
+                op,
+                left,
+                left_affinity,
+                right,
+                right_affinity,
+                ..
+            } => {
+                let (op, value, affinity) = match (left.as_ref(), right.as_ref()) {
+                    (BoundExpr::Column(pos), BoundExpr::Literal(value))
+                        if *pos == alias && left_affinity.is_none() =>
+                    {
+                        (*op, value, *right_affinity)
+                    }
+                    (BoundExpr::Literal(value), BoundExpr::Column(pos))
+                        if *pos == alias && right_affinity.is_none() =>
+                    {
+                        (flipped(*op), value, *left_affinity)
+                    }
+                    _ => return None,
+                };
+                let value = match affinity {
+                    Some(affinity) => value.clone().apply_affinity(affinity),
+                    None => value.clone(),
+                };
+                rowid_range(op, &value)
+            }
+            _ => None,
+        }
     }
-}
-
-pub fn count_all_rows(root_leaf_page_cell: &dyn Cell, builder: &mut PageReaderBuilder) {
-    let (page_no, page) = fetch_table_first_page(root_leaf_page_cell, builder);
-    let page_num_and_page: Vec<(u32, Page)> = fetch_all_leaves_for_table(page, builder, page_no);
-    println!(
-        "{:?}",
-        page_num_and_page
-            .iter()
-            .map(|(_, page)| page.page_header.cell_count as u64)
-            .sum::<u64>()
-    );
-}
 
-fn fetch_indexed_rows(
-    root_index_page: Page,
-    builder: &mut PageReaderBuilder,
-    filter_value: &FilterValue,
-) -> Vec<String> {
-    let mut page = root_index_page;
-    let mut page_to_read = 0u32;
-    let mut record_ids = Vec::new();
-    let payload_extractor_fn =
-        |cell: &dyn Cell| -> String { cell.record().unwrap().rows.first().unwrap().to_string() };
-
-    while page.page_header.page_type == PageType::IdxInt {
-        let cells = page.cells.deref();
-        let res = bin_search_payload::<IdxIntCell>(cells, filter_value, &payload_extractor_fn);
-        page_to_read = match res {
-            SearchResult::ThisPage(cell, _) => {
-                let (_, row_id) = get_payload_id(&*cell);
-                record_ids.push(row_id);
-                cell.left_child_page_no().unwrap()
+    /*
+        the lookup an index on one column can do for this filter: = and IN terms, or a LIKE or
+        GLOB with a literal prefix, that must hold for the whole clause to be true. `indexed`
+        says whether a column has an index sorted by the collation the lookup compares with
+    */
+    pub fn index_lookup(
+        &self,
+        table: &Table,
+        indexed: &impl Fn(usize, Collation) -> bool,
+    ) -> Option<(usize, Collation, IndexLookup)> {
+        match self {
+            BoundExpr::And(left, right) => left
+                .index_lookup(table, indexed)
+                .or_else(|| right.index_lookup(table, indexed)),
+            /* both sides of an OR have to be lookups on the same column, like an IN list */
+            BoundExpr::Or(left, right) => {
+                match (
+                    left.index_lookup(table, indexed)?,
+                    right.index_lookup(table, indexed)?,
+                ) {
+                    (
+                        (left_pos, left_collation, IndexLookup::Keys(mut keys)),
+                        (right_pos, right_collation, IndexLookup::Keys(more)),
+                    ) if left_pos == right_pos && left_collation == right_collation => {
+                        keys.extend(more);
+                        Some((left_pos, left_collation, IndexLookup::Keys(keys)))
+                    }
+                    _ => None,
+                }
             }
-            SearchResult::LeftPage(cell) => cell.left_child_page_no().unwrap(),
-            SearchResult::RightPage => page.page_header.right_pointer.unwrap(),
-        };
-        page = builder.new_reader(page_to_read).read_page();
+            BoundExpr::Compare {
+                op: BinaryOp::Eq | BinaryOp::Is,
+                left,
+                left_affinity,
+                right,
+                right_affinity,
+                collation,
+            } => match (left.as_ref(), right.as_ref()) {
+                (BoundExpr::Column(pos), BoundExpr::Literal(value))
+                    if indexed(*pos, *collation) =>
+                {
+                    index_key(*pos, *collation, value, *right_affinity)
+                }
+                (BoundExpr::Literal(value), BoundExpr::Column(pos))
+                    if indexed(*pos, *collation) =>
+                {
+                    index_key(*pos, *collation, value, *left_affinity)
+                }
+                _ => None,
+            },
+            /*
+                only a TEXT column holds its numbers as text, so it's the only kind where every
+                match sorts among the text keys. the prefixes match case for case, so they need
+                an index in BINARY order
+            */
+            BoundExpr::Like {
+                expr,
+                pattern,
+                escape: None,
+                glob,
+            } => match (expr.as_ref(), pattern.as_ref()) {
+                (BoundExpr::Column(pos), BoundExpr::Literal(Value::Text(pattern)))
+                    if indexed(*pos, Collation::Binary)
+                        && table.columns[*pos].affinity() == Affinity::Text =>
+                {
+                    let prefix = pattern::literal_prefix(pattern, *glob)?;
+                    /* LIKE ignores ASCII case, so each casing of the prefix is a separate run */
+                    let prefixes = if *glob {
+                        vec![prefix]
+                    } else {
+                        pattern::ascii_casings(&prefix, MAX_CASED_PREFIX_LETTERS)
+                    };
+                    Some((*pos, Collation::Binary, IndexLookup::Prefixes(prefixes)))
+                }
+                _ => None,
+            },
+            _ => None,
+        }
     }
+}
 
-    let cell_idx = match bin_search_payload::<IdxLeafCell>(
-        page.cells.deref(),
-        filter_value,
-        &payload_extractor_fn,
-    ) {
-        SearchResult::ThisPage(_, cell_idx) => cell_idx as i64,
-        SearchResult::LeftPage(_) | SearchResult::RightPage => -1,
-    };
+fn index_key(
+    pos: usize,
+    collation: Collation,
+    value: &Value,
+    affinity: Option<Affinity>,
+) -> Option<(usize, Collation, IndexLookup)> {
+    lookup_key(value, affinity).map(|key| (pos, collation, IndexLookup::Keys(vec![key])))
+}
 
-    if cell_idx < 0 {
-        return vec![];
+/* the comparison with its operands swapped, 5 < id is id > 5 */
+fn flipped(op: BinaryOp) -> BinaryOp {
+    match op {
+        BinaryOp::Lt => BinaryOp::Gt,
+        BinaryOp::LtEq => BinaryOp::GtEq,
+        BinaryOp::Gt => BinaryOp::Lt,
+        BinaryOp::GtEq => BinaryOp::LtEq,
+        op => op,
     }
+}
 
-    let mut cell_idx = cell_idx as usize;
-    'outer: loop {
-        let cells = page.cells.deref();
-        let len = page.cells.deref().len();
-        for cell in cells.iter().take(len).skip(cell_idx) {
-            let (payload, row_id) = get_payload_id(cell.deref());
-            if filter_cmp(filter_value, &payload) == Ordering::Less {
-                break 'outer;
+/*
+    the row_ids `row_id op value` holds for. a row_id is never NULL and never equals text, the
+    ranges against text and huge reals are left to a scan
+*/
+fn rowid_range(op: BinaryOp, value: &Value) -> Option<RowIdLookup> {
+    let none = RowIdLookup::Keys(Vec::new());
+    let low = |low: Option<i64>| RowIdLookup::Range { low, high: None };
+    let high = |high: Option<i64>| RowIdLookup::Range { low: None, high };
+    Some(match (op, value) {
+        (_, Value::Null) => none,
+        (BinaryOp::Eq | BinaryOp::Is, value) => match lookup_key(value, None)? {
+            FilterValue::Int(int) => RowIdLookup::Keys(vec![int]),
+            FilterValue::String(_) => none,
+        },
+        (BinaryOp::Gt, Value::Integer(int)) => {
+            int.checked_add(1).map_or(none, |int| low(Some(int)))
+        }
+        (BinaryOp::GtEq, Value::Integer(int)) => low(Some(*int)),
+        (BinaryOp::Lt, Value::Integer(int)) => {
+            int.checked_sub(1).map_or(none, |int| high(Some(int)))
+        }
+        (BinaryOp::LtEq, Value::Integer(int)) => high(Some(*int)),
+        /* the whole numbers past the real, which are all in range or none of them */
+        (BinaryOp::Gt | BinaryOp::GtEq, Value::Real(real)) => {
+            let bound = if op == BinaryOp::Gt {
+                real.floor() + 1.0
+            } else {
+                real.ceil()
+            };
+            match bound {
+                bound if bound >= i64::MAX as f64 => none,
+                bound if bound <= i64::MIN as f64 => low(None),
+                bound => low(Some(bound as i64)),
             }
-            record_ids.push(row_id);
         }
-        page_to_read += 1;
-        page = builder.new_reader(page_to_read).read_page();
-        cell_idx = 0;
-    }
-
-    record_ids
+        (BinaryOp::Lt | BinaryOp::LtEq, Value::Real(real)) => {
+            let bound = if op == BinaryOp::Lt {
+                real.ceil() - 1.0
+            } else {
+                real.floor()
+            };
+            match bound {
+                bound if bound < i64::MIN as f64 => none,
+                bound if bound >= i64::MAX as f64 => high(None),
+                bound => high(Some(bound as i64)),
+            }
+        }
+        _ => return None,
+    })
 }
 
-fn fetch_rows_with_id(
-    root_table_page: Page,
-    record_ids: Vec<String>,
-    builder: &mut PageReaderBuilder,
-    select_col_names: Vec<String>,
-    table_query_details: QueryDetails,
-    filter: &Filter,
-) {
-    let col_positions = get_column_position(select_col_names, &table_query_details.stmt.columns);
-    let root_page_rc = Rc::new(root_table_page.clone()); // use rc to avoid cloning the page repeatedly
-    let payload_extractor_fn = |cell: &dyn Cell| -> String {
-        cell.row_id()
-            .expect("Failed to convert row_id to string")
-            .to_string()
+/*
+    the value as the comparison sees it, once the affinity beside it has been applied, as a key
+    to seek. None for NULL, blobs and reals that aren't whole, which need a scan instead
+*/
+pub fn lookup_key(value: &Value, affinity: Option<Affinity>) -> Option<FilterValue> {
+    let value = match affinity {
+        Some(affinity) => value.clone().apply_affinity(affinity),
+        None => value.clone(),
     };
-    for row_id in record_ids {
-        let mut page = root_page_rc.clone();
-        let mut page_to_read = 0u32;
-        let filter_row_id = FilterValue::Int(row_id.parse().unwrap());
-        while page.page_header.page_type == PageType::TblInt {
-            let cells = page.cells.deref();
-            let res =
-                bin_search_payload::<TableIntCell>(cells, &filter_row_id, &payload_extractor_fn);
-            page_to_read = match res {
-                SearchResult::ThisPage(cell, _) | SearchResult::LeftPage(cell) => {
-                    cell.left_child_page_no().unwrap()
-                }
-                SearchResult::RightPage => page.page_header.right_pointer.unwrap(),
-            };
-            page = Rc::from(builder.new_reader(page_to_read).read_page());
+    match value {
+        Value::Text(text) => Some(FilterValue::String(text)),
+        Value::Integer(int) => Some(FilterValue::Int(int)),
+        /* whole reals compare equal to the integer */
+        Value::Real(real) if real.fract() == 0.0 && real.abs() < 9.0e15 => {
+            Some(FilterValue::Int(real as i64))
         }
+        _ => None,
+    }
+}
 
-        let cell = match bin_search_payload::<TableLeafCell>(
-            page.cells.deref(),
-            &filter_row_id,
-            &payload_extractor_fn,
-        ) {
-            SearchResult::ThisPage(cell, _) => cell,
-            SearchResult::LeftPage(_) | SearchResult::RightPage => {
-                panic!("interior page contains entry but leaf doesn't. Page no: {page_to_read}")
-            }
-        };
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum FilterValue {
+    String(String),
+    Int(i64),
+}
 
-        if let Some(row) = filter_rows(
-            filter,
-            downcast::<TableLeafCell>(&cell).unwrap(),
-            &col_positions,
-        ) {
-            println!("{}", row);
+impl From<&FilterValue> for Value {
+    fn from(filter_value: &FilterValue) -> Self {
+        match filter_value {
+            FilterValue::String(string) => Value::Text(string.clone()),
+            FilterValue::Int(int) => Value::Integer(*int),
         }
     }
 }
 
-fn fetch_all_leaves(
-    first_page: Page,
-    builder: &mut PageReaderBuilder,
-    first_page_no: u32,
-) -> Vec<(u32, Page)> {
-    let mut pages = vec![];
-    let mut stack = VecDeque::new();
-    let mut visited = HashSet::new();
-    stack.push_back((first_page_no, first_page));
-
-    let mut check_and_push = |page_no: u32, stack: &mut VecDeque<(u32, Page)>| {
-        let mut reader = builder.new_reader(page_no);
-        let page = reader.read_page();
-        if !visited.contains(&page_no) {
-            visited.insert(page_no);
-            if reader.page_meta_data.page_type == PageType::TblLeaf {
-                pages.push((page_no, page));
-            } else {
-                stack.push_back((page_no, page));
-            }
+/*
+    walks the index entries the lookup selects, in index order or backwards, and emits the table
+    rows they point to. stops as soon as emit breaks, which gets the builder back to read other
+    tables with. a covering scan reads the rows from the index entries instead, with only the
+    indexed columns and the row_id filled in
+*/
+#[allow(clippy::too_many_arguments)]
+pub fn perform_index_scan<S: PageSource>(
+    index: &Index,
+    table: &Table,
+    builder: &mut PageReaderBuilder<S>,
+    lookup: &IndexLookup,
+    backwards: bool,
+    covering: bool,
+    filter: Option<&BoundExpr>,
+    emit: &mut impl FnMut(&mut PageReaderBuilder<S>, Vec<Value>) -> Result<ControlFlow<()>>,
+) -> Result<()> {
+    let encoding = builder.header().text_encoding;
+    let mut table_cursor = TableCursor::new(table.root_page());
+    let mut emit_row = |builder: &mut PageReaderBuilder<S>,
+                        record: &Record,
+                        row_id: i64|
+     -> Result<ControlFlow<()>> {
+        let row = if covering {
+            index_row(record, row_id, index, table)
+        } else {
+            let cell = match table_cursor.seek(builder, row_id)? {
+                Some(cell) if cell.row_id == row_id => cell,
+                _ => {
+                    return Err(ReaderError::corrupt(
+                        table.root_page(),
+                        0,
+                        format!(
+                            "index refers to row_id {} which is missing from the table",
+                            row_id
+                        ),
+                    ))
+                }
+            };
+            table_row(&cell.record.rows, Some(row_id), table, encoding)?
+        };
+        if filter.map_or(Ok(true), |filter| filter.matches(&row, encoding))? {
+            return emit(builder, row);
         }
+        Ok(ControlFlow::Continue(()))
     };
 
-    while !stack.is_empty() {
-        let (_, int_page) = stack.pop_front().unwrap();
-        if let Some(right_page_no) = int_page.page_header.right_pointer {
-            check_and_push(right_page_no, &mut stack);
+    let root_page = index.root_page();
+    let mut cursor = IndexCursor::new(root_page);
+    /* the planner only seeks an index whose collation is the one the lookup compares by */
+    let collation = index.collation(0, table).unwrap_or_default();
+    let descending = index
+        .columns
+        .first()
+        .is_some_and(|column| column.descending);
+    let in_index_order = |ordering: Ordering| {
+        if descending {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    };
+    let in_scan_order = |ordering: Ordering| {
+        if backwards {
+            in_index_order(ordering).reverse()
+        } else {
+            in_index_order(ordering)
+        }
+    };
+    /* a backwards scan starts each run at its last entry */
+    let seek = |cursor: &mut IndexCursor,
+                builder: &mut PageReaderBuilder<S>,
+                cmp: &dyn Fn(&[Value]) -> Ordering| match backwards {
+        true => cursor.seek_back(builder, cmp),
+        false => cursor.seek(builder, cmp),
+    };
+    let step = |cursor: &mut IndexCursor, builder: &mut PageReaderBuilder<S>| match backwards {
+        true => cursor.prev(builder),
+        false => cursor.next(builder),
+    };
+    match lookup {
+        IndexLookup::Keys(keys) => {
+            /* seeking the keys in index order keeps the rows in index order, and skips repeats */
+            let mut keys: Vec<Value> = keys.iter().map(Value::from).collect();
+            keys.sort_by(|a, b| in_scan_order(a.compare_collated(b, collation, encoding)));
+            keys.dedup_by(|a, b| a.compare_collated(b, collation, encoding) == Ordering::Equal);
+            for key in keys.iter() {
+                /* entries are sorted by key, so the matches are a run starting at the seek position */
+                let mut entry = seek(
+                    &mut cursor,
+                    builder,
+                    &|entry: &[Value]| match entry.first() {
+                        Some(first) => {
+                            in_index_order(first.compare_collated(key, collation, encoding))
+                        }
+                        None => Ordering::Less,
+                    },
+                )?;
+                while let Some(record) = entry {
+                    let (payload, row_id) = get_payload_id(&record, root_page)?;
+                    if key.compare_collated(&payload, collation, encoding) != Ordering::Equal {
+                        break;
+                    }
+                    if emit_row(builder, &record, row_id)?.is_break() {
+                        return Ok(());
+                    }
+                    entry = step(&mut cursor, builder)?;
+                }
+            }
+        }
+        IndexLookup::Prefixes(prefixes) => {
+            let mut prefixes = prefixes.clone();
+            prefixes.sort_by(|a, b| in_scan_order(encoding.compare(a, b)));
+            for prefix in prefixes.iter() {
+                /* the keys starting with the prefix are one run, in either direction */
+                let starts_with = |value: &Value| {
+                    value
+                        .as_text()
+                        .is_some_and(|text| text.starts_with(prefix.as_str()))
+                };
+                let target = Value::Text(prefix.clone());
+                let mut entry = seek(
+                    &mut cursor,
+                    builder,
+                    &|entry: &[Value]| match entry.first() {
+                        Some(first) if starts_with(first) => Ordering::Equal,
+                        Some(first) => in_index_order(first.compare(&target, encoding)),
+                        None => Ordering::Less,
+                    },
+                )?;
+                while let Some(record) = entry {
+                    let (payload, row_id) = get_payload_id(&record, root_page)?;
+                    if !starts_with(&payload) {
+                        break;
+                    }
+                    if emit_row(builder, &record, row_id)?.is_break() {
+                        return Ok(());
+                    }
+                    entry = step(&mut cursor, builder)?;
+                }
+            }
+        }
+        IndexLookup::All => {
+            let mut entry = step(&mut cursor, builder)?;
+            while let Some(record) = entry {
+                let (_, row_id) = get_payload_id(&record, root_page)?;
+                if emit_row(builder, &record, row_id)?.is_break() {
+                    return Ok(());
+                }
+                entry = step(&mut cursor, builder)?;
+            }
         }
-        int_page.cells.iter().for_each(|cell| {
-            let cell = downcast::<TableIntCell>(cell).unwrap();
-            let left_page_no = cell.left_child_page_no;
-            check_and_push(left_page_no, &mut stack);
-        });
     }
-    pages
+    Ok(())
 }
 
-fn filter_rows(filter: &Filter, cell: &TableLeafCell, col_positions: &[usize]) -> Option<String> {
-    let rows = &cell.record.rows;
-    let mut row_str = Vec::new();
+/*
+    emits every row passing the filter, in row_id order or backwards, until emit breaks. a
+    WITHOUT ROWID table is an index b-tree of its rows, in primary key order
+*/
+pub fn perform_full_table_scan<S: PageSource>(
+    table: &Table,
+    builder: &mut PageReaderBuilder<S>,
+    backwards: bool,
+    filter: Option<&BoundExpr>,
+    emit: &mut impl FnMut(&mut PageReaderBuilder<S>, Vec<Value>) -> Result<ControlFlow<()>>,
+) -> Result<()> {
+    let encoding = builder.header().text_encoding;
+    let mut emit_row = |builder: &mut PageReaderBuilder<S>, row: Vec<Value>| {
+        if filter.map_or(Ok(true), |filter| filter.matches(&row, encoding))? {
+            return emit(builder, row);
+        }
+        Ok(ControlFlow::Continue(()))
+    };
 
-    if filter.filter_col_pos == -1 || decode_match(filter, rows) {
-        col_positions.iter().for_each(|&pos| {
-            /* fetch based on the column position mentioned in the select query */
-            let val = if pos == 0 {
-                cell.row_id.to_string()
-            } else {
-                let row = &rows[pos];
-                row.clone()
-            };
-            row_str.push(val);
-        });
-        return Some(row_str.join("|"));
+    if table.without_rowid {
+        let mut cursor = IndexCursor::new(table.root_page());
+        let mut step = |builder: &mut PageReaderBuilder<S>| match backwards {
+            true => cursor.prev(builder),
+            false => cursor.next(builder),
+        };
+        while let Some(record) = step(builder)? {
+            let row = table_row(&record.rows, None, table, encoding)?;
+            if emit_row(builder, row)?.is_break() {
+                break;
+            }
+        }
+        return Ok(());
     }
-    None
-}
-
-pub fn fetch_all_leaves_for_table(
-    first_page: Page,
-    builder: &mut PageReaderBuilder,
-    page_no: u32,
-) -> Vec<(u32, Page)> {
-    let page_type = first_page.page_header.page_type;
-    if page_type == PageType::TblLeaf || page_type == PageType::IdxLeaf {
-        vec![(page_no, first_page.clone())]
-    } else if first_page.page_header.page_type == PageType::TblInt {
-        // look for table leaves
-        fetch_all_leaves(first_page, builder, page_no)
-    } else {
-        panic!("Invalid page type {:?}", page_type);
+    let mut cursor = TableCursor::new(table.root_page());
+    let mut step = |builder: &mut PageReaderBuilder<S>| match backwards {
+        true => cursor.prev(builder),
+        false => cursor.next(builder),
+    };
+    while let Some(cell) = step(builder)? {
+        let row = table_row(&cell.record.rows, Some(cell.row_id), table, encoding)?;
+        if emit_row(builder, row)?.is_break() {
+            break;
+        }
     }
+    Ok(())
 }
 
-fn bin_search_payload<T: Any + Cell>(
-    cells: &[Box<dyn Cell>],
-    filter_value: &FilterValue,
-    payload_extractor_fn: &dyn Fn(&dyn Cell) -> String,
-) -> SearchResult {
-    let len = cells.len() as u32;
-    let (mut l, mut h) = (0u32, len);
-    let mut ret = SearchResult::RightPage;
-    while l < h {
-        let m = (l + h) / 2;
-        let cell = downcast::<T>(&cells[m as usize]).unwrap();
-        let payload = payload_extractor_fn(cell);
-        let ordering = filter_cmp(filter_value, &payload);
-        // println!("{:?} {:?} {:?}", filter_value, payload, ordering);
+/*
+    emits the rows the lookup selects that pass the filter, in row_id order or backwards, until
+    emit breaks
+*/
+pub fn perform_rowid_scan<S: PageSource>(
+    table: &Table,
+    builder: &mut PageReaderBuilder<S>,
+    lookup: &RowIdLookup,
+    backwards: bool,
+    filter: Option<&BoundExpr>,
+    emit: &mut impl FnMut(&mut PageReaderBuilder<S>, Vec<Value>) -> Result<ControlFlow<()>>,
+) -> Result<()> {
+    let encoding = builder.header().text_encoding;
+    let mut emit_row = |builder: &mut PageReaderBuilder<S>, found: TableRow| {
+        let row = table_row(&found.record.rows, Some(found.row_id), table, encoding)?;
+        if filter.map_or(Ok(true), |filter| filter.matches(&row, encoding))? {
+            return emit(builder, row);
+        }
+        Ok(ControlFlow::Continue(()))
+    };
 
-        match ordering {
-            Ordering::Greater => {
-                l = m + 1;
+    let mut cursor = TableCursor::new(table.root_page());
+    match lookup {
+        RowIdLookup::Keys(keys) => {
+            let mut keys = keys.clone();
+            keys.sort_unstable();
+            keys.dedup();
+            if backwards {
+                keys.reverse();
             }
-            Ordering::Equal => {
-                ret = SearchResult::ThisPage(cell.clone_cell(), m);
-                h = m;
+            for key in keys {
+                let found = cursor.seek(builder, key)?;
+                let Some(found) = found.filter(|found| found.row_id == key) else {
+                    continue;
+                };
+                if emit_row(builder, found)?.is_break() {
+                    break;
+                }
+            }
+        }
+        RowIdLookup::Range { low, high } if backwards => {
+            let mut entry = match high {
+                Some(high) => cursor.seek_back(builder, *high)?,
+                None => cursor.last(builder)?,
+            };
+            while let Some(found) = entry {
+                if low.is_some_and(|low| found.row_id < low) || emit_row(builder, found)?.is_break()
+                {
+                    break;
+                }
+                entry = cursor.prev(builder)?;
             }
-            Ordering::Less => {
-                ret = SearchResult::LeftPage(cell.clone_cell());
-                h = m;
+        }
+        RowIdLookup::Range { low, high } => {
+            let mut entry = match low {
+                Some(low) => cursor.seek(builder, *low)?,
+                None => cursor.first(builder)?,
+            };
+            while let Some(found) = entry {
+                if high.is_some_and(|high| found.row_id > high)
+                    || emit_row(builder, found)?.is_break()
+                {
+                    break;
+                }
+                entry = cursor.next(builder)?;
             }
         }
     }
-    ret
+    Ok(())
 }
 
-fn get_payload_id(cell: &dyn Cell) -> (String, String) {
-    let rows = cell.record().unwrap().rows;
-    (rows.first().unwrap().to_string(), rows[1].clone())
+/* the row with this row_id, if the table has one */
+pub fn perform_rowid_lookup<S: PageSource>(
+    table: &Table,
+    builder: &mut PageReaderBuilder<S>,
+    row_id: i64,
+) -> Result<Option<Vec<Value>>> {
+    let encoding = builder.header().text_encoding;
+    let mut cursor = TableCursor::new(table.root_page());
+    match cursor.seek(builder, row_id)? {
+        Some(cell) if cell.row_id == row_id => {
+            table_row(&cell.record.rows, Some(row_id), table, encoding).map(Some)
+        }
+        _ => Ok(None),
+    }
 }
 
-fn fetch_table_data(
-    col_positions: &[usize],
-    page_num_and_page: &(u32, Page),
-    filter: &Filter,
-) -> anyhow::Result<Vec<String>> {
-    let (_, page) = page_num_and_page;
-    let page_type = page.page_header.page_type;
-    let mut rows = Vec::new();
-    if page_type == PageType::TblLeaf {
-        page.cells.iter().for_each(|cell| {
-            let cell = downcast::<TableLeafCell>(cell).unwrap();
-            if let Some(row) = filter_rows(filter, cell, col_positions) {
-                rows.push(row);
-            }
-        });
-        Ok(rows)
-    } else {
-        bail!("type unhandled {:?}", page_type);
+pub fn count_all_rows<S: PageSource>(
+    table: &Table,
+    builder: &mut PageReaderBuilder<S>,
+) -> Result<u64> {
+    if table.without_rowid {
+        let mut cursor = IndexCursor::new(table.root_page());
+        let mut count = 0;
+        while cursor.next(builder)?.is_some() {
+            count += 1;
+        }
+        return Ok(count);
     }
+    let cursor = TableCursor::new(table.root_page());
+    cursor.count(builder)
 }
 
-fn decode_match(filter: &Filter, rows: &[String]) -> bool {
-    if rows.len() <= filter.filter_col_pos as usize {
-        return false;
+/*
+    the table's columns in declaration order, laid out from the stored record. the INTEGER
+    PRIMARY KEY (stored as NULL) is filled in from row_id, a column the record stops short of
+    reads its DEFAULT and VIRTUAL generated columns are computed from the rest of the row
+*/
+fn table_row(
+    rows: &[Value],
+    row_id: Option<i64>,
+    table: &Table,
+    encoding: TextEncoding,
+) -> Result<Vec<Value>> {
+    if table.record_positions.is_empty() {
+        return Ok(rows.to_vec());
     }
 
-    filter_cmp(&filter.filter_value, &rows[filter.filter_col_pos as usize]) == Ordering::Equal
+    let mut row = Vec::with_capacity(table.columns.len());
+    for (pos, record_pos) in table.record_positions.iter().enumerate() {
+        let affinity = table.columns[pos].affinity();
+        let value = match (record_pos, row_id) {
+            (_, Some(row_id)) if table.rowid_alias == Some(pos) => Value::Integer(row_id),
+            (Some(record_pos), _) => match rows.get(*record_pos) {
+                Some(value) => value.clone(),
+                /* rows written before an ALTER TABLE ADD COLUMN stop short of the new columns */
+                None => table.defaults[pos]
+                    .eval(&[], encoding)?
+                    .apply_affinity(affinity),
+            },
+            (None, _) => Value::Null,
+        };
+        row.push(whole_real(value, affinity));
+    }
+    for (pos, generated) in table.generated.iter() {
+        let affinity = table.columns[*pos].affinity();
+        row[*pos] = whole_real(
+            generated.eval(&row, encoding)?.apply_affinity(affinity),
+            affinity,
+        );
+    }
+    Ok(row)
 }
 
-fn fetch_table_first_page(cell: &dyn Cell, builder: &mut PageReaderBuilder) -> (u32, Page) {
-    /* page where the table is stored */
-    let page_no: u32 = cell.record().unwrap().rows.get(3).unwrap().parse().unwrap();
-    (page_no, builder.new_reader(page_no).read_page())
+/* sqlite stores whole REAL values as integers to save space */
+fn whole_real(value: Value, affinity: Affinity) -> Value {
+    match (value, affinity) {
+        (Value::Integer(int), Affinity::Real) => Value::Real(int as f64),
+        (value, _) => value,
+    }
 }
 
-fn get_column_position(select_col_names: Vec<String>, table_columns: &[String]) -> Vec<usize> {
-    let mut col_positions = Vec::new();
-    if select_col_names.first().unwrap() == "*" {
-        for pos in 0..table_columns.len() {
-            col_positions.push(pos);
-        }
-    } else {
-        select_col_names.iter().for_each(|col| {
-            col_positions.push(
-                table_columns
-                    .iter()
-                    .position(|name| name == col)
-                    .expect("column {col} not found"),
-            );
-        });
+/* an index entry is the indexed columns followed by the row_id of the table row */
+fn get_payload_id(record: &Record, index_page_no: u32) -> Result<(Value, i64)> {
+    let rows = &record.rows;
+    let row_id = rows
+        .last()
+        .and_then(|row_id| row_id.as_integer())
+        .ok_or_else(|| {
+            ReaderError::corrupt(index_page_no, 0, "index entry without an integer row_id")
+        })?;
+    if rows.len() < 2 {
+        return Err(ReaderError::corrupt(
+            index_page_no,
+            0,
+            "index entry without a key",
+        ));
     }
-    col_positions
+    Ok((rows[0].clone(), row_id))
 }
 
-fn filter_cmp(filter_value: &FilterValue, payload: &String) -> Ordering {
-    match filter_value {
-        FilterValue::String(filter_string) => filter_string.cmp(payload),
-        FilterValue::Int(filter_int) => {
-            let payload_int = payload.parse::<i64>().unwrap();
-            filter_int.cmp(&payload_int)
+/* a table row laid out from an index entry, the columns the index doesn't hold read as NULL */
+fn index_row(record: &Record, row_id: i64, index: &Index, table: &Table) -> Vec<Value> {
+    let mut row = vec![Value::Null; table.columns.len()];
+    for (column, value) in index.columns.iter().zip(record.rows.iter()) {
+        if let Some(pos) = table.column_position(&column.name) {
+            row[pos] = whole_real(value.clone(), table.columns[pos].affinity());
         }
     }
+    if let Some(pos) = table.rowid_alias {
+        row[pos] = Value::Integer(row_id);
+    }
+    row
 }
@@ -0,0 +1,221 @@
+use crate::error::{ReaderError, Result};
+use std::cmp::Ordering;
+use std::fmt::{Display, Formatter};
+
+pub const HEADER_SIZE: usize = 100;
+const MAGIC: &[u8; 16] = b"SQLite format 3\0";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextEncoding {
+    Utf8,
+    Utf16le,
+    Utf16be,
+}
+
+impl TextEncoding {
+    pub fn from_header_value(value: u32) -> Option<Self> {
+        match value {
+            1 => Some(TextEncoding::Utf8),
+            2 => Some(TextEncoding::Utf16le),
+            3 => Some(TextEncoding::Utf16be),
+            _ => None,
+        }
+    }
+
+    pub fn header_value(&self) -> u32 {
+        match self {
+            TextEncoding::Utf8 => 1,
+            TextEncoding::Utf16le => 2,
+            TextEncoding::Utf16be => 3,
+        }
+    }
+
+    pub fn decode(&self, bytes: &[u8]) -> String {
+        let units = bytes.chunks_exact(2);
+        match self {
+            TextEncoding::Utf8 => String::from_utf8_lossy(bytes).to_string(),
+            TextEncoding::Utf16le => String::from_utf16_lossy(
+                &units
+                    .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+                    .collect::<Vec<_>>(),
+            ),
+            TextEncoding::Utf16be => String::from_utf16_lossy(
+                &units
+                    .map(|unit| u16::from_be_bytes([unit[0], unit[1]]))
+                    .collect::<Vec<_>>(),
+            ),
+        }
+    }
+
+    pub fn encode(&self, text: &str) -> Vec<u8> {
+        match self {
+            TextEncoding::Utf8 => text.as_bytes().to_vec(),
+            TextEncoding::Utf16le => text.encode_utf16().flat_map(u16::to_le_bytes).collect(),
+            TextEncoding::Utf16be => text.encode_utf16().flat_map(u16::to_be_bytes).collect(),
+        }
+    }
+
+    /* BINARY collation is a memcmp of the text as stored, so the order depends on the encoding */
+    pub fn compare(&self, a: &str, b: &str) -> Ordering {
+        match self {
+            TextEncoding::Utf8 => a.cmp(b),
+            _ => self.encode(a).cmp(&self.encode(b)),
+        }
+    }
+}
+
+impl Display for TextEncoding {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            TextEncoding::Utf8 => "utf8",
+            TextEncoding::Utf16le => "utf16le",
+            TextEncoding::Utf16be => "utf16be",
+        };
+        write!(f, "{} ({})", self.header_value(), name)
+    }
+}
+
+/* the first 100 bytes of page 1, see https://www.sqlite.org/fileformat.html#the_database_header */
+#[derive(Debug, Clone)]
+pub struct DatabaseHeader {
+    pub page_size: u32,
+    pub write_version: u8,
+    pub read_version: u8,
+    pub reserved_bytes: u8,
+    pub max_payload_fraction: u8,
+    pub min_payload_fraction: u8,
+    pub leaf_payload_fraction: u8,
+    pub file_change_counter: u32,
+    pub page_count: u32,
+    pub freelist_trunk_page: u32,
+    pub freelist_page_count: u32,
+    pub schema_cookie: u32,
+    pub schema_format: u32,
+    pub default_cache_size: u32,
+    pub largest_root_page: u32,
+    pub text_encoding: TextEncoding,
+    pub user_version: u32,
+    pub incremental_vacuum: u32,
+    pub application_id: u32,
+    pub version_valid_for: u32,
+    pub sqlite_version_number: u32,
+}
+
+impl DatabaseHeader {
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < HEADER_SIZE {
+            return Err(ReaderError::corrupt(
+                1,
+                bytes.len(),
+                "database header is truncated",
+            ));
+        }
+        if &bytes[0..16] != MAGIC {
+            return Err(ReaderError::corrupt(1, 0, "file is not a database"));
+        }
+
+        let be_u32 =
+            |offset: usize| u32::from_be_bytes(bytes[offset..offset + 4].try_into().unwrap());
+
+        /* the value 1 stands for 65536, which doesn't fit in two bytes */
+        let page_size = match u16::from_be_bytes([bytes[16], bytes[17]]) {
+            1 => 65536_u32,
+            size => size as u32,
+        };
+        if !(512..=65536).contains(&page_size) || !page_size.is_power_of_two() {
+            return Err(ReaderError::corrupt(
+                1,
+                16,
+                format!("invalid page size {}", page_size),
+            ));
+        }
+
+        let reserved_bytes = bytes[20];
+        if page_size - (reserved_bytes as u32) < 480 {
+            return Err(ReaderError::corrupt(
+                1,
+                20,
+                format!(
+                    "{} reserved bytes leave too little usable space",
+                    reserved_bytes
+                ),
+            ));
+        }
+
+        /* the file format fixes the payload fractions, anything else is not a valid database */
+        if (bytes[21], bytes[22], bytes[23]) != (64, 32, 32) {
+            return Err(ReaderError::corrupt(1, 21, "invalid payload fractions"));
+        }
+
+        let text_encoding = match be_u32(56) {
+            /* a brand new database that has no schema yet may leave the encoding unset */
+            0 => TextEncoding::Utf8,
+            value => match TextEncoding::from_header_value(value) {
+                Some(encoding) => encoding,
+                None => {
+                    return Err(ReaderError::corrupt(
+                        1,
+                        56,
+                        format!("invalid text encoding {}", value),
+                    ))
+                }
+            },
+        };
+
+        Ok(Self {
+            page_size,
+            write_version: bytes[18],
+            read_version: bytes[19],
+            reserved_bytes,
+            max_payload_fraction: bytes[21],
+            min_payload_fraction: bytes[22],
+            leaf_payload_fraction: bytes[23],
+            file_change_counter: be_u32(24),
+            page_count: be_u32(28),
+            freelist_trunk_page: be_u32(32),
+            freelist_page_count: be_u32(36),
+            schema_cookie: be_u32(40),
+            schema_format: be_u32(44),
+            default_cache_size: be_u32(48),
+            largest_root_page: be_u32(52),
+            text_encoding,
+            user_version: be_u32(60),
+            incremental_vacuum: be_u32(64),
+            application_id: be_u32(68),
+            version_valid_for: be_u32(92),
+            sqlite_version_number: be_u32(96),
+        })
+    }
+
+    /* page size minus the bytes reserved at the end of every page for extensions */
+    pub fn usable_size(&self) -> u32 {
+        self.page_size - self.reserved_bytes as u32
+    }
+}
+
+impl Display for DatabaseHeader {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let fields: [(&str, &dyn Display); 16] = [
+            ("database page size:", &self.page_size),
+            ("write format:", &self.write_version),
+            ("read format:", &self.read_version),
+            ("reserved bytes:", &self.reserved_bytes),
+            ("file change counter:", &self.file_change_counter),
+            ("database page count:", &self.page_count),
+            ("freelist page count:", &self.freelist_page_count),
+            ("schema cookie:", &self.schema_cookie),
+            ("schema format:", &self.schema_format),
+            ("default cache size:", &self.default_cache_size),
+            ("autovacuum top root:", &self.largest_root_page),
+            ("incremental vacuum:", &self.incremental_vacuum),
+            ("text encoding:", &self.text_encoding),
+            ("user version:", &self.user_version),
+            ("application id:", &self.application_id),
+            ("software version:", &self.sqlite_version_number),
+        ];
+        for (name, value) in fields {
+            writeln!(f, "{:<20} {}", name, value)?;
+        }
+        Ok(())
+    }
+}
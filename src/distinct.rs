@@ -0,0 +1,89 @@
+use crate::aggregate::KeyPart;
+use crate::error::Result;
+use crate::spill::{row_size, SpillFile};
+use crate::value::{Collation, Value};
+use std::collections::HashSet;
+use std::ops::ControlFlow;
+
+/*
+    drops rows whose leading values were seen before, NULLs being equal to each other. rows
+    arriving sorted by those values only need the last one remembered, otherwise the seen ones
+    are hashed until they outgrow the memory budget. rows that might be new after that are
+    spilled to disk and deduplicated in another pass after these ones are done
+*/
+pub struct Distinct {
+    /* how each leading value compares, the trailing values just ride along */
+    collations: Vec<Collation>,
+    sorted: bool,
+    memory_budget: usize,
+    seen: HashSet<Vec<KeyPart>>,
+    last: Option<Vec<KeyPart>>,
+    bytes: usize,
+    overflow: Option<SpillFile>,
+}
+
+impl Distinct {
+    pub fn new(collations: Vec<Collation>, sorted: bool, memory_budget: usize) -> Self {
+        Self {
+            collations,
+            sorted,
+            memory_budget,
+            seen: HashSet::new(),
+            last: None,
+            bytes: 0,
+            overflow: None,
+        }
+    }
+
+    /* the row back when it's the first of its kind, None when it's a repeat or was spilled */
+    pub fn push(&mut self, row: Vec<Value>) -> Result<Option<Vec<Value>>> {
+        let key: Vec<KeyPart> = row
+            .iter()
+            .zip(self.collations.iter())
+            .map(|(value, collation)| KeyPart::new(value, *collation))
+            .collect();
+
+        if self.sorted {
+            if self.last.as_ref() == Some(&key) {
+                return Ok(None);
+            }
+            self.last = Some(key);
+            return Ok(Some(row));
+        }
+        if self.seen.contains(&key) {
+            return Ok(None);
+        }
+        if self.seen.is_empty() || self.bytes < self.memory_budget {
+            self.bytes += row_size(&row[..self.collations.len()]);
+            self.seen.insert(key);
+            return Ok(Some(row));
+        }
+        let overflow = match self.overflow.as_mut() {
+            Some(overflow) => overflow,
+            None => self.overflow.insert(SpillFile::create()?),
+        };
+        overflow.write_row(&row)?;
+        Ok(None)
+    }
+
+    /* emits the first of each kind among the spilled rows, until `emit` breaks */
+    pub fn finish(
+        self,
+        emit: &mut impl FnMut(Vec<Value>) -> Result<ControlFlow<()>>,
+    ) -> Result<()> {
+        let mut distinct = self;
+        while let Some(overflow) = distinct.overflow.take() {
+            let mut next =
+                Distinct::new(distinct.collations.clone(), false, distinct.memory_budget);
+            for row in overflow.into_reader()? {
+                if let Some(row) = next.push(row?)? {
+                    if emit(row)?.is_break() {
+                        return Ok(());
+                    }
+                }
+            }
+            distinct = next;
+        }
+        Ok(())
+    }
+}
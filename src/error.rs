@@ -0,0 +1,50 @@
+use std::io;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ReaderError {
+    #[error("i/o error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("database disk image is malformed: {reason} (page {page}, offset {offset})")]
+    Corrupt {
+        page: u32,
+        offset: usize,
+        reason: String,
+    },
+
+    #[error("unsupported: {0}")]
+    Unsupported(String),
+
+    #[error("no such table: {0}")]
+    NoSuchTable(String),
+
+    #[error("no such column: {0}")]
+    NoSuchColumn(String),
+
+    /* an unqualified name that more than one table of the join has */
+    #[error("ambiguous column name: {0}")]
+    AmbiguousColumn(String),
+
+    #[error("parse error: {0}")]
+    Parse(String),
+
+    /* a value that had to be an integer, like a LIMIT, wasn't one */
+    #[error("datatype mismatch")]
+    DatatypeMismatch,
+
+    #[error("integer overflow")]
+    IntegerOverflow,
+}
+
+pub type Result<T> = std::result::Result<T, ReaderError>;
+
+impl ReaderError {
+    pub fn corrupt(page: u32, offset: usize, reason: impl Into<String>) -> Self {
+        ReaderError::Corrupt {
+            page,
+            offset,
+            reason: reason.into(),
+        }
+    }
+}
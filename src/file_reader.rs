@@ -1,3 +1,4 @@
+use bytes::Bytes;
 use std::fs::File;
 use std::io::{self, BufReader, Read, Seek};
 
@@ -7,9 +8,9 @@ pub struct FileReader {
 }
 
 impl FileReader {
-    pub fn new(path: &String) -> Option<Self> {
-        let f = File::open(path).expect("cannot open file in path {path}");
-        Some(FileReader {
+    pub fn new(path: &String) -> io::Result<Self> {
+        let f = File::open(path)?;
+        Ok(FileReader {
             reader: BufReader::with_capacity(u16::MAX as usize, f),
             last_offset: 0_u64,
         })
@@ -20,7 +21,7 @@ impl FileReader {
     }
 
     fn read_from_offset(&mut self, offset: u64, size: usize) -> io::Result<BytesIterator> {
-        let _ = self.reader.seek(io::SeekFrom::Start(offset));
+        self.reader.seek(io::SeekFrom::Start(offset))?;
         self.read_bytes(size)
     }
 
@@ -36,49 +37,60 @@ impl FileReader {
     }
 }
 
+/* Bytes slices share the underlying buffer, so carving cells out of a page doesn't copy */
 #[derive(Debug)]
 pub struct BytesIterator {
-    bytes: Box<[u8]>,
+    bytes: Bytes,
     offset: usize,
 }
 
 impl BytesIterator {
     #[inline]
-    pub fn new(bytes: Box<[u8]>) -> Self {
-        Self { bytes, offset: 0 }
+    pub fn new(bytes: impl Into<Bytes>) -> Self {
+        Self {
+            bytes: bytes.into(),
+            offset: 0,
+        }
     }
 
     #[inline]
     fn within_bounds(&self, n: &usize) -> bool {
-        *n != 0usize || self.offset < self.bytes.len()
+        *n <= self.offset && self.offset <= self.bytes.len()
     }
 
+    /* a read past the end leaves the position where it was */
     #[inline]
-    pub fn next_n(&mut self, n: usize) -> Option<Box<[u8]>> {
-        self.offset += n;
+    pub fn next_n(&mut self, n: usize) -> Option<Bytes> {
+        let end = self.offset.checked_add(n)?;
+        if end > self.bytes.len() {
+            return None;
+        }
+        self.offset = end;
         self.peek_back_n(&n)
     }
 
     pub fn next_n_as_iter(&mut self, n: usize) -> Option<Self> {
-        let bytes = self.next_n(n).unwrap();
-        Some(Self::new(bytes))
+        self.next_n(n).map(Self::new)
     }
 
     #[inline]
-    pub fn peek_back_n(&self, n: &usize) -> Option<Box<[u8]>> {
+    pub fn peek_back_n(&self, n: &usize) -> Option<Bytes> {
         if !self.within_bounds(n) {
             return None;
         }
 
         let start = self.offset - n;
         let end = self.offset;
-        Some(self.bytes[start..end].to_vec().into_boxed_slice())
+        Some(self.bytes.slice(start..end))
     }
 
     #[inline]
-    pub fn from_offset(&mut self, start: usize, n: usize) -> Option<Box<[u8]>> {
-        self.offset = start + n;
-        self.peek_back_n(&n)
+    pub fn from_offset(&mut self, start: usize, n: usize) -> Option<Bytes> {
+        self.jump_to(start).next_n(n)
+    }
+
+    pub fn offset(&self) -> usize {
+        self.offset
     }
 
     pub fn has_next(&self) -> bool {
@@ -95,7 +107,8 @@ impl Iterator for BytesIterator {
     type Item = u8;
 
     fn next(&mut self) -> Option<Self::Item> {
+        let byte = *self.bytes.get(self.offset)?;
         self.offset += 1;
-        Some(self.bytes[self.offset - 1])
+        Some(byte)
     }
 }
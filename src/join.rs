@@ -0,0 +1,691 @@
+use crate::aggregate::KeyPart;
+use crate::ast::{BinaryOp, ColumnDef, JoinConstraint, JoinKind, TableReference};
+use crate::bound_expr::BoundExpr;
+use crate::data_filter_processor::{self, FilterValue, IndexLookup};
+use crate::db_header::TextEncoding;
+use crate::error::{ReaderError, Result};
+use crate::page_reader::PageReaderBuilder;
+use crate::page_source::PageSource;
+use crate::parser::identifiers_match;
+use crate::schema::{Index, Schema, Table};
+use crate::spill::row_size;
+use crate::value::{Affinity, Collation, Value};
+use std::collections::HashMap;
+use std::ops::{ControlFlow, Range};
+
+/* bytes of rows a hash join holds in memory before it falls back to rescanning the table */
+pub const DEFAULT_JOIN_MEMORY: usize = 64 * 1024 * 1024;
+
+/*
+    the tables of a FROM clause side by side. a joined row is their rows one after another,
+    so every column of the join is a position in that row
+*/
+pub struct Source<'a> {
+    pub tables: Vec<SourceTable<'a>>,
+}
+
+pub struct SourceTable<'a> {
+    pub table: &'a Table,
+    /* the alias, or else the table's name, that table.column refers to it by */
+    pub name: String,
+    /* where the table's columns start in the joined row */
+    pub offset: usize,
+    pub join: JoinKind,
+    /* the columns a USING made equal, as (earlier table's, this table's) row positions */
+    pub using: Vec<(usize, usize)>,
+}
+
+impl<'a> Source<'a> {
+    pub fn new(schema: &'a Schema, from: &[TableReference]) -> Result<Self> {
+        let mut source = Source { tables: Vec::new() };
+        for reference in from {
+            let table = schema.table(&reference.table_name)?;
+            if table.virtual_table {
+                return Err(ReaderError::Unsupported(format!(
+                    "virtual table {}",
+                    table.name()
+                )));
+            }
+            let mut using = Vec::new();
+            if let Some(JoinConstraint::Using(names)) = &reference.constraint {
+                for name in names {
+                    let missing = || {
+                        ReaderError::Parse(format!(
+                            "cannot join using column {} - column not present in both tables",
+                            name
+                        ))
+                    };
+                    let left = match source.resolve(None, name) {
+                        Err(ReaderError::NoSuchColumn(_)) => return Err(missing()),
+                        left => left?,
+                    };
+                    let right = table.column_position(name).ok_or_else(missing)?;
+                    using.push((left, source.width() + right));
+                }
+            }
+            source.tables.push(SourceTable {
+                table,
+                name: reference
+                    .alias
+                    .clone()
+                    .unwrap_or_else(|| table.name().to_string()),
+                offset: source.width(),
+                join: reference.join,
+                using,
+            });
+        }
+        Ok(source)
+    }
+
+    /* a single table under its own name, for the expressions of its definition */
+    pub fn of_table(table: &'a Table) -> Self {
+        Source {
+            tables: vec![SourceTable {
+                table,
+                name: table.name().to_string(),
+                offset: 0,
+                join: JoinKind::Inner,
+                using: Vec::new(),
+            }],
+        }
+    }
+
+    /* the number of columns in a joined row */
+    pub fn width(&self) -> usize {
+        self.tables
+            .last()
+            .map_or(0, |last| last.offset + last.table.columns.len())
+    }
+
+    pub fn column(&self, pos: usize) -> Option<&'a ColumnDef> {
+        let table = &self.tables[self.table_at(pos)?];
+        table.table.columns.get(pos - table.offset)
+    }
+
+    /* which table the column at `pos` belongs to */
+    pub fn table_at(&self, pos: usize) -> Option<usize> {
+        self.tables
+            .iter()
+            .rposition(|table| table.offset <= pos)
+            .filter(|_| pos < self.width())
+    }
+
+    /*
+        the position of table.name, or of a name only one table has. a column a USING merged
+        into an earlier table's only answers to its qualified name
+    */
+    pub fn resolve(&self, table: Option<&str>, name: &str) -> Result<usize> {
+        if let Some(table) = table {
+            let qualified = || format!("{}.{}", table, name);
+            let mut matches = self
+                .tables
+                .iter()
+                .filter(|source_table| identifiers_match(&source_table.name, table));
+            let source_table = matches
+                .next()
+                .ok_or_else(|| ReaderError::NoSuchColumn(qualified()))?;
+            let pos = source_table
+                .table
+                .column_position(name)
+                .ok_or_else(|| ReaderError::NoSuchColumn(qualified()))?;
+            if matches.next().is_some() {
+                return Err(ReaderError::AmbiguousColumn(qualified()));
+            }
+            return Ok(source_table.offset + pos);
+        }
+
+        let mut found = self.tables.iter().filter_map(|source_table| {
+            let pos = source_table.offset + source_table.table.column_position(name)?;
+            let merged = source_table.using.iter().any(|(_, right)| *right == pos);
+            (!merged).then_some(pos)
+        });
+        let pos = found
+            .next()
+            .ok_or_else(|| ReaderError::NoSuchColumn(name.to_string()))?;
+        if found.next().is_some() {
+            return Err(ReaderError::AmbiguousColumn(name.to_string()));
+        }
+        Ok(pos)
+    }
+
+    /* the columns * stands for: every table's, less the ones a USING merged away */
+    pub fn star_columns(&self) -> Vec<(&str, &str)> {
+        self.tables
+            .iter()
+            .flat_map(|source_table| {
+                source_table
+                    .table
+                    .columns
+                    .iter()
+                    .enumerate()
+                    .filter(|(pos, _)| {
+                        let pos = source_table.offset + pos;
+                        !source_table.using.iter().any(|(_, right)| *right == pos)
+                    })
+                    .map(|(_, column)| (source_table.name.as_str(), column.name.as_str()))
+            })
+            .collect()
+    }
+}
+
+/* how a joined table finds its rows matching a row of the tables before it */
+#[derive(Debug)]
+enum Access<'a> {
+    /* seeks the row_id the expression gives, converted to the affinity beside it */
+    RowId(BoundExpr, Option<Affinity>),
+    /*
+        seeks the index on a column equal to the expression, in the index's direction. the
+        index sorts the column by the collation the equality compares with
+    */
+    Index(&'a Index, BoundExpr, Option<Affinity>),
+    /*
+        hashes the table's rows on the `build` expressions and looks up the values of the
+        `probe` ones, each converted to its affinity and keyed by its equality's collation. with
+        no equality to hash on, every row lands in one bucket
+    */
+    Hash {
+        build: Vec<(BoundExpr, Option<Affinity>)>,
+        probe: Vec<(BoundExpr, Option<Affinity>)>,
+        collations: Vec<Collation>,
+        /* the terms that only read this table, which keep rows out of the hash */
+        local: Option<BoundExpr>,
+    },
+}
+
+/* one table after the first, joined to the rows of the tables before it */
+#[derive(Debug)]
+pub struct Join<'a> {
+    pub table: &'a Table,
+    offset: usize,
+    kind: JoinKind,
+    access: Access<'a>,
+    /* whether the index holds every column the query reads from the table */
+    pub covering: bool,
+    /* the terms a joined row has to pass to be a match, the ON of a LEFT JOIN */
+    condition: Option<BoundExpr>,
+    /* the WHERE terms on a LEFT JOIN's table, which see the NULLs of a row without matches */
+    filter: Option<BoundExpr>,
+}
+
+impl<'a> Join<'a> {
+    /* the index it seeks, when it seeks one */
+    pub fn index(&self) -> Option<&'a Index> {
+        match self.access {
+            Access::Index(index, ..) => Some(index),
+            _ => None,
+        }
+    }
+
+    /* the row positions of its table, in the joined row */
+    pub fn positions(&self) -> Range<usize> {
+        self.offset..self.offset + self.table.columns.len()
+    }
+
+    /* adds the positions of the joined row values this reads to `columns` */
+    pub fn columns(&self, columns: &mut Vec<usize>) {
+        match &self.access {
+            Access::RowId(expr, _) | Access::Index(_, expr, _) => expr.columns(columns),
+            Access::Hash {
+                build,
+                probe,
+                local,
+                ..
+            } => {
+                for (expr, _) in build.iter().chain(probe.iter()) {
+                    expr.columns(columns);
+                }
+                if let Some(local) = local {
+                    local.columns(columns);
+                }
+            }
+        }
+        for filter in self.condition.iter().chain(self.filter.iter()) {
+            filter.columns(columns);
+        }
+    }
+}
+
+/*
+    spreads the WHERE terms and join constraints over the tables, each term going to the first
+    table whose rows have every column it reads. returns the first table's share, which its
+    scan applies, and a join for each of the other tables in FROM order
+*/
+pub fn plan_joins<'a>(
+    schema: &'a Schema,
+    source: &Source<'a>,
+    filter: Option<BoundExpr>,
+    constraints: Vec<Option<BoundExpr>>,
+) -> Result<(Option<BoundExpr>, Vec<Join<'a>>)> {
+    let count = source.tables.len();
+    let mut conditions: Vec<Vec<BoundExpr>> = (0..count).map(|_| Vec::new()).collect();
+    let mut filters: Vec<Vec<BoundExpr>> = (0..count).map(|_| Vec::new()).collect();
+
+    let last_table = |term: &BoundExpr| {
+        let mut columns = Vec::new();
+        term.columns(&mut columns);
+        columns
+            .iter()
+            .filter_map(|pos| source.table_at(*pos))
+            .max()
+            .unwrap_or(0)
+    };
+    /* an inner join's ON is as good as WHERE, a LEFT JOIN's only decides what matches */
+    let mut terms = Vec::new();
+    conjuncts(filter, &mut terms);
+    for (i, constraint) in constraints.into_iter().enumerate() {
+        let mut on = Vec::new();
+        conjuncts(constraint, &mut on);
+        for (left, right) in source.tables[i].using.iter() {
+            on.push(BoundExpr::columns_equal(*left, *right, source));
+        }
+        match source.tables[i].join {
+            JoinKind::Inner => terms.extend(on),
+            JoinKind::Left => {
+                for term in on {
+                    if last_table(&term) > i {
+                        return Err(ReaderError::Parse(
+                            "ON clause references tables to its right".to_string(),
+                        ));
+                    }
+                    conditions[i].push(term);
+                }
+            }
+        }
+    }
+    for term in terms {
+        let i = last_table(&term);
+        match source.tables[i].join {
+            JoinKind::Left if i > 0 => filters[i].push(term),
+            _ => conditions[i].push(term),
+        }
+    }
+
+    let mut conditions = conditions.into_iter();
+    let first = conjoin(conditions.next().unwrap_or_default());
+    let joins = source
+        .tables
+        .iter()
+        .skip(1)
+        .zip(conditions.zip(filters.into_iter().skip(1)))
+        .map(|(source_table, (condition, filter))| Join {
+            table: source_table.table,
+            offset: source_table.offset,
+            kind: source_table.join,
+            access: plan_access(schema, source_table, &condition),
+            covering: false,
+            condition: conjoin(condition),
+            filter: conjoin(filter),
+        })
+        .collect();
+    Ok((first, joins))
+}
+
+/*
+    a seek on the row_id or an index for a term equating one of the table's columns with the
+    earlier tables, a hash join on all such terms otherwise
+*/
+fn plan_access<'a>(
+    schema: &'a Schema,
+    source_table: &SourceTable<'a>,
+    terms: &[BoundExpr],
+) -> Access<'a> {
+    let table = source_table.table;
+    let range = source_table.offset..source_table.offset + table.columns.len();
+    let reads = |expr: &BoundExpr| {
+        let mut columns = Vec::new();
+        expr.columns(&mut columns);
+        columns
+    };
+    let earlier = |expr: &BoundExpr| reads(expr).iter().all(|pos| *pos < range.start);
+    let own = |expr: &BoundExpr| {
+        let columns = reads(expr);
+        !columns.is_empty() && columns.iter().all(|pos| range.contains(pos))
+    };
+
+    /*
+        each equality as (this table's side, the earlier tables' side), with their affinities,
+        and the collation it compares by
+    */
+    let mut equalities = Vec::new();
+    let mut local = Vec::new();
+    for term in terms {
+        if let BoundExpr::Compare {
+            op: BinaryOp::Eq,
+            left,
+            left_affinity,
+            right,
+            right_affinity,
+            collation,
+        } = term
+        {
+            let (left, right) = ((&**left, *left_affinity), (&**right, *right_affinity));
+            if own(left.0) && earlier(right.0) {
+                equalities.push((left, right, *collation));
+                continue;
+            }
+            if own(right.0) && earlier(left.0) {
+                equalities.push((right, left, *collation));
+                continue;
+            }
+        }
+        if own(term) {
+            local.push(term.clone());
+        }
+    }
+
+    /* a seek finds the stored values, so it only works when the column isn't converted */
+    for ((column, column_affinity), (value, affinity), _) in equalities.iter() {
+        let pos = match column {
+            BoundExpr::Column(pos) if column_affinity.is_none() => pos - range.start,
+            _ => continue,
+        };
+        if table.rowid_alias == Some(pos) {
+            return Access::RowId((*value).clone(), *affinity);
+        }
+    }
+    /* an index sorted by another collation keeps equal values apart, so those get hashed */
+    for ((column, column_affinity), (value, affinity), collation) in equalities.iter() {
+        let pos = match column {
+            BoundExpr::Column(pos) if column_affinity.is_none() => pos - range.start,
+            _ => continue,
+        };
+        if let Some(index) = schema.index_on(table, &table.columns[pos].name, *collation) {
+            return Access::Index(index, (*value).clone(), *affinity);
+        }
+    }
+
+    let mut build = Vec::new();
+    let mut probe = Vec::new();
+    let mut collations = Vec::new();
+    for ((column, column_affinity), (value, affinity), collation) in equalities {
+        build.push((column.clone(), column_affinity));
+        probe.push((value.clone(), affinity));
+        collations.push(collation);
+    }
+    Access::Hash {
+        build,
+        probe,
+        collations,
+        local: conjoin(local),
+    }
+}
+
+fn conjuncts(filter: Option<BoundExpr>, terms: &mut Vec<BoundExpr>) {
+    match filter {
+        Some(BoundExpr::And(left, right)) => {
+            conjuncts(Some(*left), terms);
+            conjuncts(Some(*right), terms);
+        }
+        Some(term) => terms.push(term),
+        None => {}
+    }
+}
+
+fn conjoin(terms: Vec<BoundExpr>) -> Option<BoundExpr> {
+    terms
+        .into_iter()
+        .reduce(|all, term| BoundExpr::And(Box::new(all), Box::new(term)))
+}
+
+/* a hash join's table of rows, built the first time a row looks it up */
+enum HashedRows {
+    Unbuilt,
+    Built(HashMap<Vec<KeyPart>, Vec<Vec<Value>>>),
+    /* outgrew the memory budget, so each row scans the table instead */
+    TooLarge,
+}
+
+/*
+    runs the joins as nested loops: each row of the first table is joined to the second table's
+    matches, each of those to the third's, and so on
+*/
+pub struct Joiner<'j, 'a> {
+    joins: &'j [Join<'a>],
+    hashed: Vec<HashedRows>,
+    encoding: TextEncoding,
+    memory_budget: usize,
+}
+
+impl<'j, 'a> Joiner<'j, 'a> {
+    pub fn new(joins: &'j [Join<'a>], encoding: TextEncoding, memory_budget: usize) -> Self {
+        Self {
+            joins,
+            hashed: joins.iter().map(|_| HashedRows::Unbuilt).collect(),
+            encoding,
+            memory_budget,
+        }
+    }
+
+    /* emits the joined rows a row of the first table extends to, until emit breaks */
+    pub fn extend<S: PageSource>(
+        &mut self,
+        builder: &mut PageReaderBuilder<S>,
+        row: Vec<Value>,
+        emit: &mut dyn FnMut(Vec<Value>) -> Result<ControlFlow<()>>,
+    ) -> Result<ControlFlow<()>> {
+        let settings = (self.encoding, self.memory_budget);
+        extend(self.joins, &mut self.hashed, builder, row, settings, emit)
+    }
+}
+
+fn extend<S: PageSource>(
+    joins: &[Join],
+    hashed: &mut [HashedRows],
+    builder: &mut PageReaderBuilder<S>,
+    row: Vec<Value>,
+    settings: (TextEncoding, usize),
+    emit: &mut dyn FnMut(Vec<Value>) -> Result<ControlFlow<()>>,
+) -> Result<ControlFlow<()>> {
+    let (join, rest) = match joins.split_first() {
+        Some(split) => split,
+        None => return emit(row),
+    };
+    let (rows, rest_hashed) = match hashed.split_first_mut() {
+        Some(split) => split,
+        None => return emit(row),
+    };
+    let encoding = settings.0;
+    let passes = |filter: &Option<BoundExpr>, row: &[Value]| {
+        filter
+            .as_ref()
+            .map_or(Ok(true), |filter| filter.matches(row, encoding))
+    };
+
+    let mut matched = false;
+    let mut step = |builder: &mut PageReaderBuilder<S>, inner: Vec<Value>| {
+        let mut joined = row.clone();
+        joined.extend(inner);
+        if !passes(&join.condition, &joined)? {
+            return Ok(ControlFlow::Continue(()));
+        }
+        matched = true;
+        if !passes(&join.filter, &joined)? {
+            return Ok(ControlFlow::Continue(()));
+        }
+        extend(rest, rest_hashed, builder, joined, settings, emit)
+    };
+    if find_matches(join, rows, builder, &row, settings, &mut step)?.is_break() {
+        return Ok(ControlFlow::Break(()));
+    }
+
+    /* a LEFT JOIN keeps a row without matches, with NULLs for the table's columns */
+    if matched || join.kind != JoinKind::Left {
+        return Ok(ControlFlow::Continue(()));
+    }
+    let mut joined = row;
+    joined.resize(join.offset + join.table.columns.len(), Value::Null);
+    if !passes(&join.filter, &joined)? {
+        return Ok(ControlFlow::Continue(()));
+    }
+    extend(rest, rest_hashed, builder, joined, settings, emit)
+}
+
+/* passes `step` the rows of the join's table that can match the row, until it breaks */
+fn find_matches<S: PageSource>(
+    join: &Join,
+    rows: &mut HashedRows,
+    builder: &mut PageReaderBuilder<S>,
+    row: &[Value],
+    (encoding, memory_budget): (TextEncoding, usize),
+    step: &mut impl FnMut(&mut PageReaderBuilder<S>, Vec<Value>) -> Result<ControlFlow<()>>,
+) -> Result<ControlFlow<()>> {
+    let table = join.table;
+    match &join.access {
+        Access::RowId(expr, affinity) => {
+            /* a row_id only equals an integer */
+            match data_filter_processor::lookup_key(&expr.eval(row, encoding)?, *affinity) {
+                Some(FilterValue::Int(row_id)) => {
+                    match data_filter_processor::perform_rowid_lookup(table, builder, row_id)? {
+                        Some(inner) => step(builder, inner),
+                        None => Ok(ControlFlow::Continue(())),
+                    }
+                }
+                _ => Ok(ControlFlow::Continue(())),
+            }
+        }
+        Access::Index(index, expr, affinity) => {
+            let value = expr.eval(row, encoding)?;
+            if value.is_null() {
+                return Ok(ControlFlow::Continue(()));
+            }
+            let key = match data_filter_processor::lookup_key(&value, *affinity) {
+                Some(key) => key,
+                /* the index can't seek blobs and fractions, but they can still match */
+                None => return scan_table(table, builder, step),
+            };
+            let mut flow = ControlFlow::Continue(());
+            data_filter_processor::perform_index_scan(
+                index,
+                table,
+                builder,
+                &IndexLookup::Keys(vec![key]),
+                false,
+                join.covering,
+                None,
+                &mut |builder, inner| {
+                    flow = step(builder, inner)?;
+                    Ok(flow)
+                },
+            )?;
+            Ok(flow)
+        }
+        Access::Hash {
+            build,
+            probe,
+            collations,
+            local,
+        } => {
+            if let HashedRows::Unbuilt = rows {
+                *rows = build_hash(
+                    join,
+                    (build, collations),
+                    local.as_ref(),
+                    builder,
+                    encoding,
+                    memory_budget,
+                )?;
+            }
+            let buckets = match rows {
+                HashedRows::Built(buckets) => buckets,
+                _ => return scan_table(table, builder, step),
+            };
+            let key = match hash_key(probe, collations, row, encoding)? {
+                Some(key) => key,
+                None => return Ok(ControlFlow::Continue(())),
+            };
+            for inner in buckets.get(&key).into_iter().flatten() {
+                if step(builder, inner.clone())?.is_break() {
+                    return Ok(ControlFlow::Break(()));
+                }
+            }
+            Ok(ControlFlow::Continue(()))
+        }
+    }
+}
+
+/* passes `step` every row of the table, for the rows nothing narrows down */
+fn scan_table<S: PageSource>(
+    table: &Table,
+    builder: &mut PageReaderBuilder<S>,
+    step: &mut impl FnMut(&mut PageReaderBuilder<S>, Vec<Value>) -> Result<ControlFlow<()>>,
+) -> Result<ControlFlow<()>> {
+    let mut flow = ControlFlow::Continue(());
+    data_filter_processor::perform_full_table_scan(
+        table,
+        builder,
+        false,
+        None,
+        &mut |builder, inner| {
+            flow = step(builder, inner)?;
+            Ok(flow)
+        },
+    )?;
+    Ok(flow)
+}
+
+/* the table's rows passing `local`, by their build key, unless they outgrow the budget */
+fn build_hash<S: PageSource>(
+    join: &Join,
+    (build, collations): (&[(BoundExpr, Option<Affinity>)], &[Collation]),
+    local: Option<&BoundExpr>,
+    builder: &mut PageReaderBuilder<S>,
+    encoding: TextEncoding,
+    memory_budget: usize,
+) -> Result<HashedRows> {
+    let mut buckets: HashMap<Vec<KeyPart>, Vec<Vec<Value>>> = HashMap::new();
+    let mut bytes = 0;
+    let mut too_large = false;
+    /* the expressions read the table's columns at their place in the joined row */
+    let mut joined = vec![Value::Null; join.offset];
+    data_filter_processor::perform_full_table_scan(
+        join.table,
+        builder,
+        false,
+        None,
+        &mut |_, inner| {
+            joined.truncate(join.offset);
+            joined.extend(inner);
+            if !local.map_or(Ok(true), |local| local.matches(&joined, encoding))? {
+                return Ok(ControlFlow::Continue(()));
+            }
+            let key = match hash_key(build, collations, &joined, encoding)? {
+                Some(key) => key,
+                None => return Ok(ControlFlow::Continue(())),
+            };
+            let inner = joined.split_off(join.offset);
+            bytes += row_size(&inner);
+            if bytes > memory_budget {
+                too_large = true;
+                return Ok(ControlFlow::Break(()));
+            }
+            buckets.entry(key).or_default().push(inner);
+            Ok(ControlFlow::Continue(()))
+        },
+    )?;
+    Ok(if too_large {
+        HashedRows::TooLarge
+    } else {
+        HashedRows::Built(buckets)
+    })
+}
+
+/* the values as = compares them, None when one is NULL and so equals nothing */
+fn hash_key(
+    exprs: &[(BoundExpr, Option<Affinity>)],
+    collations: &[Collation],
+    row: &[Value],
+    encoding: TextEncoding,
+) -> Result<Option<Vec<KeyPart>>> {
+    let mut key = Vec::with_capacity(exprs.len());
+    for ((expr, affinity), collation) in exprs.iter().zip(collations) {
+        let value = match affinity {
+            Some(affinity) => expr.eval(row, encoding)?.apply_affinity(*affinity),
+            None => expr.eval(row, encoding)?,
+        };
+        if value.is_null() {
+            return Ok(None);
+        }
+        key.push(KeyPart::new(&value, *collation));
+    }
+    Ok(Some(key))
+}
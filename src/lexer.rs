@@ -0,0 +1,310 @@
+use crate::error::{ReaderError, Result};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    /* a keyword or a bare identifier, which one is up to the parser */
+    Word(String),
+    /* "name", [name] or `name`, with doubled quotes already collapsed */
+    QuotedIdentifier(String),
+    String(String),
+    Number(String),
+    Blob(Vec<u8>),
+    LeftParen,
+    RightParen,
+    Comma,
+    Semicolon,
+    Dot,
+    Star,
+    Plus,
+    Minus,
+    Slash,
+    Percent,
+    Concat,
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+    BitAnd,
+    BitOr,
+    BitNot,
+    ShiftLeft,
+    ShiftRight,
+}
+
+/* a token along with the byte range of the sql it was read from */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lexeme {
+    pub token: Token,
+    pub start: usize,
+    pub end: usize,
+}
+
+/* 1-based column of a byte offset, counted in characters */
+pub fn column_at(sql: &str, offset: usize) -> usize {
+    sql[..offset.min(sql.len())].chars().count() + 1
+}
+
+pub fn tokenize(sql: &str) -> Result<Vec<Lexeme>> {
+    let mut lexer = Lexer { sql, offset: 0 };
+    let mut lexemes = Vec::new();
+    while let Some(lexeme) = lexer.next_lexeme()? {
+        lexemes.push(lexeme);
+    }
+    Ok(lexemes)
+}
+
+struct Lexer<'a> {
+    sql: &'a str,
+    offset: usize,
+}
+
+impl Lexer<'_> {
+    fn peek(&self) -> Option<char> {
+        self.sql[self.offset..].chars().next()
+    }
+
+    fn peek_second(&self) -> Option<char> {
+        self.sql[self.offset..].chars().nth(1)
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.offset += c.len_utf8();
+        Some(c)
+    }
+
+    fn error(&self, offset: usize, message: &str) -> ReaderError {
+        ReaderError::Parse(format!(
+            "{} at column {}",
+            message,
+            column_at(self.sql, offset)
+        ))
+    }
+
+    fn skip_whitespace_and_comments(&mut self) -> Result<()> {
+        loop {
+            match (self.peek(), self.peek_second()) {
+                (Some(c), _) if c.is_ascii_whitespace() => {
+                    self.bump();
+                }
+                (Some('-'), Some('-')) => while !matches!(self.bump(), Some('\n') | None) {},
+                (Some('/'), Some('*')) => {
+                    let start = self.offset;
+                    self.offset += 2;
+                    match self.sql[self.offset..].find("*/") {
+                        Some(end) => self.offset += end + 2,
+                        None => return Err(self.error(start, "unterminated comment")),
+                    }
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn next_lexeme(&mut self) -> Result<Option<Lexeme>> {
+        self.skip_whitespace_and_comments()?;
+        let start = self.offset;
+        let c = match self.bump() {
+            Some(c) => c,
+            None => return Ok(None),
+        };
+
+        let token = match c {
+            '(' => Token::LeftParen,
+            ')' => Token::RightParen,
+            ',' => Token::Comma,
+            ';' => Token::Semicolon,
+            '*' => Token::Star,
+            '+' => Token::Plus,
+            '-' => Token::Minus,
+            '/' => Token::Slash,
+            '%' => Token::Percent,
+            '&' => Token::BitAnd,
+            '~' => Token::BitNot,
+            '|' => match self.peek() {
+                Some('|') => {
+                    self.bump();
+                    Token::Concat
+                }
+                _ => Token::BitOr,
+            },
+            '=' => {
+                if self.peek() == Some('=') {
+                    self.bump();
+                }
+                Token::Eq
+            }
+            '!' => match self.bump() {
+                Some('=') => Token::NotEq,
+                _ => return Err(self.error(start, "unrecognized token \"!\"")),
+            },
+            '<' => match self.peek() {
+                Some('=') => {
+                    self.bump();
+                    Token::LtEq
+                }
+                Some('>') => {
+                    self.bump();
+                    Token::NotEq
+                }
+                Some('<') => {
+                    self.bump();
+                    Token::ShiftLeft
+                }
+                _ => Token::Lt,
+            },
+            '>' => match self.peek() {
+                Some('=') => {
+                    self.bump();
+                    Token::GtEq
+                }
+                Some('>') => {
+                    self.bump();
+                    Token::ShiftRight
+                }
+                _ => Token::Gt,
+            },
+            '\'' => Token::String(self.quoted(start, '\'')?),
+            '"' => Token::QuotedIdentifier(self.quoted(start, '"')?),
+            '`' => Token::QuotedIdentifier(self.quoted(start, '`')?),
+            '[' => match self.sql[self.offset..].find(']') {
+                Some(end) => {
+                    let name = self.sql[self.offset..self.offset + end].to_string();
+                    self.offset += end + 1;
+                    Token::QuotedIdentifier(name)
+                }
+                None => return Err(self.error(start, "unterminated identifier")),
+            },
+            'x' | 'X' if self.peek() == Some('\'') => {
+                self.bump();
+                Token::Blob(self.blob(start)?)
+            }
+            '.' if self.peek().is_some_and(|c| c.is_ascii_digit()) => self.number(start)?,
+            '.' => Token::Dot,
+            c if c.is_ascii_digit() => self.number(start)?,
+            c if is_identifier_start(c) => {
+                while self.peek().is_some_and(is_identifier_char) {
+                    self.bump();
+                }
+                Token::Word(self.sql[start..self.offset].to_string())
+            }
+            c => {
+                return Err(self.error(start, &format!("unrecognized token \"{}\"", c)));
+            }
+        };
+
+        Ok(Some(Lexeme {
+            token,
+            start,
+            end: self.offset,
+        }))
+    }
+
+    /* the body of a quoted string or identifier, where a doubled quote stands for itself */
+    fn quoted(&mut self, start: usize, quote: char) -> Result<String> {
+        let mut text = String::new();
+        loop {
+            match self.bump() {
+                Some(c) if c == quote => {
+                    if self.peek() == Some(quote) {
+                        self.bump();
+                        text.push(quote);
+                    } else {
+                        return Ok(text);
+                    }
+                }
+                Some(c) => text.push(c),
+                None => return Err(self.error(start, "unterminated quoted text")),
+            }
+        }
+    }
+
+    fn blob(&mut self, start: usize) -> Result<Vec<u8>> {
+        let hex = self.quoted(start, '\'')?;
+        if hex.len() % 2 != 0 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(self.error(start, "malformed blob literal"));
+        }
+        Ok((0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect())
+    }
+
+    /*
+        a number runs into the letters after it like sqlite's does, so 1e, 0x and 12abc are
+        unrecognized tokens rather than a number and an alias
+    */
+    fn number(&mut self, start: usize) -> Result<Token> {
+        let first = self.sql.as_bytes()[start];
+        if first == b'0'
+            && matches!(self.peek(), Some('x' | 'X'))
+            && self.sql[self.offset + 1..]
+                .chars()
+                .next()
+                .is_some_and(|c| c.is_ascii_hexdigit())
+        {
+            self.bump();
+            while self.peek().is_some_and(|c| c.is_ascii_hexdigit()) {
+                self.bump();
+            }
+            return self.end_of_number(start);
+        }
+
+        let mut seen_dot = first == b'.';
+        loop {
+            match self.peek() {
+                Some(c) if c.is_ascii_digit() => {
+                    self.bump();
+                }
+                Some('.') if !seen_dot => {
+                    seen_dot = true;
+                    self.bump();
+                }
+                Some('e' | 'E') => {
+                    let after = self.sql[self.offset + 1..].chars().next();
+                    let sign_then_digit = matches!(after, Some('+' | '-'))
+                        && self.sql[self.offset + 2..]
+                            .chars()
+                            .next()
+                            .is_some_and(|c| c.is_ascii_digit());
+                    if !(after.is_some_and(|c| c.is_ascii_digit()) || sign_then_digit) {
+                        break;
+                    }
+                    self.bump();
+                    if sign_then_digit {
+                        self.bump();
+                    }
+                    while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                        self.bump();
+                    }
+                    break;
+                }
+                _ => break,
+            }
+        }
+        self.end_of_number(start)
+    }
+
+    fn end_of_number(&mut self, start: usize) -> Result<Token> {
+        if !self.peek().is_some_and(is_identifier_char) {
+            return Ok(Token::Number(self.sql[start..self.offset].to_string()));
+        }
+        while self.peek().is_some_and(is_identifier_char) {
+            self.bump();
+        }
+        Err(self.error(
+            start,
+            &format!("unrecognized token \"{}\"", &self.sql[start..self.offset]),
+        ))
+    }
+}
+
+fn is_identifier_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_' || !c.is_ascii()
+}
+
+fn is_identifier_char(c: char) -> bool {
+    is_identifier_start(c) || c.is_ascii_digit() || c == '$'
+}
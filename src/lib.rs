@@ -1,10 +1,30 @@
 extern crate core;
 
+pub mod aggregate;
+pub mod ast;
+pub mod bound_expr;
+pub mod cursor;
+pub mod db_header;
+pub mod distinct;
+pub mod error;
 pub mod file_reader;
+pub mod join;
+pub mod lexer;
 pub mod page;
+pub mod page_cache;
 pub mod page_reader;
+pub mod page_source;
 pub mod page_type;
 pub mod parser;
+pub mod pattern;
+pub mod printf;
+pub mod query;
+pub mod scalar;
+pub mod schema;
+pub mod sorter;
+pub mod spill;
+pub mod value;
 pub mod varint;
+pub mod wal;
 
 pub mod data_filter_processor;
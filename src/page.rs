@@ -1,12 +1,13 @@
 use crate::{
     file_reader::BytesIterator,
     page_type::{get_page_type, PageType},
+    value::Value,
 };
+use bytes::Bytes;
 use std::fmt::Display;
 
 use core::any::Any;
 use std::fmt::{Debug, Formatter};
-use std::ops::Deref;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum SerialType {
@@ -17,19 +18,32 @@ pub enum SerialType {
     FLOAT64(u64),
     BLOB(u64),
     TEXT(u64),
-    RESERVED,
 }
 
 #[derive(Debug, Clone)]
 pub struct RecordHeader {
-    pub header_size: u8,
+    /* the bytes of serial types after the header size varint, more than 255 for wide rows */
+    pub header_size: usize,
     pub serial_types: Box<[SerialType]>,
 }
 
 #[derive(Debug, Clone)]
 pub struct Record {
     pub record_header: RecordHeader,
-    pub rows: Vec<String>,
+    pub rows: Vec<Value>,
+}
+
+/*
+    a cell's payload as the page holds it: the bytes stored on the page and the first page of
+    the overflow chain holding the rest. cursors decode it into a Record when they read the cell
+*/
+#[derive(Debug, Clone)]
+pub struct Payload {
+    /* the b-tree page the cell is on */
+    pub page_no: u32,
+    pub size: u64,
+    pub local: Bytes,
+    pub overflow_page_no: Option<u32>,
 }
 
 pub trait CellClone {
@@ -39,7 +53,7 @@ pub trait CellClone {
 pub trait Cell: CellClone + Display + Debug + 'static {
     fn as_any(&self) -> &dyn Any; /* to help with downcast */
 
-    fn record(&self) -> Option<Record> {
+    fn payload(&self) -> Option<&Payload> {
         None
     }
 
@@ -60,9 +74,8 @@ impl<T: Cell + Clone> CellClone for T {
 
 #[derive(Debug, Clone)]
 pub struct TableLeafCell {
-    pub record_size: u64,
     pub row_id: i64,
-    pub record: Record,
+    pub payload: Payload,
 }
 
 #[derive(Debug, Clone)]
@@ -73,23 +86,21 @@ pub struct TableIntCell {
 
 #[derive(Debug, Clone)]
 pub struct IdxLeafCell {
-    pub record_size: u64,
-    pub record: Record,
+    pub payload: Payload,
 }
 
 #[derive(Debug, Clone)]
 pub struct IdxIntCell {
     pub left_child_page_no: u32,
-    pub record_size: u64,
-    pub record: Record,
+    pub payload: Payload,
 }
 
 impl Display for TableLeafCell {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "TableLeafCell {{ record_size: {}, row_id: {}, record: {:?} }}",
-            self.record_size, self.row_id, self.record
+            "TableLeafCell {{ record_size: {}, row_id: {} }}",
+            self.payload.size, self.row_id
         )
     }
 }
@@ -99,8 +110,8 @@ impl Cell for TableLeafCell {
         self
     }
 
-    fn record(&self) -> Option<Record> {
-        Some(self.record.clone())
+    fn payload(&self) -> Option<&Payload> {
+        Some(&self.payload)
     }
 
     fn row_id(&self) -> Option<i64> {
@@ -134,11 +145,7 @@ impl Cell for TableIntCell {
 
 impl Display for IdxLeafCell {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "IdxLeafCell {{ record_size: {}, record: {:?} }}",
-            self.record_size, self.record
-        )
+        write!(f, "IdxLeafCell {{ record_size: {} }}", self.payload.size)
     }
 }
 
@@ -147,8 +154,8 @@ impl Cell for IdxLeafCell {
         self
     }
 
-    fn record(&self) -> Option<Record> {
-        Some(self.record.clone())
+    fn payload(&self) -> Option<&Payload> {
+        Some(&self.payload)
     }
 }
 
@@ -156,8 +163,8 @@ impl Display for IdxIntCell {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "IdxIntCell {{ left_child_page_no: {}, record_size: {}, record: {:?} }}",
-            self.left_child_page_no, self.record_size, self.record
+            "IdxIntCell {{ left_child_page_no: {}, record_size: {} }}",
+            self.left_child_page_no, self.payload.size
         )
     }
 }
@@ -167,8 +174,8 @@ impl Cell for IdxIntCell {
         self
     }
 
-    fn record(&self) -> Option<Record> {
-        Some(self.record.clone())
+    fn payload(&self) -> Option<&Payload> {
+        Some(&self.payload)
     }
 
     fn left_child_page_no(&self) -> Option<u32> {
@@ -177,8 +184,8 @@ impl Cell for IdxIntCell {
 }
 
 #[inline]
-pub fn downcast<T: Any + Cell>(x: &Box<dyn Cell>) -> Option<&T> {
-    x.deref().as_any().downcast_ref::<T>()
+pub fn downcast<T: Any + Cell>(x: &dyn Cell) -> Option<&T> {
+    x.as_any().downcast_ref::<T>()
 }
 #[derive(Debug, Clone)]
 pub struct PageHeader {
@@ -229,19 +236,19 @@ impl Display for Page {
     }
 }
 
-pub fn get_page_metadata(bytes_iterator: &mut BytesIterator) -> PageMetaData {
-    let byte = bytes_iterator.next().unwrap();
+pub fn get_page_metadata(bytes_iterator: &mut BytesIterator) -> Option<PageMetaData> {
+    let byte = bytes_iterator.next()?;
     let page_type = get_page_type(&byte);
     if page_type == PageType::IdxInt || page_type == PageType::TblInt {
-        PageMetaData {
+        Some(PageMetaData {
             page_type,
             page_header_size: 12,
-        }
+        })
     } else {
-        PageMetaData {
+        Some(PageMetaData {
             page_type,
             page_header_size: 8,
-        }
+        })
     }
 }
 
@@ -254,12 +261,5 @@ pub fn get_read_size(serial_type: &SerialType) -> u64 {
         SerialType::FLOAT64(size) => *size,
         SerialType::BLOB(size) => *size,
         SerialType::TEXT(size) => *size,
-        _ => panic!("invalid serial type info {:?}", serial_type),
     }
 }
-
-pub enum SearchResult {
-    ThisPage(Box<dyn Cell>, u32),
-    LeftPage(Box<dyn Cell>),
-    RightPage,
-}
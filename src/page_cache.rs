@@ -0,0 +1,255 @@
+use crate::page::Page;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Display, Formatter};
+use std::rc::Rc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheLimit {
+    Pages(usize),
+    Bytes(usize),
+}
+
+impl Default for CacheLimit {
+    fn default() -> Self {
+        CacheLimit::Pages(2000)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    pub pages: usize,
+    pub bytes: usize,
+}
+
+impl Display for CacheStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let lookups = self.hits + self.misses;
+        let hit_rate = if lookups == 0 {
+            0.0
+        } else {
+            self.hits as f64 * 100.0 / lookups as f64
+        };
+        write!(
+            f,
+            "page cache: {} hits, {} misses ({:.1}% hit rate), {} evictions, {} pages / {} bytes held",
+            self.hits, self.misses, hit_rate, self.evictions, self.pages, self.bytes
+        )
+    }
+}
+
+struct CacheEntry {
+    page: Rc<Page>,
+    size: usize,
+    last_used: u64,
+}
+
+/* parsed pages keyed by page number, evicting the least recently used once over the limit */
+pub struct PageCache {
+    limit: CacheLimit,
+    entries: HashMap<u32, CacheEntry>,
+    recency: BTreeMap<u64, u32>,
+    tick: u64,
+    stats: CacheStats,
+}
+
+impl PageCache {
+    pub fn new(limit: CacheLimit) -> Self {
+        Self {
+            limit,
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            tick: 0,
+            stats: CacheStats::default(),
+        }
+    }
+
+    pub fn get(&mut self, page_number: u32) -> Option<Rc<Page>> {
+        self.tick += 1;
+        match self.entries.get_mut(&page_number) {
+            Some(entry) => {
+                self.recency.remove(&entry.last_used);
+                self.recency.insert(self.tick, page_number);
+                entry.last_used = self.tick;
+                self.stats.hits += 1;
+                Some(entry.page.clone())
+            }
+            None => {
+                self.stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    /* looks a page up without counting it as a use or a lookup */
+    pub fn peek(&self, page_number: u32) -> Option<&Rc<Page>> {
+        self.entries.get(&page_number).map(|entry| &entry.page)
+    }
+
+    pub fn insert(&mut self, page_number: u32, page: Rc<Page>, size: usize) {
+        if !self.fits(size) {
+            return;
+        }
+        self.remove(page_number);
+
+        self.tick += 1;
+        self.recency.insert(self.tick, page_number);
+        self.entries.insert(
+            page_number,
+            CacheEntry {
+                page,
+                size,
+                last_used: self.tick,
+            },
+        );
+        self.stats.pages += 1;
+        self.stats.bytes += size;
+
+        while self.over_limit() {
+            let (_, evicted) = self.recency.pop_first().unwrap();
+            let entry = self.entries.remove(&evicted).unwrap();
+            self.stats.pages -= 1;
+            self.stats.bytes -= entry.size;
+            self.stats.evictions += 1;
+        }
+    }
+
+    pub fn set_limit(&mut self, limit: CacheLimit) {
+        self.limit = limit;
+        while self.over_limit() {
+            let (_, evicted) = self.recency.pop_first().unwrap();
+            self.remove(evicted);
+            self.stats.evictions += 1;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.recency.clear();
+        self.stats.pages = 0;
+        self.stats.bytes = 0;
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    fn remove(&mut self, page_number: u32) {
+        if let Some(entry) = self.entries.remove(&page_number) {
+            self.recency.remove(&entry.last_used);
+            self.stats.pages -= 1;
+            self.stats.bytes -= entry.size;
+        }
+    }
+
+    /* a page bigger than the whole budget would only flush everything else out */
+    fn fits(&self, size: usize) -> bool {
+        match self.limit {
+            CacheLimit::Pages(pages) => pages > 0,
+            CacheLimit::Bytes(bytes) => size <= bytes,
+        }
+    }
+
+    fn over_limit(&self) -> bool {
+        match self.limit {
+            CacheLimit::Pages(pages) => self.stats.pages > pages,
+            CacheLimit::Bytes(bytes) => self.stats.bytes > bytes,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::page::PageHeader;
+    use crate::page_type::PageType;
+
+    fn page() -> Rc<Page> {
+        Rc::new(Page {
+            page_header: PageHeader {
+                page_type: PageType::TblLeaf,
+                first_free_block: 0,
+                cell_count: 0,
+                cell_content_offset: 0,
+                fragmented_bytes: 0,
+                right_pointer: None,
+            },
+            cells: Box::new([]),
+        })
+    }
+
+    fn cached(cache: &PageCache) -> Vec<u32> {
+        let mut pages: Vec<u32> = cache.entries.keys().copied().collect();
+        pages.sort();
+        pages
+    }
+
+    #[test]
+    fn evicts_the_least_recently_used_page() {
+        let mut cache = PageCache::new(CacheLimit::Pages(3));
+        for page_number in 1..=3 {
+            cache.insert(page_number, page(), 4096);
+        }
+        /* using 1 leaves 2 as the oldest */
+        assert!(cache.get(1).is_some());
+        cache.insert(4, page(), 4096);
+        assert_eq!(cached(&cache), [1, 3, 4]);
+
+        /* peeking isn't a use */
+        assert!(cache.peek(3).is_some());
+        cache.insert(5, page(), 4096);
+        assert_eq!(cached(&cache), [1, 4, 5]);
+        assert_eq!(cache.stats().evictions, 2);
+
+        cache.set_limit(CacheLimit::Pages(1));
+        assert_eq!(cached(&cache), [5]);
+        assert_eq!(cache.stats().evictions, 4);
+    }
+
+    #[test]
+    fn byte_limit_counts_each_page_size() {
+        let mut cache = PageCache::new(CacheLimit::Bytes(10_000));
+        cache.insert(1, page(), 4096);
+        cache.insert(2, page(), 4096);
+        assert_eq!((cache.stats().pages, cache.stats().bytes), (2, 8192));
+
+        cache.insert(3, page(), 4096);
+        assert_eq!(cached(&cache), [2, 3]);
+        assert_eq!((cache.stats().pages, cache.stats().bytes), (2, 8192));
+
+        /* a page inserted again replaces its old size */
+        cache.insert(2, page(), 1000);
+        assert_eq!((cache.stats().pages, cache.stats().bytes), (2, 5096));
+
+        /* a page over the whole budget isn't cached, and doesn't flush the others */
+        cache.insert(4, page(), 20_000);
+        assert_eq!(cached(&cache), [2, 3]);
+
+        cache.set_limit(CacheLimit::Bytes(4096));
+        assert_eq!(cached(&cache), [2]);
+        assert_eq!((cache.stats().pages, cache.stats().bytes), (1, 1000));
+
+        cache.clear();
+        assert_eq!((cache.stats().pages, cache.stats().bytes), (0, 0));
+    }
+
+    #[test]
+    fn counts_hits_and_misses() {
+        let mut cache = PageCache::new(CacheLimit::default());
+        assert!(cache.get(1).is_none());
+        cache.insert(1, page(), 4096);
+        assert!(cache.get(1).is_some());
+        assert!(cache.get(1).is_some());
+        assert!(cache.get(2).is_none());
+        assert!(cache.peek(1).is_some());
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.evictions), (2, 2, 0));
+        assert_eq!(
+            stats.to_string(),
+            "page cache: 2 hits, 2 misses (50.0% hit rate), 0 evictions, 1 pages / 4096 bytes held"
+        );
+    }
+}
@@ -1,287 +1,687 @@
+use crate::db_header::{self, DatabaseHeader, TextEncoding};
+use crate::error::{ReaderError, Result};
 use crate::file_reader::{BytesIterator, FileReader};
-use crate::page::SerialType::RESERVED;
 use crate::page::{
-    get_read_size, Cell, IdxIntCell, IdxLeafCell, Page, PageHeader, PageMetaData, Record,
+    get_read_size, Cell, IdxIntCell, IdxLeafCell, Page, PageHeader, PageMetaData, Payload, Record,
     RecordHeader, SerialType, TableIntCell, TableLeafCell,
 };
+use crate::page_cache::{CacheLimit, CacheStats, PageCache};
+use crate::page_source::{MmapSource, PageSource};
 use crate::page_type::PageType;
+use crate::value::Value;
+use crate::wal::Wal;
 use crate::{page, varint};
-pub struct PageReader {
+use bytes::Bytes;
+use std::cmp::min;
+use std::collections::HashSet;
+use std::io;
+use std::rc::Rc;
+
+pub struct PageReader<'a, S: PageSource = FileReader> {
+    builder: &'a mut PageReaderBuilder<S>,
+    page_number: u32,
     bytes_iterator: BytesIterator,
     pub page_meta_data: PageMetaData,
+    cell_content_start: usize,
 }
 
-impl PageReader {
-    pub fn new(file_reader: &mut FileReader, page_number: u32, page_size: u16) -> Self {
-        let (page_start_offset, size) = (
-            page_size as u64 * (page_number as u64 - 1),
-            page_size as usize,
-        );
-
-        let mut bytes_iterator = file_reader
-            .read_bytes_from(page_start_offset, size)
-            .unwrap();
+impl<'a, S: PageSource> PageReader<'a, S> {
+    pub fn new(builder: &'a mut PageReaderBuilder<S>, page_number: u32) -> Result<Self> {
+        let mut bytes_iterator = builder.read_page_bytes(page_number)?;
         if page_number == 1 {
             bytes_iterator.jump_to(100_usize);
         }
-        let page_meta_data = page::get_page_metadata(&mut bytes_iterator);
-        PageReader {
+        let page_meta_data = page::get_page_metadata(&mut bytes_iterator).ok_or_else(|| {
+            ReaderError::corrupt(page_number, bytes_iterator.offset(), "empty page")
+        })?;
+        Ok(PageReader {
+            builder,
+            page_number,
             bytes_iterator,
             page_meta_data,
-        }
+            cell_content_start: 0,
+        })
     }
 
-    pub fn read_page(&mut self) -> Page {
+    pub fn read_page_header(&mut self) -> Result<PageHeader> {
         let page_header_size = self.page_meta_data.page_header_size;
         let page_type = self.page_meta_data.page_type;
+        self.get_page_header(page_header_size, &page_type)
+    }
 
-        let page_header = self.get_page_header(page_header_size, &page_type);
+    pub fn read_page(&mut self) -> Result<Page> {
+        let page_type = self.page_meta_data.page_type;
+        let page_header = self.read_page_header()?;
 
         let cells: Box<[Box<dyn Cell>]> = match page_type {
-            PageType::TblLeaf => self.read_table_leaf_cells(page_header.cell_count).unwrap(),
-            PageType::TblInt => self.read_table_int_cell(page_header.cell_count).unwrap(),
-            PageType::IdxLeaf => self.read_index_leaf_cells(page_header.cell_count).unwrap(),
-            PageType::IdxInt => self.read_index_int_cell(page_header.cell_count).unwrap(),
-            PageType::Invalid => panic!("Invalid page type"),
+            PageType::TblLeaf => self.read_table_leaf_cells(page_header.cell_count)?,
+            PageType::TblInt => self.read_table_int_cell(page_header.cell_count)?,
+            PageType::IdxLeaf => self.read_index_leaf_cells(page_header.cell_count)?,
+            PageType::IdxInt => self.read_index_int_cell(page_header.cell_count)?,
+            PageType::Invalid => unreachable!("rejected by get_page_header"),
         };
 
-        Page { page_header, cells }
+        Ok(Page { page_header, cells })
     }
 
-    fn get_page_header(&mut self, page_header_size: usize, page_type: &PageType) -> PageHeader {
+    fn get_page_header(
+        &mut self,
+        page_header_size: usize,
+        page_type: &PageType,
+    ) -> Result<PageHeader> {
+        if page_type == &PageType::Invalid {
+            return Err(self.corrupt("not a b-tree page"));
+        }
         // the first bit was already read in get_page_metadata to determine a page type
-        let page_header = self.bytes_iterator.next_n(page_header_size - 1).unwrap();
+        let page_header = self.next_n(page_header_size - 1)?;
         let right_pointer = if page_type == &PageType::TblInt || page_type == &PageType::IdxInt {
             Option::from(u32::from_be_bytes(page_header[7..11].try_into().unwrap()))
         } else {
             None
         };
 
-        PageHeader {
+        let page_header = PageHeader {
             page_type: *page_type,
             first_free_block: u16::from_be_bytes([page_header[0], page_header[1]]),
             cell_count: u16::from_be_bytes([page_header[2], page_header[3]]),
             cell_content_offset: u16::from_be_bytes([page_header[4], page_header[5]]),
             fragmented_bytes: u8::from_be_bytes([page_header[6]]),
             right_pointer,
+        };
+        self.validate_page_header(&page_header)?;
+        Ok(page_header)
+    }
+
+    /* the cell pointer array, free blocks and cell content must all fit in the usable part of the page */
+    fn validate_page_header(&mut self, page_header: &PageHeader) -> Result<()> {
+        let usable_size = self.builder.usable_size as usize;
+        let pointer_array_end = self.bytes_iterator.offset() + page_header.cell_count as usize * 2;
+        if pointer_array_end > usable_size {
+            return Err(self.corrupt(format!(
+                "{} cells don't fit in the page",
+                page_header.cell_count
+            )));
+        }
+
+        /* a content area starting at 65536 is stored as 0 */
+        let cell_content_start = match page_header.cell_content_offset {
+            0 => 65536,
+            offset => offset as usize,
+        };
+        if cell_content_start < pointer_array_end || cell_content_start > usable_size {
+            return Err(self.corrupt(format!(
+                "cell content area starts at {}",
+                cell_content_start
+            )));
+        }
+
+        let first_free_block = page_header.first_free_block as usize;
+        if first_free_block != 0
+            && (first_free_block < cell_content_start || first_free_block + 4 > usable_size)
+        {
+            return Err(self.corrupt(format!(
+                "first free block at {} is outside the cell content area",
+                first_free_block
+            )));
+        }
+        if page_header.fragmented_bytes > 60 {
+            return Err(self.corrupt(format!("{} fragmented bytes", page_header.fragmented_bytes)));
         }
+        if page_header.right_pointer == Some(0) {
+            return Err(self.corrupt("interior page has no right child"));
+        }
+
+        self.cell_content_start = cell_content_start;
+        Ok(())
     }
 
-    fn read_table_int_cell(&mut self, cell_count: u16) -> Option<Box<[Box<dyn Cell>]>> {
-        let mut cell_offsets_iterator = self.new_cell_iterator(cell_count);
+    fn read_table_int_cell(&mut self, cell_count: u16) -> Result<Box<[Box<dyn Cell>]>> {
+        let mut cell_offsets_iterator = self.new_cell_iterator(cell_count)?;
 
         let mut cells: Vec<Box<dyn Cell>> = Vec::new();
         while cell_offsets_iterator.has_next() {
-            let cell_offset = self.read_cell_offset(&mut cell_offsets_iterator);
-            let left_child_page_no = self.read_left_child_page_no(&cell_offset);
-            let (row_id, _) = varint::decode(&mut self.bytes_iterator);
+            let cell_offset = self.read_cell_offset(&mut cell_offsets_iterator)?;
+            let left_child_page_no = self.read_left_child_page_no(&cell_offset)?;
+            let (row_id, _) = self.read_varint()?;
             cells.push(Box::new(TableIntCell {
                 row_id: row_id as i64,
                 left_child_page_no,
             }));
         }
 
-        Some(cells.into())
+        Ok(cells.into())
     }
 
-    fn read_table_leaf_cells(&mut self, cell_count: u16) -> Option<Box<[Box<dyn Cell>]>> {
-        let mut cell_offsets_iterator = self.new_cell_iterator(cell_count);
+    fn read_table_leaf_cells(&mut self, cell_count: u16) -> Result<Box<[Box<dyn Cell>]>> {
+        let mut cell_offsets_iterator = self.new_cell_iterator(cell_count)?;
         let mut cells: Vec<Box<dyn Cell>> = Vec::new();
         while cell_offsets_iterator.has_next() {
-            let cell_offset = self.read_cell_offset(&mut cell_offsets_iterator);
-            let (record_size, _) = varint::decode(self.bytes_iterator.jump_to(cell_offset));
-            let (row_id, _) = varint::decode(&mut self.bytes_iterator);
-            let record = self.read_record(true).unwrap();
+            let cell_offset = self.read_cell_offset(&mut cell_offsets_iterator)?;
+            self.bytes_iterator.jump_to(cell_offset);
+            let (record_size, _) = self.read_varint()?;
+            let (row_id, _) = self.read_varint()?;
+            let payload = self.read_payload(record_size, &PageType::TblLeaf)?;
 
             cells.push(Box::new(TableLeafCell {
-                record_size,
                 row_id: row_id as i64,
-                record,
+                payload,
             }));
         }
-        Some(cells.into())
+        Ok(cells.into())
     }
 
-    fn read_index_int_cell(&mut self, cell_count: u16) -> Option<Box<[Box<dyn Cell>]>> {
-        let mut cell_offsets_iterator = self.new_cell_iterator(cell_count);
+    fn read_index_int_cell(&mut self, cell_count: u16) -> Result<Box<[Box<dyn Cell>]>> {
+        let mut cell_offsets_iterator = self.new_cell_iterator(cell_count)?;
         let mut cells: Vec<Box<dyn Cell>> = Vec::new();
         while cell_offsets_iterator.has_next() {
-            let cell_offset = self.read_cell_offset(&mut cell_offsets_iterator);
-            let left_child_page_no = self.read_left_child_page_no(&cell_offset);
-            let (record_size, _) = varint::decode(&mut self.bytes_iterator);
-            let record = self.read_record(false);
-            if record.is_none() {
-                continue;
-            }
-            let record = record.unwrap();
+            let cell_offset = self.read_cell_offset(&mut cell_offsets_iterator)?;
+            let left_child_page_no = self.read_left_child_page_no(&cell_offset)?;
+            let (record_size, _) = self.read_varint()?;
+            let payload = self.read_payload(record_size, &PageType::IdxInt)?;
 
             cells.push(Box::new(IdxIntCell {
-                record_size,
                 left_child_page_no,
-                record,
+                payload,
             }))
         }
-        Some(cells.into())
+        Ok(cells.into())
     }
 
-    fn read_index_leaf_cells(&mut self, cell_count: u16) -> Option<Box<[Box<dyn Cell>]>> {
-        let mut cell_offsets_iterator = self.new_cell_iterator(cell_count);
+    fn read_index_leaf_cells(&mut self, cell_count: u16) -> Result<Box<[Box<dyn Cell>]>> {
+        let mut cell_offsets_iterator = self.new_cell_iterator(cell_count)?;
         let mut cells: Vec<Box<dyn Cell>> = Vec::new();
         while cell_offsets_iterator.has_next() {
-            let cell_offset = self.read_cell_offset(&mut cell_offsets_iterator);
-            let (record_size, _) = varint::decode(self.bytes_iterator.jump_to(cell_offset));
+            let cell_offset = self.read_cell_offset(&mut cell_offsets_iterator)?;
+            self.bytes_iterator.jump_to(cell_offset);
+            let (record_size, _) = self.read_varint()?;
+            let payload = self.read_payload(record_size, &PageType::IdxLeaf)?;
 
-            let record = self.read_record(true).unwrap();
+            cells.push(Box::new(IdxLeafCell { payload }));
+        }
+        Ok(cells.into())
+    }
 
-            cells.push(Box::new(IdxLeafCell {
-                record_size,
-                record,
-            }));
+    /* cells live in the content area, between its start and the reserved bytes at the end */
+    fn read_cell_offset(&self, cell_offsets_iterator: &mut BytesIterator) -> Result<usize> {
+        let cell_offset = cell_offsets_iterator
+            .next_n(2)
+            .ok_or_else(|| self.corrupt("cell pointer array is truncated"))?;
+        let cell_offset: usize = u16::from_be_bytes(cell_offset[0..2].try_into().unwrap()).into();
+        if cell_offset < self.cell_content_start || cell_offset >= self.builder.usable_size as usize
+        {
+            return Err(self.corrupt(format!(
+                "cell offset {} is outside the cell content area",
+                cell_offset
+            )));
         }
-        Some(cells.into())
+        Ok(cell_offset)
+    }
+
+    fn read_left_child_page_no(&mut self, cell_offset: &usize) -> Result<u32> {
+        self.bytes_iterator.jump_to(*cell_offset);
+        let bytes = self.next_n(4)?;
+        Ok(u32::from_be_bytes(bytes[0..=3].try_into().unwrap()))
+    }
+
+    /* the part of the payload on this page, the overflow chain is only followed when it's decoded */
+    fn read_payload(&mut self, payload_size: u64, page_type: &PageType) -> Result<Payload> {
+        let local_size = self.builder.local_payload_size(payload_size, page_type);
+        let local = self.next_n(local_size as usize)?;
+        let overflow_page_no = if local_size < payload_size {
+            let overflow_page_no = self.next_n(4)?;
+            Some(u32::from_be_bytes(
+                overflow_page_no[0..4].try_into().unwrap(),
+            ))
+        } else {
+            None
+        };
+        Ok(Payload {
+            page_no: self.page_number,
+            size: payload_size,
+            local,
+            overflow_page_no,
+        })
     }
 
-    fn read_cell_offset(&mut self, cell_offsets_iterator: &mut BytesIterator) -> usize {
-        let cell_offset = cell_offsets_iterator.next_n(2).unwrap();
-        u16::from_be_bytes(cell_offset[0..2].try_into().unwrap()).into()
+    fn new_cell_iterator(&mut self, cell_count: u16) -> Result<BytesIterator> {
+        self.bytes_iterator
+            .next_n_as_iter(cell_count as usize * 2_usize)
+            .ok_or_else(|| self.corrupt("cell pointer array runs past the page"))
+    }
+
+    fn read_varint(&mut self) -> Result<(u64, u64)> {
+        varint::decode(&mut self.bytes_iterator)
+            .ok_or_else(|| self.corrupt("varint runs past the end of the page"))
     }
 
-    fn read_left_child_page_no(&mut self, cell_offset: &usize) -> u32 {
-        let bytes = self.bytes_iterator.jump_to(*cell_offset).next_n(4).unwrap();
-        u32::from_be_bytes(bytes[0..=3].try_into().unwrap())
+    fn next_n(&mut self, n: usize) -> Result<Bytes> {
+        self.bytes_iterator
+            .next_n(n)
+            .ok_or_else(|| self.corrupt(format!("{} bytes read past the end of the page", n)))
+    }
+
+    fn corrupt(&self, reason: impl Into<String>) -> ReaderError {
+        ReaderError::corrupt(self.page_number, self.bytes_iterator.offset(), reason)
     }
+}
 
-    fn read_record(&mut self, null_allowed: bool) -> Option<Record> {
-        let (mut record_header_size, bytes_read) = varint::decode(&mut self.bytes_iterator);
+pub struct PageReaderBuilder<S: PageSource = FileReader> {
+    source: S,
+    header: DatabaseHeader,
+    page_size: u32,
+    usable_size: u32,
+    wal: Option<Wal>,
+    cache: PageCache,
+}
 
-        record_header_size -= bytes_read;
+impl PageReaderBuilder<FileReader> {
+    /* opens the database along with its -wal file when one is present */
+    pub fn open(path: &String) -> Result<Self> {
+        let mut builder = Self::from_source(FileReader::new(path)?)?;
+        builder.attach_wal(path)?;
+        Ok(builder)
+    }
+}
 
-        let mut serial_types = Vec::new();
-        let record_header_size_copy = record_header_size;
-        let mut record_body_size: u64 = 0;
-        while record_header_size > 0 {
-            let (val, bytes_read) = varint::decode(&mut self.bytes_iterator);
-            let serial_type: SerialType = self.get_column_serial_type_info(val);
-            let size = get_read_size(&serial_type);
-            record_body_size += size;
-            serial_types.push(serial_type);
+impl PageReaderBuilder<MmapSource> {
+    /* for databases no one writes to while they're read, see MmapSource */
+    pub fn open_mmap(path: &String) -> Result<Self> {
+        let mut builder = Self::from_source(MmapSource::open(path)?)?;
+        builder.attach_wal(path)?;
+        Ok(builder)
+    }
+}
 
-            record_header_size -= bytes_read;
+impl<S: PageSource> PageReaderBuilder<S> {
+    pub fn new(source: S, header: DatabaseHeader) -> Self {
+        Self {
+            source,
+            page_size: header.page_size,
+            usable_size: header.usable_size(),
+            header,
+            wal: None,
+            cache: PageCache::new(CacheLimit::default()),
         }
+    }
 
-        let record_header = RecordHeader {
-            header_size: record_header_size_copy as u8,
-            serial_types: serial_types.into_boxed_slice(),
-        };
+    pub fn from_source(mut source: S) -> Result<Self> {
+        let mut header_reader = source.read_bytes_from(0, db_header::HEADER_SIZE)?;
+        let header = header_reader
+            .next_n(db_header::HEADER_SIZE)
+            .ok_or_else(|| ReaderError::corrupt(1, 0, "database header is truncated"))?;
+        Ok(Self::new(source, DatabaseHeader::parse(&header)?))
+    }
 
-        let mut rows: Vec<String> = Vec::new();
-        let mut record_body_iterator = self
-            .bytes_iterator
-            .next_n_as_iter(record_body_size as usize)
-            .unwrap();
+    /* overlays the committed frames of <path>-wal, if that file exists */
+    pub fn attach_wal(&mut self, path: &String) -> Result<()> {
+        let wal_path = format!("{}-wal", path);
+        self.wal = Wal::open(&wal_path, self.page_size)?;
+        if self.wal.as_ref().is_some_and(|wal| wal.contains(1)) {
+            /* page 1 was rewritten after the last checkpoint, so its header is the current one */
+            let mut page_one = self.read_page_bytes(1)?;
+            let header = page_one
+                .next_n(db_header::HEADER_SIZE)
+                .ok_or_else(|| ReaderError::corrupt(1, 0, "database header is truncated"))?;
+            self.header = DatabaseHeader::parse(&header)?;
+        }
+        Ok(())
+    }
 
-        for serial_type in record_header.serial_types.iter() {
-            let read_size = get_read_size(serial_type);
-            if !null_allowed && serial_type == &SerialType::NULL {
-                return None;
-            }
+    pub fn wal(&self) -> Option<&Wal> {
+        self.wal.as_ref()
+    }
+
+    pub fn header(&self) -> &DatabaseHeader {
+        &self.header
+    }
+
+    pub fn with_cache_limit(mut self, limit: CacheLimit) -> Self {
+        self.cache.set_limit(limit);
+        self
+    }
+
+    pub fn cache_stats(&self) -> CacheStats {
+        self.cache.stats()
+    }
+
+    pub fn new_reader(&mut self, page_number: u32) -> Result<PageReader<'_, S>> {
+        PageReader::new(self, page_number)
+    }
+
+    /* header only, so callers that just need the page type or cell count skip decoding cells */
+    pub fn fetch_page_header(&mut self, page_number: u32) -> Result<PageHeader> {
+        if let Some(page) = self.cache.peek(page_number) {
+            return Ok(page.page_header.clone());
+        }
+        self.new_reader(page_number)?.read_page_header()
+    }
 
-            if read_size == 0 {
-                rows.push(String::new());
-                continue;
+    /* parsed page, served from the page cache when it was read recently */
+    pub fn fetch_page(&mut self, page_number: u32) -> Result<Rc<Page>> {
+        if let Some(page) = self.cache.get(page_number) {
+            return Ok(page);
+        }
+
+        let page_size = self.page_size as usize;
+        let page = Rc::new(self.new_reader(page_number)?.read_page()?);
+        self.cache.insert(page_number, page.clone(), page_size);
+        Ok(page)
+    }
+
+    /* decodes a cell's record, reading the rest of it from the overflow chain when it spills */
+    pub fn read_record(&mut self, payload: &Payload) -> Result<Record> {
+        let mut bytes = self.read_overflow(payload)?;
+        read_record(&mut bytes, payload.page_no, self.header.text_encoding)
+    }
+
+    fn read_overflow(&mut self, payload: &Payload) -> Result<BytesIterator> {
+        let Some(mut overflow_page_no) = payload.overflow_page_no else {
+            return Ok(BytesIterator::new(payload.local.clone()));
+        };
+        let payload_size = payload.size;
+        let mut bytes = payload.local.to_vec();
+        let overflow_content_size = self.usable_size as u64 - 4;
+        let mut visited = HashSet::new();
+
+        while overflow_page_no != 0 && (bytes.len() as u64) < payload_size {
+            if !visited.insert(overflow_page_no) {
+                return Err(ReaderError::corrupt(
+                    overflow_page_no,
+                    0,
+                    "overflow chain loops back on itself",
+                ));
             }
+            let mut overflow_page = self.read_page_bytes(overflow_page_no)?;
+            let read_size = min(payload_size - bytes.len() as u64, overflow_content_size);
+            let (next_page_no, content) = overflow_page
+                .next_n(4)
+                .zip(overflow_page.next_n(read_size as usize))
+                .ok_or_else(|| {
+                    ReaderError::corrupt(overflow_page_no, 0, "overflow page is truncated")
+                })?;
+            bytes.extend_from_slice(&content);
+            overflow_page_no = u32::from_be_bytes(next_page_no[0..4].try_into().unwrap());
+        }
 
-            rows.push(decode(
-                serial_type,
-                &record_body_iterator.next_n(read_size as usize).unwrap(),
+        if (bytes.len() as u64) < payload_size {
+            return Err(ReaderError::corrupt(
+                payload.page_no,
+                0,
+                "overflow chain ends before the payload does",
             ));
         }
+        Ok(BytesIterator::new(bytes))
+    }
 
-        Some(Record {
-            record_header,
-            rows,
-        })
+    pub fn read_page_bytes(&mut self, page_number: u32) -> Result<BytesIterator> {
+        if page_number == 0 {
+            return Err(ReaderError::corrupt(0, 0, "page number 0 is never used"));
+        }
+        if let Some(wal_page) = self.wal.as_mut().and_then(|wal| wal.read_page(page_number)) {
+            return Ok(wal_page?);
+        }
+
+        let (page_start_offset, size) = (
+            self.page_size as u64 * (page_number as u64 - 1),
+            self.page_size as usize,
+        );
+        self.source
+            .read_bytes_from(page_start_offset, size)
+            .map_err(|err| match err.kind() {
+                io::ErrorKind::UnexpectedEof => {
+                    ReaderError::corrupt(page_number, 0, "page is past the end of the file")
+                }
+                _ => ReaderError::Io(err),
+            })
     }
 
-    fn new_cell_iterator(&mut self, cell_count: u16) -> BytesIterator {
-        self.bytes_iterator
-            .next_n_as_iter(cell_count as usize * 2_usize)
-            .unwrap()
-    }
-
-    fn get_column_serial_type_info(&self, val: u64) -> SerialType {
-        if val == 0 {
-            SerialType::NULL
-        } else if val < 12 {
-            if val == 1 {
-                SerialType::INTEGER(1)
-            } else if val == 2 {
-                SerialType::INTEGER(2)
-            } else if val == 3 {
-                SerialType::INTEGER(3)
-            } else if val == 4 {
-                SerialType::INTEGER(4)
-            } else if val == 5 {
-                SerialType::INTEGER(6)
-            } else if val == 6 {
-                SerialType::INTEGER(8)
-            } else if val == 7 {
-                SerialType::FLOAT64(8)
-            } else if val == 8 {
-                SerialType::INTEGER0
-            } else if val == 9 {
-                SerialType::INTEGER1
-            } else {
-                RESERVED
-            }
-        } else if val % 2 == 0 {
-            SerialType::BLOB((val - 12) / 2)
+    /* number of payload bytes stored on the b-tree page itself, the rest spills to overflow pages */
+    fn local_payload_size(&self, payload_size: u64, page_type: &PageType) -> u64 {
+        let usable_size = self.usable_size as u64;
+        let max_local = match page_type {
+            PageType::TblLeaf => usable_size - 35,
+            _ => ((usable_size - 12) * 64 / 255) - 23,
+        };
+        if payload_size <= max_local {
+            return payload_size;
+        }
+
+        let min_local = ((usable_size - 12) * 32 / 255) - 23;
+        let local_size = min_local + ((payload_size - min_local) % (usable_size - 4));
+        if local_size <= max_local {
+            local_size
         } else {
-            SerialType::TEXT((val - 13) / 2)
+            min_local
         }
     }
 }
 
-pub struct PageReaderBuilder {
-    file_reader: FileReader,
-    page_size: u16,
-}
+fn read_record(
+    payload: &mut BytesIterator,
+    page_no: u32,
+    encoding: TextEncoding,
+) -> Result<Record> {
+    let (record_header_size, bytes_read) = varint::decode(payload)
+        .ok_or_else(|| corrupt_record(page_no, payload, "record header size is truncated"))?;
+
+    let mut record_header_size = record_header_size.checked_sub(bytes_read).ok_or_else(|| {
+        corrupt_record(
+            page_no,
+            payload,
+            "record header is shorter than its size varint",
+        )
+    })?;
+
+    let mut serial_types = Vec::new();
+    let record_header_size_copy = record_header_size;
+    let mut record_body_size: u64 = 0;
+    while record_header_size > 0 {
+        let (val, bytes_read) = varint::decode(payload)
+            .ok_or_else(|| corrupt_record(page_no, payload, "record header is truncated"))?;
+        let serial_type = get_column_serial_type_info(val).ok_or_else(|| {
+            corrupt_record(page_no, payload, format!("reserved serial type {}", val))
+        })?;
+        record_body_size = record_body_size
+            .checked_add(get_read_size(&serial_type))
+            .ok_or_else(|| corrupt_record(page_no, payload, "record body size overflows"))?;
+        serial_types.push(serial_type);
+
+        record_header_size = record_header_size.checked_sub(bytes_read).ok_or_else(|| {
+            corrupt_record(page_no, payload, "serial types run past the record header")
+        })?;
+    }
 
-impl PageReaderBuilder {
-    pub fn new(file_reader: FileReader, page_size: u16) -> Self {
-        Self {
-            file_reader,
-            page_size,
+    let record_header = RecordHeader {
+        /* every byte of it was read above, so it fits */
+        header_size: record_header_size_copy as usize,
+        serial_types: serial_types.into_boxed_slice(),
+    };
+
+    let mut rows: Vec<Value> = Vec::new();
+    let mut record_body_iterator = payload
+        .next_n_as_iter(record_body_size as usize)
+        .ok_or_else(|| {
+            corrupt_record(page_no, payload, "record body is larger than its payload")
+        })?;
+
+    for serial_type in record_header.serial_types.iter() {
+        let read_size = get_read_size(serial_type);
+        if read_size == 0 {
+            rows.push(decode(serial_type, &[], encoding));
+            continue;
         }
+
+        let row = record_body_iterator
+            .next_n(read_size as usize)
+            .ok_or_else(|| corrupt_record(page_no, payload, "record body is truncated"))?;
+        rows.push(decode(serial_type, &row, encoding));
     }
-    pub fn new_reader(&mut self, page_number: u32) -> PageReader {
-        PageReader::new(&mut self.file_reader, page_number, self.page_size)
-    }
+
+    Ok(Record {
+        record_header,
+        rows,
+    })
+}
+
+fn corrupt_record(page_no: u32, payload: &BytesIterator, reason: impl Into<String>) -> ReaderError {
+    ReaderError::corrupt(page_no, payload.offset(), reason)
+}
+
+/* None for the reserved serial types 10 and 11 */
+fn get_column_serial_type_info(val: u64) -> Option<SerialType> {
+    let serial_type = match val {
+        0 => SerialType::NULL,
+        1 => SerialType::INTEGER(1),
+        2 => SerialType::INTEGER(2),
+        3 => SerialType::INTEGER(3),
+        4 => SerialType::INTEGER(4),
+        5 => SerialType::INTEGER(6),
+        6 => SerialType::INTEGER(8),
+        7 => SerialType::FLOAT64(8),
+        8 => SerialType::INTEGER0,
+        9 => SerialType::INTEGER1,
+        10 | 11 => return None,
+        _ if val % 2 == 0 => SerialType::BLOB((val - 12) / 2),
+        _ => SerialType::TEXT((val - 13) / 2),
+    };
+    Some(serial_type)
 }
 
-fn decode(serial_type: &SerialType, row: &[u8]) -> String {
+fn decode(serial_type: &SerialType, row: &[u8], encoding: TextEncoding) -> Value {
     match serial_type {
-        SerialType::INTEGER(size) => row_u64_converter(row, *size).to_string(),
-        SerialType::TEXT(_size) | SerialType::BLOB(_size) => {
-            String::from_utf8_lossy(row).to_string()
-        }
-        SerialType::FLOAT64(_size) => f64::from_be_bytes([
+        SerialType::NULL => Value::Null,
+        SerialType::INTEGER0 => Value::Integer(0),
+        SerialType::INTEGER1 => Value::Integer(1),
+        SerialType::INTEGER(size) => Value::Integer(row_i64_converter(row, *size)),
+        SerialType::TEXT(_size) => Value::Text(encoding.decode(row)),
+        SerialType::BLOB(_size) => Value::Blob(row.to_vec()),
+        SerialType::FLOAT64(_size) => Value::Real(f64::from_be_bytes([
             row[0], row[1], row[2], row[3], row[4], row[5], row[6], row[7],
-        ])
-        .to_string(),
-        _ => String::new(),
+        ])),
     }
 }
-fn row_u64_converter(row: &[u8], n: u64) -> u64 {
-    match n {
-        1 => u8::from_be_bytes([row[0]]) as u64,
-        2 => u16::from_be_bytes([row[0], row[1]]) as u64,
-        3 => u32::from_be_bytes([0_u8, row[0], row[1], row[2]]) as u64,
-        4 => u32::from_be_bytes([row[0], row[1], row[2], row[3]]) as u64,
-        6 => u64::from_be_bytes([0_u8, 0_u8, row[0], row[1], row[2], row[3], row[4], row[5]]),
-        8 => u64::from_be_bytes([
-            row[0], row[1], row[2], row[3], row[4], row[5], row[6], row[7],
-        ]),
-        _ => 0_u64,
+
+/* big-endian two's complement of 1 to 8 bytes, sign extended to 64 bits */
+fn row_i64_converter(row: &[u8], n: u64) -> i64 {
+    let n = n as usize;
+    let mut bytes = if row[0] & 0x80 != 0 {
+        [0xFF_u8; 8]
+    } else {
+        [0_u8; 8]
+    };
+    bytes[8 - n..].copy_from_slice(&row[..n]);
+    i64::from_be_bytes(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    /* the serial type sqlite writes an integer as, 1 to 6 by the bytes it needs */
+    fn integer_serial_type(value: i64) -> u64 {
+        match value {
+            -0x80..=0x7F => 1,
+            -0x8000..=0x7FFF => 2,
+            -0x80_0000..=0x7F_FFFF => 3,
+            -0x8000_0000..=0x7FFF_FFFF => 4,
+            -0x8000_0000_0000..=0x7FFF_FFFF_FFFF => 5,
+            _ => 6,
+        }
+    }
+
+    /* the value written as sqlite stores it, through the serial type back to a Value */
+    fn round_trip(value: i64) -> Value {
+        let serial_type = get_column_serial_type_info(integer_serial_type(value)).unwrap();
+        let size = match serial_type {
+            SerialType::INTEGER(size) => size as usize,
+            _ => panic!("not an integer serial type"),
+        };
+        let bytes = value.to_be_bytes();
+        decode(&serial_type, &bytes[8 - size..], TextEncoding::Utf8)
+    }
+
+    fn boundaries() -> Vec<i64> {
+        let mut values = vec![0, 1, -1, i64::MIN, i64::MAX, i64::MIN + 1, i64::MAX - 1];
+        for bits in [7, 15, 23, 31, 47] {
+            let edge = 1_i64 << bits;
+            values.extend([edge - 1, edge, edge + 1, -edge + 1, -edge, -edge - 1]);
+        }
+        values
+    }
+
+    #[test]
+    fn each_width_decodes_its_boundaries() {
+        for value in boundaries() {
+            assert_eq!(round_trip(value), Value::Integer(value), "value {}", value);
+        }
+    }
+
+    #[test]
+    fn each_width_sign_extends() {
+        for (width, smallest, largest) in [
+            (1, -0x80, 0x7F),
+            (2, -0x8000, 0x7FFF),
+            (3, -0x80_0000, 0x7F_FFFF),
+            (4, -0x8000_0000, 0x7FFF_FFFF),
+            (6, -0x8000_0000_0000, 0x7FFF_FFFF_FFFF),
+            (8, i64::MIN, i64::MAX),
+        ] {
+            for value in [smallest, smallest + 1, -1, 0, 1, largest - 1, largest] {
+                let bytes = value.to_be_bytes();
+                assert_eq!(
+                    row_i64_converter(&bytes[8 - width..], width as u64),
+                    value,
+                    "{} bytes",
+                    width
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn constant_serial_types() {
+        assert_eq!(
+            decode(&SerialType::INTEGER0, &[], TextEncoding::Utf8),
+            Value::Integer(0)
+        );
+        assert_eq!(
+            decode(&SerialType::INTEGER1, &[], TextEncoding::Utf8),
+            Value::Integer(1)
+        );
+        assert!(get_column_serial_type_info(10).is_none());
+        assert!(get_column_serial_type_info(11).is_none());
+    }
+
+    proptest! {
+        #[test]
+        fn round_trips_any_integer(value: i64) {
+            prop_assert_eq!(round_trip(value), Value::Integer(value));
+        }
+
+        #[test]
+        fn wider_widths_decode_the_same(value: i64) {
+            let bytes = value.to_be_bytes();
+            let narrowest = match integer_serial_type(value) {
+                5 => 6,
+                6 => 8,
+                serial_type => serial_type as usize,
+            };
+            for width in [1, 2, 3, 4, 6, 8].into_iter().filter(|width| *width >= narrowest) {
+                prop_assert_eq!(row_i64_converter(&bytes[8 - width..], width as u64), value);
+            }
+        }
+    }
+
+    #[test]
+    fn headers_longer_than_255_bytes_keep_their_size() {
+        /* 300 one-byte integers, after a header size varint of 302 */
+        let mut bytes = vec![0x82, 0x2e];
+        bytes.extend([1; 300]);
+        bytes.extend((0..300).map(|i| (i % 100) as u8));
+        let record = read_record(&mut BytesIterator::new(bytes), 2, TextEncoding::Utf8).unwrap();
+        assert_eq!(record.record_header.header_size, 300);
+        assert_eq!(record.rows.len(), 300);
+        assert_eq!(record.rows[299], Value::Integer(99));
     }
 }
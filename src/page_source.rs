@@ -0,0 +1,109 @@
+use crate::file_reader::{BytesIterator, FileReader};
+use bytes::Bytes;
+use memmap2::Mmap;
+use std::fs::File;
+use std::io;
+use std::sync::Arc;
+
+/* anything the database bytes can be read from: a file, a memory map or a buffer */
+pub trait PageSource {
+    fn read_bytes_from(&mut self, offset: u64, size: usize) -> io::Result<BytesIterator>;
+}
+
+impl PageSource for FileReader {
+    fn read_bytes_from(&mut self, offset: u64, size: usize) -> io::Result<BytesIterator> {
+        FileReader::read_bytes_from(self, offset, size)
+    }
+}
+
+/* the whole database held in one buffer, pages are handed out as slices of it */
+#[derive(Debug, Clone)]
+pub struct MemorySource {
+    bytes: Bytes,
+}
+
+impl MemorySource {
+    pub fn new(bytes: impl Into<Bytes>) -> Self {
+        Self {
+            bytes: bytes.into(),
+        }
+    }
+
+    /* copies the buffer once up front, for buffers that don't outlive the reader */
+    pub fn copy_from_slice(bytes: &[u8]) -> Self {
+        Self::new(Bytes::copy_from_slice(bytes))
+    }
+}
+
+impl From<Vec<u8>> for MemorySource {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self::new(bytes)
+    }
+}
+
+impl From<&'static [u8]> for MemorySource {
+    fn from(bytes: &'static [u8]) -> Self {
+        Self::new(Bytes::from_static(bytes))
+    }
+}
+
+impl PageSource for MemorySource {
+    fn read_bytes_from(&mut self, offset: u64, size: usize) -> io::Result<BytesIterator> {
+        slice_range(&self.bytes, offset, size).map(BytesIterator::new)
+    }
+}
+
+/*
+    maps the file into memory so page reads are slices of the mapping rather than copies. only
+    for databases no one is writing to: a checkpoint after a VACUUM can truncate the main file,
+    and touching a mapped page past the new end raises SIGBUS. reads inside the mapping don't
+    look at the file again, a read past it maps the file again when it has grown. a live
+    database should be read through FileReader
+*/
+#[derive(Debug, Clone)]
+pub struct MmapSource {
+    bytes: Bytes,
+    file: Arc<File>,
+}
+
+impl MmapSource {
+    pub fn open(path: &String) -> io::Result<Self> {
+        let file = File::open(path)?;
+        Ok(Self {
+            bytes: map(&file)?,
+            file: Arc::new(file),
+        })
+    }
+}
+
+/* the whole file as it is now */
+fn map(file: &File) -> io::Result<Bytes> {
+    /* safety: the reader never writes through the mapping, and the file must not be truncated
+    while it's mapped, see MmapSource */
+    let mmap = unsafe { Mmap::map(file)? };
+    Ok(Bytes::from_owner(mmap))
+}
+
+impl PageSource for MmapSource {
+    fn read_bytes_from(&mut self, offset: u64, size: usize) -> io::Result<BytesIterator> {
+        let end = offset.saturating_add(size as u64);
+        if end > self.bytes.len() as u64 && self.file.metadata()?.len() >= end {
+            self.bytes = map(&self.file)?;
+        }
+        slice_range(&self.bytes, offset, size).map(BytesIterator::new)
+    }
+}
+
+fn slice_range(bytes: &Bytes, offset: u64, size: usize) -> io::Result<Bytes> {
+    let start = usize::try_from(offset).unwrap_or(usize::MAX);
+    match start.checked_add(size) {
+        Some(end) if end <= bytes.len() => Ok(bytes.slice(start..end)),
+        _ => Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!(
+                "read of {} bytes at offset {} is past the end",
+                size, offset
+            ),
+        )),
+    }
+}
@@ -1,153 +1,1201 @@
-use std::collections::HashMap;
-use std::str::FromStr;
+use crate::ast::{
+    BinaryOp, ColumnConstraint, ColumnDef, CreateIndex, CreateTable, CreateView, Expr,
+    IndexedColumn, JoinConstraint, JoinKind, OrderingTerm, ResultColumn, Select, Statement,
+    TableConstraint, TableReference, UnaryOp,
+};
+use crate::error::{ReaderError, Result};
+use crate::lexer::{self, Lexeme, Token};
+use crate::value::Value;
 
-use once_cell::sync::Lazy;
-use regex::{Regex, RegexSet};
+/* sqlite compares identifiers case-insensitively, folding ASCII letters only */
+pub fn identifiers_match(a: &str, b: &str) -> bool {
+    a.eq_ignore_ascii_case(b)
+}
+
+fn binary(op: BinaryOp, left: Expr, right: Expr) -> Expr {
+    Expr::Binary {
+        op,
+        left: Box::new(left),
+        right: Box::new(right),
+    }
+}
 
-pub fn parse_sql(sql: &str, replacement_map: &HashMap<&str, &str>) -> Option<QueryDetails> {
-    let mut sql_sanitized = String::from_str(sql).unwrap();
+pub fn parse(sql: &str) -> Result<Statement> {
+    let mut parser = Parser::new(sql)?;
+    let statement = parser.statement()?;
+    parser.expect_end()?;
+    Ok(statement)
+}
 
-    replacement_map
-        .iter()
-        .for_each(|(&from, &to)| sql_sanitized = sql_sanitized.replace(from, to));
+pub fn parse_create_table(sql: &str) -> Result<CreateTable> {
+    let mut parser = Parser::new(sql)?;
+    let create_table = parser.create_table()?;
+    parser.expect_end()?;
+    Ok(create_table)
+}
 
-    parse_sql_sanitized(&sql_sanitized)
+pub fn parse_create_index(sql: &str) -> Result<CreateIndex> {
+    let mut parser = Parser::new(sql)?;
+    let create_index = parser.create_index()?;
+    parser.expect_end()?;
+    Ok(create_index)
 }
 
-fn parse_sql_sanitized(sql: &str) -> Option<QueryDetails> {
-    let matches: Vec<_> = QUERY_SET.matches(sql).into_iter().collect();
-    // println!("matches: {:?} {sql}", matches);
-    match matches.as_slice() {
-        [0] => {
-            let regex = &REGEXES[0];
-            let caps = regex.captures(sql).unwrap();
-            let index_name = caps["index_name"].to_string();
-            let table_name = caps["table_name"].to_string();
-            let column_name = caps["column_name"].to_string();
-            Some(QueryDetails {
-                qtype: QueryType::INDEX(index_name),
-                stmt: Statement {
-                    table_name,
-                    columns: vec![column_name],
-                    filter: None,
-                    is_star: None,
-                },
-            })
+/* a lone expression, like a column's DEFAULT or the expression of a generated column */
+pub fn parse_expr(sql: &str) -> Result<Expr> {
+    let mut parser = Parser::new(sql)?;
+    let expr = parser.expr()?;
+    parser.expect_end()?;
+    Ok(expr)
+}
+
+/* words that end a column's type name because they start one of its constraints */
+const COLUMN_CONSTRAINT_KEYWORDS: &[&str] = &[
+    "CONSTRAINT",
+    "PRIMARY",
+    "NOT",
+    "NULL",
+    "UNIQUE",
+    "CHECK",
+    "DEFAULT",
+    "COLLATE",
+    "REFERENCES",
+    "GENERATED",
+    "AS",
+];
+
+const TABLE_CONSTRAINT_KEYWORDS: &[&str] = &["CONSTRAINT", "PRIMARY", "UNIQUE", "CHECK", "FOREIGN"];
+
+/* words that can't be a bare column name in an expression, they need quoting */
+const RESERVED_KEYWORDS: &[&str] = &[
+    "SELECT", "FROM", "WHERE", "GROUP", "HAVING", "ORDER", "LIMIT", "AND", "OR", "NOT", "AS", "ON",
+    "JOIN", "UNION", "CASE", "WHEN", "THEN", "ELSE", "END",
+];
+
+/* words that start or constrain a join, so they can't be a table alias without AS */
+const JOIN_KEYWORDS: &[&str] = &[
+    "LEFT", "RIGHT", "FULL", "INNER", "OUTER", "CROSS", "NATURAL", "USING",
+];
+
+/* recursive-descent parser over the lexer's tokens */
+struct Parser<'a> {
+    sql: &'a str,
+    lexemes: Vec<Lexeme>,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(sql: &'a str) -> Result<Self> {
+        Ok(Self {
+            sql,
+            lexemes: lexer::tokenize(sql)?,
+            pos: 0,
+        })
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.lexemes.get(self.pos).map(|lexeme| &lexeme.token)
+    }
+
+    fn peek_nth(&self, n: usize) -> Option<&Token> {
+        self.lexemes.get(self.pos + n).map(|lexeme| &lexeme.token)
+    }
+
+    fn advance(&mut self) -> Option<&Lexeme> {
+        let lexeme = self.lexemes.get(self.pos);
+        if lexeme.is_some() {
+            self.pos += 1;
         }
-        [1] => {
-            let regex = &REGEXES[1];
-            let caps = regex.captures(sql).unwrap();
-            let cols: Vec<_> = caps["column_names"]
-                .split(",")
-                .map(|col_expr| {
-                    col_expr
-                        .trim()
-                        .split(" ")
-                        .collect::<Vec<_>>()
-                        .first()
-                        .unwrap()
-                        .to_string()
-                })
-                .collect();
-
-            Some(QueryDetails {
-                qtype: QueryType::CREATE,
-                stmt: Statement {
-                    table_name: caps["table_name"].to_string(),
-                    columns: cols,
-                    filter: None,
-                    is_star: None,
-                },
-            })
+        lexeme
+    }
+
+    fn error(&self, message: &str) -> ReaderError {
+        match self.lexemes.get(self.pos) {
+            Some(lexeme) => ReaderError::Parse(format!(
+                "{} at column {}",
+                message,
+                lexer::column_at(self.sql, lexeme.start)
+            )),
+            None => ReaderError::Parse(format!("{} at end of input", message)),
+        }
+    }
+
+    fn unexpected(&self) -> ReaderError {
+        match self.lexemes.get(self.pos) {
+            Some(lexeme) => self.error(&format!(
+                "unexpected token \"{}\"",
+                &self.sql[lexeme.start..lexeme.end]
+            )),
+            None => ReaderError::Parse("unexpected end of input".to_string()),
+        }
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        self.is_keyword_at(0, keyword)
+    }
+
+    fn is_keyword_at(&self, n: usize, keyword: &str) -> bool {
+        matches!(self.peek_nth(n), Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword))
+    }
+
+    fn is_any_keyword(&self, keywords: &[&str]) -> bool {
+        keywords.iter().any(|keyword| self.is_keyword(keyword))
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        if self.is_keyword(keyword) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<()> {
+        if self.eat_keyword(keyword) {
+            return Ok(());
+        }
+        Err(self.unexpected())
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn expect(&mut self, token: &Token) -> Result<()> {
+        if self.eat(token) {
+            return Ok(());
+        }
+        Err(self.unexpected())
+    }
+
+    fn expect_end(&mut self) -> Result<()> {
+        while self.eat(&Token::Semicolon) {}
+        match self.peek() {
+            None => Ok(()),
+            Some(_) => Err(self.unexpected()),
+        }
+    }
+
+    /* a bare word, a quoted identifier, or a string literal used as a name like sqlite allows */
+    fn identifier(&mut self) -> Result<String> {
+        match self.peek() {
+            Some(Token::Word(name) | Token::QuotedIdentifier(name) | Token::String(name)) => {
+                let name = name.clone();
+                self.pos += 1;
+                Ok(name)
+            }
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    /* schema-name.table-name, keeping only the table name */
+    fn qualified_name(&mut self) -> Result<String> {
+        let name = self.identifier()?;
+        if self.eat(&Token::Dot) {
+            return self.identifier();
+        }
+        Ok(name)
+    }
+
+    /* the sql text of everything up to the matching close paren, which is consumed too */
+    fn parenthesized_text(&mut self) -> Result<String> {
+        self.expect(&Token::LeftParen)?;
+        let start = self.lexemes.get(self.pos).map(|lexeme| lexeme.start);
+        let mut depth = 1;
+        loop {
+            let end = match self.advance() {
+                Some(lexeme) => lexeme.clone(),
+                None => return Err(self.error("missing closing parenthesis")),
+            };
+            match end.token {
+                Token::LeftParen => depth += 1,
+                Token::RightParen => {
+                    depth -= 1;
+                    if depth == 0 {
+                        let start = start.unwrap_or(end.start);
+                        return Ok(self.sql[start..end.start].trim().to_string());
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn if_not_exists(&mut self) -> Result<()> {
+        if self.eat_keyword("IF") {
+            self.expect_keyword("NOT")?;
+            self.expect_keyword("EXISTS")?;
         }
-        [2] => {
-            let regex = &REGEXES[2];
-            let caps = regex.captures(sql).unwrap();
-            let count = caps.name("count").is_some();
-            let filter = caps.name("filters").map(|_expr| {
-                (
-                    caps["filter_column"].to_string(),
-                    caps["filter_value"].to_string(),
-                )
+        Ok(())
+    }
+
+    fn column_name_list(&mut self) -> Result<Vec<String>> {
+        self.expect(&Token::LeftParen)?;
+        let mut names = Vec::new();
+        loop {
+            names.push(self.identifier()?);
+            /* indexed-column: COLLATE name and ASC/DESC may follow */
+            if self.eat_keyword("COLLATE") {
+                self.identifier()?;
+            }
+            let _ = self.eat_keyword("ASC") || self.eat_keyword("DESC");
+            if !self.eat(&Token::Comma) {
+                break;
+            }
+        }
+        self.expect(&Token::RightParen)?;
+        Ok(names)
+    }
+
+    fn statement(&mut self) -> Result<Statement> {
+        if self.is_keyword("SELECT") {
+            return Ok(Statement::Select(self.select()?));
+        }
+        if self.is_keyword("CREATE") {
+            let mut n = 1;
+            while ["TEMP", "TEMPORARY", "UNIQUE"]
+                .iter()
+                .any(|keyword| self.is_keyword_at(n, keyword))
+            {
+                n += 1;
+            }
+            if self.is_keyword_at(n, "TABLE") {
+                return Ok(Statement::CreateTable(self.create_table()?));
+            } else if self.is_keyword_at(n, "INDEX") {
+                return Ok(Statement::CreateIndex(self.create_index()?));
+            } else if self.is_keyword_at(n, "VIEW") {
+                return Ok(Statement::CreateView(self.create_view()?));
+            }
+            self.pos += n;
+        }
+        Err(self.unexpected())
+    }
+
+    fn select(&mut self) -> Result<Select> {
+        self.expect_keyword("SELECT")?;
+        let distinct = self.eat_keyword("DISTINCT");
+        if !distinct {
+            self.eat_keyword("ALL");
+        }
+        let mut columns = Vec::new();
+        loop {
+            columns.push(self.result_column()?);
+            if !self.eat(&Token::Comma) {
+                break;
+            }
+        }
+        self.expect_keyword("FROM")?;
+        let from = self.table_list()?;
+        let filter = if self.eat_keyword("WHERE") {
+            Some(self.expr()?)
+        } else {
+            None
+        };
+        let mut group_by = Vec::new();
+        if self.eat_keyword("GROUP") {
+            self.expect_keyword("BY")?;
+            loop {
+                group_by.push(self.expr()?);
+                if !self.eat(&Token::Comma) {
+                    break;
+                }
+            }
+        }
+        let having = if self.eat_keyword("HAVING") {
+            Some(self.expr()?)
+        } else {
+            None
+        };
+        let mut order_by = Vec::new();
+        if self.eat_keyword("ORDER") {
+            self.expect_keyword("BY")?;
+            loop {
+                order_by.push(self.ordering_term()?);
+                if !self.eat(&Token::Comma) {
+                    break;
+                }
+            }
+        }
+
+        /* LIMIT m, n is LIMIT n OFFSET m */
+        let (mut limit, mut offset) = (None, None);
+        if self.eat_keyword("LIMIT") {
+            let first = self.expr()?;
+            if self.eat_keyword("OFFSET") {
+                offset = Some(self.expr()?);
+                limit = Some(first);
+            } else if self.eat(&Token::Comma) {
+                limit = Some(self.expr()?);
+                offset = Some(first);
+            } else {
+                limit = Some(first);
+            }
+        }
+
+        Ok(Select {
+            distinct,
+            columns,
+            from,
+            filter,
+            group_by,
+            having,
+            order_by,
+            limit,
+            offset,
+        })
+    }
+
+    /* the first table, then each table joined to it by a comma or a JOIN with its ON or USING */
+    fn table_list(&mut self) -> Result<Vec<TableReference>> {
+        let mut from = vec![self.table_reference(JoinKind::Inner)?];
+        loop {
+            let join = if self.eat(&Token::Comma) || self.eat_keyword("JOIN") {
+                JoinKind::Inner
+            } else if self.eat_keyword("INNER") || self.eat_keyword("CROSS") {
+                self.expect_keyword("JOIN")?;
+                JoinKind::Inner
+            } else if self.eat_keyword("LEFT") {
+                self.eat_keyword("OUTER");
+                self.expect_keyword("JOIN")?;
+                JoinKind::Left
+            } else if self.is_any_keyword(&["NATURAL", "RIGHT", "FULL"]) {
+                let word = self.identifier()?.to_ascii_uppercase();
+                return Err(ReaderError::Unsupported(format!("{} JOIN", word)));
+            } else {
+                break;
+            };
+            let mut table = self.table_reference(join)?;
+            if self.eat_keyword("ON") {
+                table.constraint = Some(JoinConstraint::On(self.expr()?));
+            } else if self.eat_keyword("USING") {
+                table.constraint = Some(JoinConstraint::Using(self.column_name_list()?));
+            }
+            from.push(table);
+        }
+        Ok(from)
+    }
+
+    /* table-name [[AS] alias], where a keyword can't be the alias unless AS comes first */
+    fn table_reference(&mut self, join: JoinKind) -> Result<TableReference> {
+        let table_name = self.qualified_name()?;
+        let alias = if self.eat_keyword("AS") {
+            Some(self.identifier()?)
+        } else {
+            match self.peek() {
+                Some(Token::Word(word))
+                    if !RESERVED_KEYWORDS
+                        .iter()
+                        .chain(JOIN_KEYWORDS)
+                        .any(|keyword| word.eq_ignore_ascii_case(keyword)) =>
+                {
+                    Some(self.identifier()?)
+                }
+                Some(Token::QuotedIdentifier(_) | Token::String(_)) => Some(self.identifier()?),
+                _ => None,
+            }
+        };
+        Ok(TableReference {
+            table_name,
+            alias,
+            join,
+            constraint: None,
+        })
+    }
+
+    fn ordering_term(&mut self) -> Result<OrderingTerm> {
+        /* a COLLATE around the whole term is the term's, so an alias or position under it works */
+        let (expr, collation) = match self.expr()? {
+            Expr::Collate { expr, collation } => (*expr, Some(collation)),
+            expr => (expr, None),
+        };
+        let descending = if self.eat_keyword("DESC") {
+            true
+        } else {
+            self.eat_keyword("ASC");
+            false
+        };
+        let nulls_first = if self.eat_keyword("NULLS") {
+            if self.eat_keyword("FIRST") {
+                Some(true)
+            } else {
+                self.expect_keyword("LAST")?;
+                Some(false)
+            }
+        } else {
+            None
+        };
+        Ok(OrderingTerm {
+            expr,
+            collation,
+            descending,
+            nulls_first,
+        })
+    }
+
+    fn result_column(&mut self) -> Result<ResultColumn> {
+        if self.eat(&Token::Star) {
+            return Ok(ResultColumn::Star);
+        }
+        if let (Some(Token::Word(table) | Token::QuotedIdentifier(table)), Some(Token::Dot)) =
+            (self.peek(), self.peek_nth(1))
+        {
+            if self.peek_nth(2) == Some(&Token::Star) {
+                let table = table.clone();
+                self.pos += 3;
+                return Ok(ResultColumn::TableStar(table));
+            }
+        }
+        let expr = self.expr()?;
+        /* the AS is optional, but then a keyword can't be the alias */
+        let alias = if self.eat_keyword("AS") {
+            Some(self.identifier()?)
+        } else {
+            match self.peek() {
+                Some(Token::Word(word))
+                    if !RESERVED_KEYWORDS
+                        .iter()
+                        .any(|keyword| word.eq_ignore_ascii_case(keyword)) =>
+                {
+                    Some(self.identifier()?)
+                }
+                Some(Token::QuotedIdentifier(_) | Token::String(_)) => Some(self.identifier()?),
+                _ => None,
+            }
+        };
+        Ok(ResultColumn::Expr { expr, alias })
+    }
+
+    fn expr(&mut self) -> Result<Expr> {
+        self.or()
+    }
+
+    fn or(&mut self) -> Result<Expr> {
+        let mut left = self.and()?;
+        while self.eat_keyword("OR") {
+            let right = self.and()?;
+            left = binary(BinaryOp::Or, left, right);
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expr> {
+        let mut left = self.not()?;
+        while self.eat_keyword("AND") {
+            let right = self.not()?;
+            left = binary(BinaryOp::And, left, right);
+        }
+        Ok(left)
+    }
+
+    /* NOT binds looser than comparisons, so NOT a = b is NOT (a = b) */
+    fn not(&mut self) -> Result<Expr> {
+        if self.eat_keyword("NOT") {
+            return Ok(Expr::Unary {
+                op: UnaryOp::Not,
+                expr: Box::new(self.not()?),
             });
-            let table_name = caps["table_name"].to_string();
-
-            match caps.name("star") {
-                Some(val) => {
-                    let columns = vec![val.as_str().to_string()];
-                    Some(QueryDetails {
-                        qtype: QueryType::SELECT(count),
-                        stmt: Statement {
-                            table_name,
-                            columns,
-                            filter,
-                            is_star: Some(true),
-                        },
-                    })
+        }
+        self.equality()
+    }
+
+    /*
+        = == != <> IS LIKE GLOB IN BETWEEN and the NULL tests, which all share a precedence
+        level looser than the relational operators like in sqlite
+    */
+    fn equality(&mut self) -> Result<Expr> {
+        let mut left = self.relational()?;
+        loop {
+            let negated = self.is_keyword("NOT")
+                && ["LIKE", "GLOB", "IN", "BETWEEN", "NULL"]
+                    .iter()
+                    .any(|keyword| self.is_keyword_at(1, keyword));
+            if negated {
+                self.pos += 1;
+            }
+
+            left = if self.eat(&Token::Eq) {
+                binary(BinaryOp::Eq, left, self.relational()?)
+            } else if self.eat(&Token::NotEq) {
+                binary(BinaryOp::NotEq, left, self.relational()?)
+            } else if self.eat_keyword("IS") {
+                let op = if self.eat_keyword("NOT") {
+                    BinaryOp::IsNot
+                } else {
+                    BinaryOp::Is
+                };
+                binary(op, left, self.relational()?)
+            } else if self.eat_keyword("ISNULL") {
+                binary(BinaryOp::Is, left, Expr::Literal(Value::Null))
+            } else if self.eat_keyword("NOTNULL") || (negated && self.eat_keyword("NULL")) {
+                binary(BinaryOp::IsNot, left, Expr::Literal(Value::Null))
+            } else if self.is_keyword("LIKE") || self.is_keyword("GLOB") {
+                self.like(left, negated)?
+            } else if self.eat_keyword("IN") {
+                Expr::In {
+                    expr: Box::new(left),
+                    list: self.expr_list()?,
+                    negated,
                 }
-                None => {
-                    let columns = caps["column_names"]
-                        .split(",")
-                        .map(|name| String::from_str(name.trim()).unwrap())
-                        .collect::<Vec<_>>();
-                    Some(QueryDetails {
-                        qtype: QueryType::SELECT(count),
-                        stmt: Statement {
-                            table_name,
-                            columns,
-                            filter,
-                            is_star: Some(false),
-                        },
-                    })
+            } else if self.eat_keyword("BETWEEN") {
+                /* the AND here belongs to BETWEEN, so the bounds are parsed above it */
+                let low = self.relational()?;
+                self.expect_keyword("AND")?;
+                let high = self.relational()?;
+                Expr::Between {
+                    expr: Box::new(left),
+                    low: Box::new(low),
+                    high: Box::new(high),
+                    negated,
                 }
+            } else {
+                return Ok(left);
+            };
+        }
+    }
+
+    fn like(&mut self, expr: Expr, negated: bool) -> Result<Expr> {
+        let glob = self.eat_keyword("GLOB");
+        if !glob {
+            self.expect_keyword("LIKE")?;
+        }
+        let pattern = self.relational()?;
+        let escape = if !glob && self.eat_keyword("ESCAPE") {
+            Some(Box::new(self.relational()?))
+        } else {
+            None
+        };
+        Ok(Expr::Like {
+            expr: Box::new(expr),
+            pattern: Box::new(pattern),
+            escape,
+            glob,
+            negated,
+        })
+    }
+
+    /* ( expr, ... ), which may be empty for IN */
+    fn expr_list(&mut self) -> Result<Vec<Expr>> {
+        self.expect(&Token::LeftParen)?;
+        let mut list = Vec::new();
+        if self.eat(&Token::RightParen) {
+            return Ok(list);
+        }
+        loop {
+            list.push(self.expr()?);
+            if !self.eat(&Token::Comma) {
+                break;
             }
         }
-        [] => {
-            println!("no matches");
+        self.expect(&Token::RightParen)?;
+        Ok(list)
+    }
+
+    /* a left-associative run of the operators of one precedence level */
+    fn binary_level(
+        &mut self,
+        operators: &[(Token, BinaryOp)],
+        operand: fn(&mut Self) -> Result<Expr>,
+    ) -> Result<Expr> {
+        let mut left = operand(self)?;
+        loop {
+            let op = match self
+                .peek()
+                .and_then(|token| operators.iter().find(|(operator, _)| operator == token))
+            {
+                Some((_, op)) => *op,
+                None => return Ok(left),
+            };
+            self.pos += 1;
+            let right = operand(self)?;
+            left = binary(op, left, right);
+        }
+    }
+
+    fn relational(&mut self) -> Result<Expr> {
+        self.binary_level(
+            &[
+                (Token::Lt, BinaryOp::Lt),
+                (Token::LtEq, BinaryOp::LtEq),
+                (Token::Gt, BinaryOp::Gt),
+                (Token::GtEq, BinaryOp::GtEq),
+            ],
+            Self::bitwise,
+        )
+    }
+
+    fn bitwise(&mut self) -> Result<Expr> {
+        self.binary_level(
+            &[
+                (Token::BitAnd, BinaryOp::BitAnd),
+                (Token::BitOr, BinaryOp::BitOr),
+                (Token::ShiftLeft, BinaryOp::ShiftLeft),
+                (Token::ShiftRight, BinaryOp::ShiftRight),
+            ],
+            Self::additive,
+        )
+    }
+
+    fn additive(&mut self) -> Result<Expr> {
+        self.binary_level(
+            &[
+                (Token::Plus, BinaryOp::Add),
+                (Token::Minus, BinaryOp::Subtract),
+            ],
+            Self::multiplicative,
+        )
+    }
+
+    fn multiplicative(&mut self) -> Result<Expr> {
+        self.binary_level(
+            &[
+                (Token::Star, BinaryOp::Multiply),
+                (Token::Slash, BinaryOp::Divide),
+                (Token::Percent, BinaryOp::Remainder),
+            ],
+            Self::concat,
+        )
+    }
+
+    fn concat(&mut self) -> Result<Expr> {
+        self.binary_level(&[(Token::Concat, BinaryOp::Concat)], Self::collate)
+    }
+
+    /* COLLATE binds tighter than || but looser than the unary operators, like in sqlite */
+    fn collate(&mut self) -> Result<Expr> {
+        let mut expr = self.unary()?;
+        while self.eat_keyword("COLLATE") {
+            expr = Expr::Collate {
+                expr: Box::new(expr),
+                collation: self.identifier()?,
+            };
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        let op = match self.peek() {
+            /* a sign in front of a number is folded into the literal */
+            Some(Token::Minus | Token::Plus)
+                if matches!(self.peek_nth(1), Some(Token::Number(_))) =>
+            {
+                return self.signed_number();
+            }
+            Some(Token::Minus) => UnaryOp::Negate,
+            Some(Token::Plus) => UnaryOp::Plus,
+            Some(Token::BitNot) => UnaryOp::BitNot,
+            _ => return self.primary(),
+        };
+        self.pos += 1;
+        Ok(Expr::Unary {
+            op,
+            expr: Box::new(self.unary()?),
+        })
+    }
+
+    fn signed_number(&mut self) -> Result<Expr> {
+        let token = match self.peek() {
+            Some(token) => token.clone(),
+            None => return Err(self.unexpected()),
+        };
+        let text = match self.peek_nth(1) {
+            Some(Token::Number(text)) => text.clone(),
+            _ => return Err(self.unexpected()),
+        };
+        self.pos += 1;
+        let value = self.number(&text)?;
+        self.pos += 1;
+        Ok(Expr::Literal(match (token, value) {
+            (Token::Plus, value) => value,
+            /* -9223372036854775808 only fits as a negative literal */
+            (_, Value::Real(_)) if text == "9223372036854775808" => Value::Integer(i64::MIN),
+            (_, Value::Integer(int)) => match int.checked_neg() {
+                Some(int) => Value::Integer(int),
+                None => Value::Real(-(int as f64)),
+            },
+            (_, Value::Real(real)) => Value::Real(-real),
+            (_, value) => value,
+        }))
+    }
+
+    fn primary(&mut self) -> Result<Expr> {
+        let token = match self.peek() {
+            Some(token) => token.clone(),
+            None => return Err(self.unexpected()),
+        };
+        match token {
+            Token::Number(text) => {
+                let value = self.number(&text)?;
+                self.pos += 1;
+                Ok(Expr::Literal(value))
+            }
+            Token::String(text) => {
+                self.pos += 1;
+                Ok(Expr::Literal(Value::Text(text)))
+            }
+            Token::Blob(bytes) => {
+                self.pos += 1;
+                Ok(Expr::Literal(Value::Blob(bytes)))
+            }
+            Token::LeftParen => {
+                self.pos += 1;
+                let expr = self.expr()?;
+                self.expect(&Token::RightParen)?;
+                Ok(expr)
+            }
+            Token::Word(word) if word.eq_ignore_ascii_case("NULL") => {
+                self.pos += 1;
+                Ok(Expr::Literal(Value::Null))
+            }
+            Token::Word(word) if word.eq_ignore_ascii_case("CASE") => self.case(),
+            Token::Word(word)
+                if word.eq_ignore_ascii_case("CAST")
+                    && self.peek_nth(1) == Some(&Token::LeftParen) =>
+            {
+                self.pos += 1;
+                self.cast()
+            }
+            Token::Word(word)
+                if RESERVED_KEYWORDS
+                    .iter()
+                    .any(|keyword| word.eq_ignore_ascii_case(keyword)) =>
+            {
+                Err(self.unexpected())
+            }
+            Token::Word(name) | Token::QuotedIdentifier(name) => {
+                self.pos += 1;
+                if self.peek() == Some(&Token::LeftParen) {
+                    return self.function_call(name);
+                }
+                if self.eat(&Token::Dot) {
+                    return Ok(Expr::Column {
+                        table: Some(name),
+                        name: self.identifier()?,
+                    });
+                }
+                Ok(Expr::Column { table: None, name })
+            }
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    fn case(&mut self) -> Result<Expr> {
+        self.expect_keyword("CASE")?;
+        let operand = if self.is_keyword("WHEN") {
             None
+        } else {
+            Some(Box::new(self.expr()?))
+        };
+        let mut branches = Vec::new();
+        while self.eat_keyword("WHEN") {
+            let when = self.expr()?;
+            self.expect_keyword("THEN")?;
+            branches.push((when, self.expr()?));
+        }
+        if branches.is_empty() {
+            return Err(self.unexpected());
         }
-        _ => {
-            print!("multiple matches");
+        let otherwise = if self.eat_keyword("ELSE") {
+            Some(Box::new(self.expr()?))
+        } else {
             None
+        };
+        self.expect_keyword("END")?;
+        Ok(Expr::Case {
+            operand,
+            branches,
+            otherwise,
+        })
+    }
+
+    /* CAST ( expr AS type-name ), the CAST itself already consumed */
+    fn cast(&mut self) -> Result<Expr> {
+        self.expect(&Token::LeftParen)?;
+        let expr = self.expr()?;
+        self.expect_keyword("AS")?;
+        let type_name = match self.type_name()? {
+            Some(type_name) => type_name,
+            None => return Err(self.unexpected()),
+        };
+        self.expect(&Token::RightParen)?;
+        Ok(Expr::Cast {
+            expr: Box::new(expr),
+            type_name,
+        })
+    }
+
+    fn function_call(&mut self, name: String) -> Result<Expr> {
+        self.expect(&Token::LeftParen)?;
+        let mut args = Vec::new();
+        let star = self.eat(&Token::Star);
+        let distinct = !star && self.eat_keyword("DISTINCT");
+        if !star && !distinct {
+            self.eat_keyword("ALL");
         }
+        if !star && self.peek() != Some(&Token::RightParen) {
+            loop {
+                args.push(self.expr()?);
+                if !self.eat(&Token::Comma) {
+                    break;
+                }
+            }
+        }
+        self.expect(&Token::RightParen)?;
+        Ok(Expr::Function {
+            name,
+            args,
+            star,
+            distinct,
+        })
     }
-}
 
-static REGEXES: Lazy<Vec<Regex>> = Lazy::new(|| {
-    let regexes = &[
-        r"((CREATE|create) (INDEX|index) (?P<index_name>[A-Za-z_]+)on (?P<table_name>[A-Za-z_]+)? \((?P<column_name>.*)\))",
-        r"((CREATE|create) (TABLE|table) (?P<table_name>[A-Za-z_]+)[\s]?\((?P<column_names>.*)\))",
-        r"((SELECT|select) ((?<count>(COUNT|count)\()?((?<column_names>[ A-Za-z_,]+)|(?<star>\*)))\)? (FROM|from) (?P<table_name>[A-Za-z_]+))( (WHERE|where) (?<filters>(?<filter_column>[A-Za-z_]+)(\s)?=(\s)?'(?<filter_value>[\w\-() ]+)'))?",
-    ];
-
-    regexes
-        .iter()
-        .map(|regex_str| Regex::new(regex_str).unwrap())
-        .collect()
-});
-
-static QUERY_SET: Lazy<RegexSet> = Lazy::new(|| {
-    let regexes: Vec<&str> = REGEXES.iter().map(|rd| rd.as_str()).collect();
-    RegexSet::new(&regexes).unwrap()
-});
-
-#[derive(Debug)]
-pub struct QueryDetails {
-    pub qtype: QueryType,
-    pub stmt: Statement,
-}
+    /* integers that don't fit in 64 bits become reals, hex literals are 64-bit two's complement */
+    fn number(&self, text: &str) -> Result<Value> {
+        if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+            return match u64::from_str_radix(hex, 16) {
+                Ok(int) => Ok(Value::Integer(int as i64)),
+                Err(_) if hex.len() > 16 => {
+                    Err(self.error(&format!("hex literal too big: {}", text)))
+                }
+                Err(_) => Err(self.error(&format!("malformed number {}", text))),
+            };
+        }
+        if let Ok(int) = text.parse::<i64>() {
+            return Ok(Value::Integer(int));
+        }
+        match text.parse::<f64>() {
+            Ok(real) => Ok(Value::Real(real)),
+            Err(_) => Err(self.error(&format!("malformed number {}", text))),
+        }
+    }
 
-#[derive(Debug)]
-pub struct Statement {
-    pub table_name: String,
-    pub columns: Vec<String>,
-    pub filter: Option<(String, String)>,
-    pub is_star: Option<bool>,
-}
+    fn create_table(&mut self) -> Result<CreateTable> {
+        self.expect_keyword("CREATE")?;
+        let _ = self.eat_keyword("TEMP") || self.eat_keyword("TEMPORARY");
+        self.expect_keyword("TABLE")?;
+        self.if_not_exists()?;
+        let table_name = self.qualified_name()?;
+        if self.is_keyword("AS") {
+            return Err(ReaderError::Unsupported(
+                "CREATE TABLE ... AS SELECT".to_string(),
+            ));
+        }
 
-#[derive(Debug)]
-pub enum QueryType {
-    CREATE,
-    SELECT(bool),
-    INDEX(String),
+        self.expect(&Token::LeftParen)?;
+        let mut columns = Vec::new();
+        let mut constraints = Vec::new();
+        loop {
+            if self.is_any_keyword(TABLE_CONSTRAINT_KEYWORDS) {
+                constraints.push(self.table_constraint()?);
+            } else if constraints.is_empty() {
+                columns.push(self.column_def()?);
+            } else {
+                return Err(self.unexpected());
+            }
+            if !self.eat(&Token::Comma) {
+                break;
+            }
+        }
+        self.expect(&Token::RightParen)?;
+
+        let (mut without_rowid, mut strict) = (false, false);
+        loop {
+            if self.eat_keyword("WITHOUT") {
+                self.expect_keyword("ROWID")?;
+                without_rowid = true;
+            } else if self.eat_keyword("STRICT") {
+                strict = true;
+            } else {
+                break;
+            }
+            if !self.eat(&Token::Comma) {
+                break;
+            }
+        }
+
+        Ok(CreateTable {
+            table_name,
+            columns,
+            constraints,
+            without_rowid,
+            strict,
+        })
+    }
+
+    fn column_def(&mut self) -> Result<ColumnDef> {
+        let name = self.identifier()?;
+        let type_name = self.type_name()?;
+
+        let mut constraints = Vec::new();
+        while let Some(constraint) = self.column_constraint()? {
+            constraints.extend(constraint);
+        }
+
+        Ok(ColumnDef {
+            name,
+            type_name,
+            constraints,
+        })
+    }
+
+    /* the words of a type name, with size arguments like VARCHAR(255) or DECIMAL(10, 2) */
+    fn type_name(&mut self) -> Result<Option<String>> {
+        let mut type_words = Vec::new();
+        while !self.is_any_keyword(COLUMN_CONSTRAINT_KEYWORDS) {
+            match self.peek() {
+                Some(Token::Word(_) | Token::QuotedIdentifier(_) | Token::String(_)) => {
+                    type_words.push(self.identifier()?)
+                }
+                _ => break,
+            }
+        }
+        let mut type_name = (!type_words.is_empty()).then(|| type_words.join(" "));
+        if type_name.is_some() && self.peek() == Some(&Token::LeftParen) {
+            let size = self.parenthesized_text()?;
+            type_name = type_name.map(|type_name| format!("{}({})", type_name, size));
+        }
+        Ok(type_name)
+    }
+
+    /* Some(None) for constraints that don't change how the column is read, like NULL */
+    fn column_constraint(&mut self) -> Result<Option<Option<ColumnConstraint>>> {
+        if self.eat_keyword("CONSTRAINT") {
+            self.identifier()?;
+        }
+
+        let constraint = if self.eat_keyword("PRIMARY") {
+            self.expect_keyword("KEY")?;
+            let descending = self.eat_keyword("DESC");
+            if !descending {
+                self.eat_keyword("ASC");
+            }
+            self.conflict_clause()?;
+            let autoincrement = self.eat_keyword("AUTOINCREMENT");
+            Some(ColumnConstraint::PrimaryKey {
+                descending,
+                autoincrement,
+            })
+        } else if self.eat_keyword("NOT") {
+            self.expect_keyword("NULL")?;
+            self.conflict_clause()?;
+            Some(ColumnConstraint::NotNull)
+        } else if self.eat_keyword("NULL") {
+            self.conflict_clause()?;
+            None
+        } else if self.eat_keyword("UNIQUE") {
+            self.conflict_clause()?;
+            Some(ColumnConstraint::Unique)
+        } else if self.eat_keyword("CHECK") {
+            Some(ColumnConstraint::Check(self.parenthesized_text()?))
+        } else if self.eat_keyword("DEFAULT") {
+            Some(ColumnConstraint::Default(self.default_value()?))
+        } else if self.eat_keyword("COLLATE") {
+            Some(ColumnConstraint::Collate(self.identifier()?))
+        } else if self.is_keyword("REFERENCES") {
+            Some(ColumnConstraint::References(self.foreign_key_clause()?))
+        } else if self.is_keyword("GENERATED") || self.is_keyword("AS") {
+            if self.eat_keyword("GENERATED") {
+                self.expect_keyword("ALWAYS")?;
+            }
+            self.expect_keyword("AS")?;
+            let expr = self.parenthesized_text()?;
+            let stored = self.eat_keyword("STORED");
+            if !stored {
+                self.eat_keyword("VIRTUAL");
+            }
+            Some(ColumnConstraint::Generated { expr, stored })
+        } else {
+            return Ok(None);
+        };
+        Ok(Some(constraint))
+    }
+
+    /* DEFAULT takes a literal, a signed number, a bare word or a parenthesized expression */
+    fn default_value(&mut self) -> Result<String> {
+        if self.peek() == Some(&Token::LeftParen) {
+            return self.parenthesized_text();
+        }
+        let start = match self.lexemes.get(self.pos) {
+            Some(lexeme) => lexeme.start,
+            None => return Err(self.unexpected()),
+        };
+        if matches!(self.peek(), Some(Token::Plus | Token::Minus)) {
+            self.pos += 1;
+        }
+        match self.advance() {
+            Some(Lexeme {
+                token:
+                    Token::Number(_)
+                    | Token::String(_)
+                    | Token::Blob(_)
+                    | Token::Word(_)
+                    | Token::QuotedIdentifier(_),
+                end,
+                ..
+            }) => {
+                let end = *end;
+                Ok(self.sql[start..end].to_string())
+            }
+            _ => {
+                self.pos -= 1;
+                Err(self.unexpected())
+            }
+        }
+    }
+
+    /* ON CONFLICT ROLLBACK | ABORT | FAIL | IGNORE | REPLACE */
+    fn conflict_clause(&mut self) -> Result<()> {
+        if self.is_keyword("ON") && self.is_keyword_at(1, "CONFLICT") {
+            self.pos += 2;
+            self.identifier()?;
+        }
+        Ok(())
+    }
+
+    /* REFERENCES table (columns) and its actions, returning the referenced table */
+    fn foreign_key_clause(&mut self) -> Result<String> {
+        self.expect_keyword("REFERENCES")?;
+        let foreign_table = self.identifier()?;
+        if self.peek() == Some(&Token::LeftParen) {
+            self.column_name_list()?;
+        }
+        loop {
+            if self.eat_keyword("ON") {
+                /* ON DELETE | UPDATE  SET NULL | SET DEFAULT | CASCADE | RESTRICT | NO ACTION */
+                self.identifier()?;
+                /* the two-word actions take one more word */
+                let _ = self.eat_keyword("SET") || self.eat_keyword("NO");
+                self.identifier()?;
+            } else if self.eat_keyword("MATCH") {
+                self.identifier()?;
+            } else if self.is_keyword("DEFERRABLE")
+                || (self.is_keyword("NOT") && self.is_keyword_at(1, "DEFERRABLE"))
+            {
+                self.eat_keyword("NOT");
+                self.expect_keyword("DEFERRABLE")?;
+                if self.eat_keyword("INITIALLY") {
+                    self.identifier()?;
+                }
+            } else {
+                return Ok(foreign_table);
+            }
+        }
+    }
+
+    fn create_index(&mut self) -> Result<CreateIndex> {
+        self.expect_keyword("CREATE")?;
+        let unique = self.eat_keyword("UNIQUE");
+        self.expect_keyword("INDEX")?;
+        self.if_not_exists()?;
+        let index_name = self.qualified_name()?;
+        self.expect_keyword("ON")?;
+        let table_name = self.identifier()?;
+        let columns = self.indexed_columns()?;
+
+        /* a partial index's WHERE runs to the end of the statement */
+        let mut condition = None;
+        if self.eat_keyword("WHERE") {
+            let start = match self.lexemes.get(self.pos) {
+                Some(lexeme) => lexeme.start,
+                None => return Err(self.unexpected()),
+            };
+            while !matches!(self.peek(), None | Some(Token::Semicolon)) {
+                self.pos += 1;
+            }
+            let end = self
+                .lexemes
+                .get(self.pos)
+                .map_or(self.sql.len(), |lexeme| lexeme.start);
+            condition = Some(self.sql[start..end].trim().to_string());
+        }
+
+        Ok(CreateIndex {
+            index_name,
+            table_name,
+            columns,
+            unique,
+            condition,
+        })
+    }
+
+    fn indexed_columns(&mut self) -> Result<Vec<IndexedColumn>> {
+        self.expect(&Token::LeftParen)?;
+        let mut columns = Vec::new();
+        loop {
+            let name = self.identifier()?;
+            let collation = if self.eat_keyword("COLLATE") {
+                Some(self.identifier()?)
+            } else {
+                None
+            };
+            let descending = if self.eat_keyword("DESC") {
+                true
+            } else {
+                self.eat_keyword("ASC");
+                false
+            };
+            columns.push(IndexedColumn {
+                name,
+                collation,
+                descending,
+            });
+            if !self.eat(&Token::Comma) {
+                break;
+            }
+        }
+        self.expect(&Token::RightParen)?;
+        Ok(columns)
+    }
+
+    fn create_view(&mut self) -> Result<CreateView> {
+        self.expect_keyword("CREATE")?;
+        let _ = self.eat_keyword("TEMP") || self.eat_keyword("TEMPORARY");
+        self.expect_keyword("VIEW")?;
+        self.if_not_exists()?;
+        let view_name = self.qualified_name()?;
+        let columns = match self.peek() {
+            Some(Token::LeftParen) => self.column_name_list()?,
+            _ => Vec::new(),
+        };
+        self.expect_keyword("AS")?;
+        let select = self.select()?;
+
+        Ok(CreateView {
+            view_name,
+            columns,
+            select,
+        })
+    }
+
+    fn table_constraint(&mut self) -> Result<TableConstraint> {
+        if self.eat_keyword("CONSTRAINT") {
+            self.identifier()?;
+        }
+
+        if self.eat_keyword("PRIMARY") {
+            self.expect_keyword("KEY")?;
+            let columns = self.indexed_columns()?;
+            self.conflict_clause()?;
+            Ok(TableConstraint::PrimaryKey(columns))
+        } else if self.eat_keyword("UNIQUE") {
+            let columns = self.indexed_columns()?;
+            self.conflict_clause()?;
+            Ok(TableConstraint::Unique(columns))
+        } else if self.eat_keyword("CHECK") {
+            Ok(TableConstraint::Check(self.parenthesized_text()?))
+        } else if self.eat_keyword("FOREIGN") {
+            self.expect_keyword("KEY")?;
+            let columns = self.column_name_list()?;
+            let foreign_table = self.foreign_key_clause()?;
+            Ok(TableConstraint::ForeignKey {
+                columns,
+                foreign_table,
+            })
+        } else {
+            Err(self.unexpected())
+        }
+    }
 }
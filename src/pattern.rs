@@ -0,0 +1,152 @@
+/* LIKE and GLOB matching, see https://www.sqlite.org/lang_expr.html#the_like_glob_regexp_match_and_extract_operators */
+
+#[derive(Debug, Clone, PartialEq)]
+enum Piece {
+    /* % or *, any run of characters including none */
+    AnyRun,
+    /* _ or ?, exactly one character */
+    AnyOne,
+    Char(char),
+    /* a GLOB [...] class, a list of inclusive ranges */
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+/* LIKE is case-insensitive for ASCII letters only, an escaped % or _ matches itself */
+pub fn like(pattern: &str, text: &str, escape: Option<char>) -> bool {
+    let mut pieces = Vec::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        pieces.push(match c {
+            c if Some(c) == escape => match chars.next() {
+                Some(escaped) => Piece::Char(escaped),
+                /* a trailing escape can't match anything */
+                None => return false,
+            },
+            '%' => Piece::AnyRun,
+            '_' => Piece::AnyOne,
+            c => Piece::Char(c),
+        });
+    }
+    matches(&pieces, text, true)
+}
+
+/* GLOB is case-sensitive and supports [abc], [a-z] and [^...] classes */
+pub fn glob(pattern: &str, text: &str) -> bool {
+    let mut pieces = Vec::new();
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        pieces.push(match c {
+            '*' => Piece::AnyRun,
+            '?' => Piece::AnyOne,
+            '[' => {
+                let negated = chars.next_if_eq(&'^').is_some();
+                let mut ranges = Vec::new();
+                /* a ] straight after the [ or [^ is part of the class */
+                if let Some(first) = chars.next_if_eq(&']') {
+                    ranges.push((first, first));
+                }
+                loop {
+                    match chars.next() {
+                        Some(']') => break,
+                        Some(low) => match chars.next_if_eq(&'-') {
+                            Some(_) => match chars.next_if(|&c| c != ']') {
+                                Some(high) => ranges.push((low, high)),
+                                None => {
+                                    ranges.push((low, low));
+                                    ranges.push(('-', '-'));
+                                }
+                            },
+                            None => ranges.push((low, low)),
+                        },
+                        /* an unterminated class matches nothing */
+                        None => return false,
+                    }
+                }
+                Piece::Class { negated, ranges }
+            }
+            c => Piece::Char(c),
+        });
+    }
+    matches(&pieces, text, false)
+}
+
+/* wildcard matching that backtracks only to the last AnyRun, so it stays O(pattern * text) */
+fn matches(pieces: &[Piece], text: &str, ignore_case: bool) -> bool {
+    let text: Vec<char> = text.chars().collect();
+    let piece_matches = |piece: &Piece, c: char| match piece {
+        Piece::AnyRun => unreachable!("handled by the caller"),
+        Piece::AnyOne => true,
+        Piece::Char(expected) if ignore_case => expected.eq_ignore_ascii_case(&c),
+        Piece::Char(expected) => *expected == c,
+        Piece::Class { negated, ranges } => {
+            ranges.iter().any(|&(low, high)| low <= c && c <= high) != *negated
+        }
+    };
+
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pieces.get(p) {
+            Some(Piece::AnyRun) => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(piece) if piece_matches(piece, text[t]) => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                /* let the last AnyRun swallow one more character and try again */
+                Some((run_p, run_t)) => {
+                    backtrack = Some((run_p, run_t + 1));
+                    p = run_p + 1;
+                    t = run_t + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pieces[p..].iter().all(|piece| *piece == Piece::AnyRun)
+}
+
+/*
+    the literal text a pattern has to start with, up to its first wildcard. used to narrow an
+    index scan, so None when the pattern starts with a wildcard
+*/
+pub fn literal_prefix(pattern: &str, glob: bool) -> Option<String> {
+    let wildcards: &[char] = if glob { &['*', '?', '['] } else { &['%', '_'] };
+    let prefix = match pattern.find(wildcards) {
+        Some(end) => &pattern[..end],
+        None => pattern,
+    };
+    (!prefix.is_empty()).then(|| prefix.to_string())
+}
+
+/* every ASCII casing of the text, cut short after `max_letters` letters to bound how many there are */
+pub fn ascii_casings(text: &str, max_letters: usize) -> Vec<String> {
+    let mut casings = vec![String::new()];
+    let mut letters = 0;
+    for c in text.chars() {
+        if c.is_ascii_alphabetic() {
+            if letters == max_letters {
+                break;
+            }
+            letters += 1;
+            casings = casings
+                .into_iter()
+                .flat_map(|casing| {
+                    [
+                        format!("{}{}", casing, c.to_ascii_uppercase()),
+                        format!("{}{}", casing, c.to_ascii_lowercase()),
+                    ]
+                })
+                .collect();
+        } else {
+            casings.iter_mut().for_each(|casing| casing.push(c));
+        }
+    }
+    casings
+}
@@ -0,0 +1,698 @@
+use crate::value::Value;
+
+/* the significant digits a real is rounded to, and with the ! flag */
+const REAL_DIGITS: usize = 16;
+const REAL_DIGITS_ALTERNATE: usize = 26;
+
+/* the digits of the largest integer sqlite scales a real to before reading its digits */
+const SCALED_LIMIT: &[u8; 19] = b"9223372036854774784";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Conversion {
+    /* d i u x X o p, and r which adds an ordinal suffix */
+    Radix {
+        base: u64,
+        signed: bool,
+        upper: bool,
+        prefix: &'static str,
+        ordinal: bool,
+    },
+    /* f */
+    Fixed,
+    /* e E */
+    Exponent {
+        upper: bool,
+    },
+    /* g G, fixed or exponent depending on the magnitude */
+    Generic {
+        upper: bool,
+    },
+    /* s z */
+    String,
+    /* q Q w, which double the quote character and Q also wraps in quotes */
+    Escape {
+        quote: u8,
+        wrap: bool,
+    },
+    /* c */
+    Char,
+    Percent,
+    /* n, which outputs nothing */
+    Nothing,
+}
+
+impl Conversion {
+    fn from_byte(byte: u8) -> Option<Self> {
+        let radix = |base, signed, upper, prefix| Conversion::Radix {
+            base,
+            signed,
+            upper,
+            prefix,
+            ordinal: false,
+        };
+        Some(match byte {
+            b'd' | b'i' => radix(10, true, false, ""),
+            b'u' => radix(10, false, false, ""),
+            b'x' | b'p' => radix(16, false, false, "0x"),
+            b'X' => radix(16, false, true, "0X"),
+            b'o' => radix(8, false, false, "0"),
+            b'r' => Conversion::Radix {
+                base: 10,
+                signed: true,
+                upper: false,
+                prefix: "",
+                ordinal: true,
+            },
+            b'f' => Conversion::Fixed,
+            b'e' => Conversion::Exponent { upper: false },
+            b'E' => Conversion::Exponent { upper: true },
+            b'g' => Conversion::Generic { upper: false },
+            b'G' => Conversion::Generic { upper: true },
+            b's' | b'z' => Conversion::String,
+            b'q' => Conversion::Escape {
+                quote: b'\'',
+                wrap: false,
+            },
+            b'Q' => Conversion::Escape {
+                quote: b'\'',
+                wrap: true,
+            },
+            b'w' => Conversion::Escape {
+                quote: b'"',
+                wrap: false,
+            },
+            b'c' => Conversion::Char,
+            b'%' => Conversion::Percent,
+            b'n' => Conversion::Nothing,
+            _ => return None,
+        })
+    }
+}
+
+/* the flags, width and precision of one % directive */
+#[derive(Debug, Default)]
+struct Spec {
+    left_justify: bool,
+    /* '+' or ' ' in front of positive numbers */
+    sign: Option<u8>,
+    alternate: bool,
+    /* the ! flag: more digits for reals, and characters rather than bytes for strings */
+    alternate2: bool,
+    zero_pad: bool,
+    thousands: bool,
+    width: i64,
+    /* negative when there is none */
+    precision: i64,
+}
+
+/* the SQL arguments a format consumes in order, missing ones read as NULL */
+struct Arguments<'a> {
+    values: std::slice::Iter<'a, Value>,
+}
+
+impl Arguments<'_> {
+    fn int(&mut self) -> i64 {
+        self.values.next().map_or(0, Value::to_integer)
+    }
+
+    fn real(&mut self) -> f64 {
+        self.values.next().map_or(0.0, Value::to_real)
+    }
+
+    fn text(&mut self) -> Option<String> {
+        match self.values.next() {
+            None | Some(Value::Null) => None,
+            Some(value) => Some(value.to_string()),
+        }
+    }
+}
+
+/* sqlite's printf() and format(), see https://www.sqlite.org/printf.html */
+pub fn printf(format: &str, args: &[Value]) -> String {
+    let format = format.as_bytes();
+    let mut args = Arguments {
+        values: args.iter(),
+    };
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < format.len() {
+        if format[i] != b'%' {
+            let end = format[i..]
+                .iter()
+                .position(|byte| *byte == b'%')
+                .map_or(format.len(), |len| i + len);
+            out.extend_from_slice(&format[i..end]);
+            i = end;
+            continue;
+        }
+        i += 1;
+        if i == format.len() {
+            out.push(b'%');
+            break;
+        }
+
+        let mut spec = Spec {
+            precision: -1,
+            ..Spec::default()
+        };
+        while let Some(flag) = format.get(i) {
+            match flag {
+                b'-' => spec.left_justify = true,
+                b'+' => spec.sign = Some(b'+'),
+                b' ' => spec.sign = Some(b' '),
+                b'#' => spec.alternate = true,
+                b'!' => spec.alternate2 = true,
+                b'0' => spec.zero_pad = true,
+                b',' => spec.thousands = true,
+                _ => break,
+            }
+            i += 1;
+        }
+        if format.get(i) == Some(&b'*') {
+            i += 1;
+            spec.width = args.int();
+            if spec.width < 0 {
+                spec.left_justify = true;
+                spec.width = spec.width.saturating_neg();
+            }
+        } else {
+            while let Some(digit) = format.get(i).filter(|byte| byte.is_ascii_digit()) {
+                spec.width = (spec.width * 10 + i64::from(digit - b'0')) & 0x7fffffff;
+                i += 1;
+            }
+        }
+        if format.get(i) == Some(&b'.') {
+            i += 1;
+            spec.precision = 0;
+            if format.get(i) == Some(&b'*') {
+                i += 1;
+                spec.precision = args.int().saturating_abs();
+            } else {
+                while let Some(digit) = format.get(i).filter(|byte| byte.is_ascii_digit()) {
+                    spec.precision = (spec.precision * 10 + i64::from(digit - b'0')) & 0x7fffffff;
+                    i += 1;
+                }
+            }
+        }
+        /* l and ll length modifiers change nothing, every integer argument is 64 bits */
+        for _ in 0..2 {
+            if format.get(i) == Some(&b'l') {
+                i += 1;
+            }
+        }
+
+        /* an unknown conversion ends the output, like in sqlite */
+        let conversion = match format.get(i).and_then(|byte| Conversion::from_byte(*byte)) {
+            Some(conversion) => conversion,
+            None => break,
+        };
+        i += 1;
+        let body = match conversion {
+            Conversion::Radix {
+                base,
+                signed,
+                upper,
+                prefix,
+                ordinal,
+            } => format_radix(&mut spec, args.int(), base, signed, upper, prefix, ordinal),
+            Conversion::Fixed | Conversion::Exponent { .. } | Conversion::Generic { .. } => {
+                format_real(&mut spec, args.real(), conversion)
+            }
+            Conversion::String => {
+                let text = args.text().unwrap_or_default();
+                let bytes = text.as_bytes();
+                let len = if spec.precision < 0 {
+                    bytes.len()
+                } else if spec.alternate2 {
+                    char_bytes(bytes, spec.precision)
+                } else {
+                    bytes.len().min(spec.precision as usize)
+                };
+                bytes[..len].to_vec()
+            }
+            Conversion::Escape { quote, wrap } => {
+                let text = args.text();
+                let escaped = match text.as_deref() {
+                    None if wrap => "NULL",
+                    None => "(NULL)",
+                    Some(text) => text,
+                };
+                let bytes = escaped.as_bytes();
+                let len = if spec.precision < 0 {
+                    bytes.len()
+                } else if spec.alternate2 {
+                    char_bytes(bytes, spec.precision)
+                } else {
+                    bytes.len().min(spec.precision as usize)
+                };
+                let wrap = wrap && text.is_some();
+                let mut body = Vec::new();
+                if wrap {
+                    body.push(quote);
+                }
+                for byte in &bytes[..len] {
+                    body.push(*byte);
+                    if *byte == quote {
+                        body.push(quote);
+                    }
+                }
+                if wrap {
+                    body.push(quote);
+                }
+                body
+            }
+            Conversion::Char => {
+                let text = args.text();
+                let c = match text.as_deref().and_then(|text| text.chars().next()) {
+                    Some(c) => c.to_string().into_bytes(),
+                    None => vec![0],
+                };
+                /* a precision repeats the character, all but the last copy go out right away */
+                if spec.precision > 1 {
+                    spec.width -= spec.precision - 1;
+                    if spec.width > 1 && !spec.left_justify {
+                        out.resize(out.len() + spec.width as usize - 1, b' ');
+                        spec.width = 0;
+                    }
+                    for _ in 1..spec.precision {
+                        out.extend_from_slice(&c);
+                    }
+                }
+                spec.alternate2 = true;
+                c
+            }
+            Conversion::Percent => vec![b'%'],
+            Conversion::Nothing => continue,
+        };
+
+        /* with the ! flag the width counts characters, so continuation bytes widen it */
+        let mut width = spec.width;
+        if spec.alternate2
+            && matches!(
+                conversion,
+                Conversion::String | Conversion::Escape { .. } | Conversion::Char
+            )
+        {
+            width += body.iter().filter(|byte| *byte & 0xc0 == 0x80).count() as i64;
+        }
+        let padding = (width - body.len() as i64).max(0) as usize;
+        if !spec.left_justify {
+            out.resize(out.len() + padding, b' ');
+        }
+        out.extend_from_slice(&body);
+        if spec.left_justify {
+            out.resize(out.len() + padding, b' ');
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/* how many bytes the first `chars` characters take */
+fn char_bytes(bytes: &[u8], chars: i64) -> usize {
+    let mut len = 0;
+    for _ in 0..chars {
+        if len == bytes.len() {
+            break;
+        }
+        len += 1;
+        while bytes.get(len).is_some_and(|byte| byte & 0xc0 == 0x80) {
+            len += 1;
+        }
+    }
+    len
+}
+
+fn format_radix(
+    spec: &mut Spec,
+    int: i64,
+    base: u64,
+    signed: bool,
+    upper: bool,
+    prefix: &'static str,
+    ordinal: bool,
+) -> Vec<u8> {
+    let (sign, mut magnitude) = if signed && int < 0 {
+        (Some(b'-'), int.unsigned_abs())
+    } else if signed {
+        (spec.sign, int as u64)
+    } else {
+        (None, int as u64)
+    };
+    let alternate = spec.alternate && magnitude != 0;
+    let mut precision = spec.precision;
+    if spec.zero_pad && precision < spec.width - i64::from(sign.is_some()) {
+        precision = spec.width - i64::from(sign.is_some());
+    }
+
+    let digits: &[u8] = if upper {
+        b"0123456789ABCDEF"
+    } else {
+        b"0123456789abcdef"
+    };
+    /* built backwards, from the last digit */
+    let mut body = Vec::new();
+    if ordinal {
+        let last = magnitude % 10;
+        let suffix = if last >= 4 || (magnitude / 10) % 10 == 1 {
+            "th"
+        } else {
+            ["th", "st", "nd", "rd"][last as usize]
+        };
+        body.extend(suffix.bytes().rev());
+    }
+    loop {
+        body.push(digits[(magnitude % base) as usize]);
+        magnitude /= base;
+        if magnitude == 0 {
+            break;
+        }
+    }
+    while (body.len() as i64) < precision {
+        body.push(b'0');
+    }
+    body.reverse();
+
+    if spec.thousands {
+        let mut grouped = Vec::new();
+        for (i, byte) in body.iter().enumerate() {
+            if i > 0 && (body.len() - i) % 3 == 0 {
+                grouped.push(b',');
+            }
+            grouped.push(*byte);
+        }
+        body = grouped;
+    }
+    let mut out = Vec::new();
+    if alternate {
+        out.extend_from_slice(prefix.as_bytes());
+    }
+    out.extend(sign);
+    out.extend(body);
+    out
+}
+
+/* the decimal digits of a positive real and where its decimal point goes, see sqlite3FpDecode */
+struct Decimal {
+    digits: Vec<u8>,
+    /* how many of the digits come before the decimal point, negative for leading zeros */
+    point: i64,
+}
+
+impl Decimal {
+    /*
+        rounds half up to `round` significant digits, or when it isn't positive to -round
+        digits after the decimal point, and never keeps more than `max_digits`
+    */
+    fn new(real: f64, round: i64, max_digits: usize) -> Self {
+        if real == 0.0 {
+            return Decimal {
+                digits: vec![b'0'],
+                point: 1,
+            };
+        }
+        let (mut digits, mut point) = scaled_digits(real);
+        let mut round = round;
+        if round <= 0 {
+            round = point - round;
+            if round == 0 && digits[0] >= b'5' {
+                round = 1;
+                digits.insert(0, b'0');
+                point += 1;
+            }
+        }
+        if round > 0 && ((round as usize) < digits.len() || digits.len() > max_digits) {
+            let keep = (round as usize).min(max_digits);
+            let round_up = digits[keep] >= b'5';
+            digits.truncate(keep);
+            if round_up {
+                let mut j = keep;
+                loop {
+                    if j == 0 {
+                        digits.insert(0, b'1');
+                        point += 1;
+                        break;
+                    }
+                    j -= 1;
+                    if digits[j] == b'9' {
+                        digits[j] = b'0';
+                    } else {
+                        digits[j] += 1;
+                        break;
+                    }
+                }
+            }
+        }
+        while digits.len() > 1 && digits.last() == Some(&b'0') {
+            digits.pop();
+        }
+        Decimal { digits, point }
+    }
+}
+
+/*
+    the significant digits sqlite reads a positive real as, and where the decimal point goes.
+    it scales the real to an integer below 2^63 as a double-double, which keeps 19 digits, or 18
+    when the leading ones are beyond those of 2^63. the integer is the nearest double plus the
+    truncated remainder, so the digits beyond are dropped toward that double rather than zero
+*/
+fn scaled_digits(real: f64) -> (Vec<u8>, i64) {
+    let scientific = format!("{:.40e}", real);
+    let (mantissa, exponent) = scientific.split_once('e').unwrap();
+    let exact: Vec<u8> = mantissa.bytes().filter(u8::is_ascii_digit).collect();
+    let mut point = exponent.parse::<i64>().unwrap() + 1;
+
+    let kept = if exact[..19] < SCALED_LIMIT[..] {
+        19
+    } else {
+        18
+    };
+    let (whole, fraction) = exact.split_at(kept);
+    let whole_text = std::str::from_utf8(whole).unwrap();
+    let mut int: u64 = whole_text.parse().unwrap();
+    if fraction.iter().any(|digit| *digit != b'0') {
+        let fraction_text = std::str::from_utf8(fraction).unwrap();
+        let nearest: f64 = format!("{}.{}", whole_text, fraction_text).parse().unwrap();
+        /* doubles this large are whole numbers */
+        if nearest as u64 > int {
+            int += 1;
+        }
+    }
+    let digits = int.to_string().into_bytes();
+    point += digits.len() as i64 - kept as i64;
+    (digits, point)
+}
+
+fn format_real(spec: &mut Spec, real: f64, conversion: Conversion) -> Vec<u8> {
+    let mut precision = if spec.precision < 0 {
+        6
+    } else {
+        spec.precision
+    };
+    let round = match conversion {
+        Conversion::Fixed => -precision,
+        Conversion::Generic { .. } => {
+            if precision == 0 {
+                precision = 1;
+            }
+            precision
+        }
+        _ => precision + 1,
+    };
+    let negative = real < 0.0;
+    let sign = if negative { Some(b'-') } else { spec.sign };
+
+    let decimal = if real.is_nan() {
+        let text: &[u8] = if spec.zero_pad { b"null" } else { b"NaN" };
+        return text.to_vec();
+    } else if real.is_infinite() {
+        if !spec.zero_pad {
+            let mut out = Vec::new();
+            out.extend(sign);
+            out.extend_from_slice(b"Inf");
+            return out;
+        }
+        /* zero padded infinities print as the largest number that reads back as infinity */
+        Decimal {
+            digits: vec![b'9'],
+            point: 1000,
+        }
+    } else {
+        let max_digits = if spec.alternate2 {
+            REAL_DIGITS_ALTERNATE
+        } else {
+            REAL_DIGITS
+        };
+        Decimal::new(real.abs(), round, max_digits)
+    };
+
+    let exponent = decimal.point - 1;
+    let (fixed, upper, remove_trailing_zeros) = match conversion {
+        Conversion::Generic { upper } => {
+            if precision > 0 {
+                precision -= 1;
+            }
+            if exponent < -4 || exponent > precision {
+                (false, upper, !spec.alternate)
+            } else {
+                precision -= exponent;
+                (true, upper, !spec.alternate)
+            }
+        }
+        Conversion::Exponent { upper } => (false, upper, spec.alternate2),
+        _ => (true, false, spec.alternate2),
+    };
+
+    let mut out = Vec::new();
+    out.extend(sign);
+    let mut digits = decimal.digits.iter().copied();
+    let mut e2 = if fixed { decimal.point - 1 } else { 0 };
+    if e2 < 0 {
+        out.push(b'0');
+    } else {
+        while e2 >= 0 {
+            out.push(digits.next().unwrap_or(b'0'));
+            if spec.thousands && e2 % 3 == 0 && e2 > 1 {
+                out.push(b',');
+            }
+            e2 -= 1;
+        }
+    }
+    let decimal_point = precision > 0 || spec.alternate || spec.alternate2;
+    if decimal_point {
+        out.push(b'.');
+    }
+    e2 += 1;
+    while e2 < 0 && precision > 0 {
+        out.push(b'0');
+        precision -= 1;
+        e2 += 1;
+    }
+    while precision > 0 {
+        out.push(digits.next().unwrap_or(b'0'));
+        precision -= 1;
+    }
+    if remove_trailing_zeros && decimal_point {
+        while out.last() == Some(&b'0') {
+            out.pop();
+        }
+        if out.last() == Some(&b'.') {
+            if spec.alternate2 {
+                out.push(b'0');
+            } else {
+                out.pop();
+            }
+        }
+    }
+    if !fixed {
+        out.push(if upper { b'E' } else { b'e' });
+        out.push(if exponent < 0 { b'-' } else { b'+' });
+        let exponent = exponent.abs();
+        if exponent >= 100 {
+            out.push(b'0' + (exponent / 100) as u8);
+        }
+        out.push(b'0' + (exponent / 10 % 10) as u8);
+        out.push(b'0' + (exponent % 10) as u8);
+    }
+
+    /* zeros pad after the sign, spaces would go in front of it */
+    if spec.zero_pad && !spec.left_justify && (out.len() as i64) < spec.width {
+        let zeros = spec.width as usize - out.len();
+        let at = usize::from(sign.is_some());
+        out.splice(at..at, std::iter::repeat(b'0').take(zeros));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn int(int: i64) -> Value {
+        Value::Integer(int)
+    }
+
+    fn real(real: f64) -> Value {
+        Value::Real(real)
+    }
+
+    fn text(text: &str) -> Value {
+        Value::Text(text.to_string())
+    }
+
+    /* each expected string is what sqlite3's printf() returns for the same arguments */
+    #[test]
+    fn conversions_match_sqlite() {
+        let cases: Vec<(&str, Vec<Value>, &str)> = vec![
+            ("%d", vec![int(42)], "42"),
+            (
+                "%5d|%-5d|%05d",
+                vec![int(42), int(42), int(42)],
+                "   42|42   |00042",
+            ),
+            ("%+d % d", vec![int(7), int(7)], "+7  7"),
+            ("%,d", vec![int(1234567)], "1,234,567"),
+            (
+                "%x %X %#x %o %#o",
+                vec![int(255), int(255), int(255), int(8), int(8)],
+                "ff FF 0xff 10 010",
+            ),
+            ("%u", vec![int(-1)], "18446744073709551615"),
+            (
+                "%r %r %r %r %r",
+                vec![int(1), int(2), int(3), int(11), int(22)],
+                "1st 2nd 3rd 11th 22nd",
+            ),
+            ("%lld", vec![int(i64::MAX)], "9223372036854775807"),
+            ("%.2f", vec![real(1.23456)], "1.23"),
+            (
+                "%10.3f|%-10.1f|",
+                vec![real(2.5), real(-2.5)],
+                "     2.500|-2.5      |",
+            ),
+            ("%.*f", vec![int(1), real(2.25)], "2.3"),
+            ("%e", vec![real(12345.678)], "1.234568e+04"),
+            ("%.3E", vec![real(0.000123)], "1.230E-04"),
+            (
+                "%g %g %g",
+                vec![int(100000), int(1000000), real(0.0001)],
+                "100000 1e+06 0.0001",
+            ),
+            ("%!.20g", vec![real(0.1)], "0.1000000000000000055"),
+            ("%s", vec![text("text")], "text"),
+            (
+                "%.3s|%5s|%-5s|",
+                vec![text("abcdef"), text("ab"), text("ab")],
+                "abc|   ab|ab   |",
+            ),
+            ("%!.2s", vec![text("ééé")], "éé"),
+            ("%q", vec![text("it's")], "it''s"),
+            ("%Q %Q", vec![text("it's"), Value::Null], "'it''s' NULL"),
+            ("%w", vec![text("a\"b")], "a\"\"b"),
+            ("%c%.3c", vec![text("xyz"), text("y")], "xyyy"),
+            (
+                "%*d|%-*d|",
+                vec![int(4), int(1), int(4), int(1)],
+                "   1|1   |",
+            ),
+            ("100%%", vec![], "100%"),
+            ("%n%d", vec![int(3)], "3"),
+            ("%", vec![], "%"),
+        ];
+        for (format, args, expected) in cases {
+            assert_eq!(printf(format, &args), expected, "{}", format);
+        }
+    }
+
+    #[test]
+    fn arguments_convert_like_sqlite() {
+        /* NULL and missing arguments are 0, 0.0 and the empty string */
+        assert_eq!(
+            printf("%d %s %f", &[Value::Null, Value::Null, Value::Null]),
+            "0  0.000000"
+        );
+        assert_eq!(printf("%d %d", &[]), "0 0");
+        assert_eq!(printf("%d", &[text("12abc")]), "12");
+        /* an unknown conversion ends the output */
+        assert_eq!(printf("%y %d", &[int(1), int(2)]), "");
+    }
+}
@@ -0,0 +1,832 @@
+use crate::aggregate::{AggregateCall, Grouper, DEFAULT_GROUP_MEMORY};
+use crate::ast::{Expr, JoinConstraint, ResultColumn, Select};
+use crate::bound_expr::BoundExpr;
+use crate::data_filter_processor::{self, IndexLookup, RowIdLookup};
+use crate::db_header::TextEncoding;
+use crate::distinct::Distinct;
+use crate::error::{ReaderError, Result};
+use crate::join::{self, Join, Joiner, Source, DEFAULT_JOIN_MEMORY};
+use crate::page_reader::PageReaderBuilder;
+use crate::page_source::PageSource;
+use crate::parser::identifiers_match;
+use crate::schema::{Index, Schema, Table};
+use crate::sorter::{SortKey, Sorter, DEFAULT_SORT_MEMORY};
+use crate::value::{Affinity, Collation, Value};
+use std::io::Write;
+use std::iter;
+use std::ops::ControlFlow;
+
+#[derive(Debug, Clone, Copy)]
+pub struct QueryOptions {
+    /* bytes of rows ORDER BY sorts in memory before spilling sorted runs to temp files */
+    pub sort_memory: usize,
+    /*
+        bytes of groups GROUP BY holds in memory before spilling the rows of new groups, and of
+        rows DISTINCT remembers before spilling the ones that might be new
+    */
+    pub group_memory: usize,
+    /* bytes of rows a hash join holds in memory before rescanning the table for each row */
+    pub join_memory: usize,
+}
+
+impl Default for QueryOptions {
+    fn default() -> Self {
+        Self {
+            sort_memory: DEFAULT_SORT_MEMORY,
+            group_memory: DEFAULT_GROUP_MEMORY,
+            join_memory: DEFAULT_JOIN_MEMORY,
+        }
+    }
+}
+
+/* where a query reads its rows from */
+enum Scan<'a> {
+    Table,
+    /* seeks the row_ids the terms on the INTEGER PRIMARY KEY allow */
+    RowId(RowIdLookup),
+    Index(&'a Index, IndexLookup),
+}
+
+/* a scan and the direction it walks its b-tree in */
+struct Plan<'a> {
+    scan: Scan<'a>,
+    backwards: bool,
+}
+
+impl<'a> Plan<'a> {
+    fn forwards(scan: Scan<'a>) -> Self {
+        Self {
+            scan,
+            backwards: false,
+        }
+    }
+
+    /* walks the scan backwards when only that yields the ORDER BY */
+    fn ordered(scan: Scan<'a>, table: &Table, order_by: &[(BoundExpr, SortKey)]) -> Self {
+        let order = scan_order(&scan, table);
+        let backwards = !order_by.is_empty()
+            && !provides_order(&order, order_by)
+            && provides_order(&reversed(order), order_by);
+        Self { scan, backwards }
+    }
+
+    fn order(&self, table: &Table) -> Vec<(usize, SortKey)> {
+        let order = scan_order(&self.scan, table);
+        if self.backwards {
+            reversed(order)
+        } else {
+            order
+        }
+    }
+}
+
+/* a SELECT with its expressions bound to the joined row of the FROM clause */
+struct BoundSelect<'a> {
+    source: Source<'a>,
+    /* the WHERE terms the first table's scan applies, the joins apply the rest */
+    filter: Option<BoundExpr>,
+    joins: Vec<Join<'a>>,
+    /* the select list, with * expanded to the tables' columns */
+    outputs: Vec<BoundExpr>,
+    /* the select list and the collation each column compares by, empty without DISTINCT */
+    distinct: Vec<(BoundExpr, Collation)>,
+    group_by: Vec<(BoundExpr, Collation)>,
+    /* the aggregate calls in the select list, HAVING and ORDER BY */
+    aggregates: Vec<AggregateCall>,
+    having: Option<BoundExpr>,
+    order_by: Vec<(BoundExpr, SortKey)>,
+}
+
+impl<'a> BoundSelect<'a> {
+    /* the first table of the FROM clause, the one the scan reads */
+    fn table(&self) -> &'a Table {
+        self.source.tables[0].table
+    }
+
+    fn is_aggregate(&self) -> bool {
+        !self.aggregates.is_empty() || !self.group_by.is_empty() || self.having.is_some()
+    }
+
+    fn project(&self, row: &[Value], encoding: TextEncoding) -> Result<Vec<Value>> {
+        self.outputs
+            .iter()
+            .map(|output| output.eval(row, encoding))
+            .collect()
+    }
+
+    /* the joined row's columns the query reads, an index holding them all can stand in for a table */
+    fn columns(&self) -> Vec<usize> {
+        let mut columns = Vec::new();
+        let exprs = self
+            .filter
+            .iter()
+            .chain(self.outputs.iter())
+            .chain(self.group_by.iter().map(|(expr, _)| expr))
+            .chain(self.aggregates.iter().flat_map(|call| call.args.iter()))
+            .chain(self.having.iter())
+            .chain(self.order_by.iter().map(|(expr, _)| expr));
+        for expr in exprs {
+            expr.columns(&mut columns);
+        }
+        for join in self.joins.iter() {
+            join.columns(&mut columns);
+        }
+        /* aggregate results come after the tables' columns */
+        columns.retain(|pos| *pos < self.source.width());
+        columns
+    }
+
+    fn deduplicator(&self, sorted: bool, options: &QueryOptions) -> Option<Distinct> {
+        if self.distinct.is_empty() {
+            return None;
+        }
+        let collations = self
+            .distinct
+            .iter()
+            .map(|(_, collation)| *collation)
+            .collect();
+        Some(Distinct::new(collations, sorted, options.group_memory))
+    }
+}
+
+/* runs a SELECT and writes its rows to `out`, one per line with the columns joined by | */
+pub fn execute_select<S: PageSource>(
+    select: &Select,
+    schema: &Schema,
+    builder: &mut PageReaderBuilder<S>,
+    options: &QueryOptions,
+    out: &mut impl Write,
+) -> Result<()> {
+    let source = Source::new(schema, &select.from)?;
+    let encoding = builder.header().text_encoding;
+    let mut limit = RowLimit::new(select, &source, encoding)?;
+    let bound = bind_select(select, schema, source)?;
+    let table = bound.table();
+
+    /* a bare COUNT(*) counts the table's cells without decoding any rows */
+    if is_count_star(&select.columns)
+        && bound.joins.is_empty()
+        && bound.filter.is_none()
+        && bound.group_by.is_empty()
+        && bound.having.is_none()
+    {
+        if !limit.done() && limit.take() {
+            writeln!(
+                out,
+                "{}",
+                data_filter_processor::count_all_rows(table, builder)?
+            )?;
+        }
+        return Ok(());
+    }
+    if limit.done() {
+        return Ok(());
+    }
+    if bound.is_aggregate() {
+        return execute_aggregate(&bound, schema, builder, options, &mut limit, out);
+    }
+
+    let filter = bound.filter.as_ref();
+    let plan = plan_scan(schema, table, filter, &bound.order_by, &bound.distinct);
+    let order = plan.order(table);
+    let sorted = groups_duplicates(&order, &bound.distinct);
+    /*
+        rows already in order go straight out, so the scan can stop once the LIMIT is reached.
+        DISTINCT rows spilled to disk come out last, so they have to go through the sorter
+    */
+    if bound.order_by.is_empty()
+        || (provides_order(&order, &bound.order_by) && (bound.distinct.is_empty() || sorted))
+    {
+        let mut distinct = bound.deduplicator(sorted, options);
+        let mut emit = |row: Vec<Value>| {
+            /* the scan stops at the LIMIT, but spilled DISTINCT rows are emitted after it */
+            if limit.done() {
+                return Ok(ControlFlow::Break(()));
+            }
+            if limit.take() {
+                write_row(out, &row)?;
+            }
+            Ok(limit.control_flow())
+        };
+        run_scan(&plan, &bound, builder, options, &mut |row| {
+            deduplicate(&mut distinct, bound.project(&row, encoding)?, &mut emit)
+        })?;
+        return match distinct {
+            Some(distinct) => distinct.finish(&mut emit),
+            None => Ok(()),
+        };
+    }
+
+    /* DISTINCT sees the select list with the sort key values riding along behind it */
+    let width = bound.outputs.len();
+    let keys = bound.order_by.iter().map(|(_, key)| *key).collect();
+    let mut sorter = Sorter::new(keys, encoding, options.sort_memory);
+    let mut distinct = bound.deduplicator(sorted, options);
+    let mut sort = |mut row: Vec<Value>| {
+        let key_values = row.split_off(width);
+        sorter.push(key_values, row)?;
+        Ok(ControlFlow::Continue(()))
+    };
+    run_scan(&plan, &bound, builder, options, &mut |row| {
+        let mut entry = bound.project(&row, encoding)?;
+        entry.extend(sort_key_values(&bound.order_by, &row, encoding)?);
+        deduplicate(&mut distinct, entry, &mut sort)
+    })?;
+    if let Some(distinct) = distinct {
+        distinct.finish(&mut sort)?;
+    }
+    write_sorted(sorter, &mut limit, out)
+}
+
+/*
+    groups the rows passing WHERE, then sorts the groups passing HAVING. groups come out in
+    GROUP BY order when there's no ORDER BY
+*/
+fn execute_aggregate<S: PageSource>(
+    bound: &BoundSelect,
+    schema: &Schema,
+    builder: &mut PageReaderBuilder<S>,
+    options: &QueryOptions,
+    limit: &mut RowLimit,
+    out: &mut impl Write,
+) -> Result<()> {
+    let table = bound.table();
+    let encoding = builder.header().text_encoding;
+    let mut grouper = Grouper::new(
+        bound.group_by.clone(),
+        bound.aggregates.clone(),
+        bound.source.width(),
+        encoding,
+        options.group_memory,
+    );
+    let filter = bound.filter.as_ref();
+    let plan = plan_scan(schema, table, filter, &[], &[]);
+    run_scan(&plan, bound, builder, options, &mut |row| {
+        grouper.push(row)?;
+        Ok(ControlFlow::Continue(()))
+    })?;
+
+    let order_by = if bound.order_by.is_empty() {
+        bound
+            .group_by
+            .iter()
+            .map(|(expr, collation)| (expr.clone(), SortKey::new(false, None, *collation)))
+            .collect()
+    } else {
+        bound.order_by.clone()
+    };
+    let width = bound.outputs.len();
+    let keys = order_by.iter().map(|(_, key)| *key).collect();
+    let mut sorter = Sorter::new(keys, encoding, options.sort_memory);
+    let mut distinct = bound.deduplicator(false, options);
+    let mut sort = |mut row: Vec<Value>| {
+        let key_values = row.split_off(width);
+        sorter.push(key_values, row)?;
+        Ok(ControlFlow::Continue(()))
+    };
+    grouper.finish(&mut |row| {
+        if bound
+            .having
+            .as_ref()
+            .map_or(Ok(true), |having| having.matches(&row, encoding))?
+        {
+            let mut entry = bound.project(&row, encoding)?;
+            entry.extend(sort_key_values(&order_by, &row, encoding)?);
+            /* sorting never stops early */
+            let _ = deduplicate(&mut distinct, entry, &mut sort)?;
+        }
+        Ok(())
+    })?;
+    if let Some(distinct) = distinct {
+        distinct.finish(&mut sort)?;
+    }
+    write_sorted(sorter, limit, out)
+}
+
+/* passes the row on unless DISTINCT has seen it before, or spilled it for later */
+fn deduplicate(
+    distinct: &mut Option<Distinct>,
+    row: Vec<Value>,
+    emit: &mut impl FnMut(Vec<Value>) -> Result<ControlFlow<()>>,
+) -> Result<ControlFlow<()>> {
+    match distinct.as_mut() {
+        Some(distinct) => match distinct.push(row)? {
+            Some(row) => emit(row),
+            None => Ok(ControlFlow::Continue(())),
+        },
+        None => emit(row),
+    }
+}
+
+fn sort_key_values(
+    order_by: &[(BoundExpr, SortKey)],
+    row: &[Value],
+    encoding: TextEncoding,
+) -> Result<Vec<Value>> {
+    order_by
+        .iter()
+        .map(|(expr, _)| expr.eval(row, encoding))
+        .collect()
+}
+
+fn write_sorted(sorter: Sorter, limit: &mut RowLimit, out: &mut impl Write) -> Result<()> {
+    for row in sorter.finish()? {
+        if limit.done() {
+            break;
+        }
+        let row = row?;
+        if limit.take() {
+            write_row(out, &row)?;
+        }
+    }
+    Ok(())
+}
+
+/* LIMIT and OFFSET as a countdown over the rows a query produces */
+struct RowLimit {
+    skip: u64,
+    /* None when there's no LIMIT, or a negative one */
+    remaining: Option<u64>,
+}
+
+impl RowLimit {
+    fn new(select: &Select, source: &Source, encoding: TextEncoding) -> Result<Self> {
+        let limit = select
+            .limit
+            .as_ref()
+            .map(|expr| constant_integer(expr, source, encoding))
+            .transpose()?;
+        let offset = select
+            .offset
+            .as_ref()
+            .map(|expr| constant_integer(expr, source, encoding))
+            .transpose()?;
+        Ok(Self {
+            skip: offset.map_or(0, |offset| offset.max(0) as u64),
+            remaining: limit.and_then(|limit| u64::try_from(limit).ok()),
+        })
+    }
+
+    fn done(&self) -> bool {
+        self.remaining == Some(0)
+    }
+
+    /* whether the next row is output, the ones inside the OFFSET are skipped */
+    fn take(&mut self) -> bool {
+        if self.skip > 0 {
+            self.skip -= 1;
+            return false;
+        }
+        if let Some(remaining) = self.remaining.as_mut() {
+            *remaining -= 1;
+        }
+        true
+    }
+
+    fn control_flow(&self) -> ControlFlow<()> {
+        if self.done() {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
+    }
+}
+
+/* LIMIT and OFFSET take any expression that is an integer once NUMERIC affinity is applied */
+fn constant_integer(expr: &Expr, source: &Source, encoding: TextEncoding) -> Result<i64> {
+    let value = BoundExpr::bind(expr, source)?
+        .eval(&[], encoding)?
+        .apply_affinity(Affinity::Numeric);
+    match value {
+        Value::Integer(int) => Ok(int),
+        Value::Real(real) if real.fract() == 0.0 && real.abs() < 9.2e18 => Ok(real as i64),
+        _ => Err(ReaderError::DatatypeMismatch),
+    }
+}
+
+fn is_count_star(columns: &[ResultColumn]) -> bool {
+    matches!(
+        columns,
+        [ResultColumn::Expr {
+            expr: Expr::Function { name, star: true, .. },
+            ..
+        }]
+            if name.eq_ignore_ascii_case("COUNT")
+    )
+}
+
+fn bind_select<'a>(
+    select: &Select,
+    schema: &'a Schema,
+    source: Source<'a>,
+) -> Result<BoundSelect<'a>> {
+    let result_columns = result_exprs(&select.columns, &source)?;
+    let filter = select
+        .filter
+        .as_ref()
+        .map(|filter| BoundExpr::bind(&with_aliases(filter, &result_columns, &source), &source))
+        .transpose()?;
+    let constraints = select
+        .from
+        .iter()
+        .map(|table| match &table.constraint {
+            Some(JoinConstraint::On(on)) => {
+                BoundExpr::bind(&with_aliases(on, &result_columns, &source), &source).map(Some)
+            }
+            _ => Ok(None),
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let (filter, joins) = join::plan_joins(schema, &source, filter, constraints)?;
+    let mut aggregates = Vec::new();
+    let outputs = result_columns
+        .iter()
+        .map(|(expr, _)| BoundExpr::bind_aggregate(expr, &source, &mut aggregates))
+        .collect::<Result<Vec<_>>>()?;
+
+    let distinct = if select.distinct {
+        outputs
+            .iter()
+            .map(|output| (output.clone(), output.collation(&source)))
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    let mut group_by = Vec::new();
+    for (i, expr) in select.group_by.iter().enumerate() {
+        let expr = result_column(expr, &result_columns, &source, "GROUP BY", i)?;
+        let mut nested = Vec::new();
+        let expr = BoundExpr::bind_aggregate(&expr, &source, &mut nested)?;
+        if !nested.is_empty() {
+            return Err(ReaderError::Parse(
+                "aggregate functions are not allowed in the GROUP BY clause".to_string(),
+            ));
+        }
+        let collation = expr.collation(&source);
+        group_by.push((expr, collation));
+    }
+    let having = select
+        .having
+        .as_ref()
+        .map(|having| {
+            let having = with_aliases(having, &result_columns, &source);
+            BoundExpr::bind_aggregate(&having, &source, &mut aggregates)
+        })
+        .transpose()?;
+    if having.is_some() && group_by.is_empty() && aggregates.is_empty() {
+        return Err(ReaderError::Parse(
+            "HAVING clause on a non-aggregate query".to_string(),
+        ));
+    }
+
+    let mut order_by = Vec::new();
+    for (i, term) in select.order_by.iter().enumerate() {
+        /* in ORDER BY an alias wins over a column of the same name */
+        let alias = match &term.expr {
+            Expr::Column { table: None, name } => aliased(name, &result_columns),
+            _ => None,
+        };
+        let expr = match alias {
+            Some(expr) => expr.clone(),
+            None => result_column(&term.expr, &result_columns, &source, "ORDER BY", i)?,
+        };
+        let expr = BoundExpr::bind_aggregate(&expr, &source, &mut aggregates)?;
+        /* a column sorts by its declared collation unless the term names one */
+        let collation = match &term.collation {
+            Some(name) => Collation::from_name(name).ok_or_else(|| {
+                ReaderError::Parse(format!("no such collation sequence: {}", name))
+            })?,
+            None => expr.collation(&source),
+        };
+        let key = SortKey::new(term.descending, term.nulls_first, collation);
+        order_by.push((expr, key));
+    }
+
+    let mut bound = BoundSelect {
+        source,
+        filter,
+        joins: Vec::new(),
+        outputs,
+        distinct,
+        group_by,
+        aggregates,
+        having,
+        order_by,
+    };
+    bound.joins = joins;
+    /* an index lookup can read a joined table from its entries alone */
+    let columns = bound.columns();
+    for join in bound.joins.iter_mut() {
+        if let Some(index) = join.index() {
+            let range = join.positions();
+            let own: Vec<usize> = columns
+                .iter()
+                .filter(|pos| range.contains(pos))
+                .map(|pos| pos - range.start)
+                .collect();
+            join.covering = covers(index, join.table, &own);
+        }
+    }
+    Ok(bound)
+}
+
+/* the select list as expressions and their aliases, with * expanded to the tables' columns */
+fn result_exprs(columns: &[ResultColumn], source: &Source) -> Result<Vec<(Expr, Option<String>)>> {
+    let column = |table: &str, name: &str| {
+        let table = Some(table.to_string());
+        let name = name.to_string();
+        (Expr::Column { table, name }, None)
+    };
+    let mut exprs = Vec::new();
+    for result_column in columns {
+        match result_column {
+            ResultColumn::Star => exprs.extend(
+                source
+                    .star_columns()
+                    .into_iter()
+                    .map(|(table, name)| column(table, name)),
+            ),
+            ResultColumn::TableStar(name) => {
+                let source_table = source
+                    .tables
+                    .iter()
+                    .find(|source_table| identifiers_match(&source_table.name, name))
+                    .ok_or_else(|| ReaderError::NoSuchTable(name.clone()))?;
+                exprs.extend(
+                    source_table
+                        .table
+                        .columns
+                        .iter()
+                        .map(|table_column| column(&source_table.name, &table_column.name)),
+                );
+            }
+            ResultColumn::Expr { expr, alias } => exprs.push((expr.clone(), alias.clone())),
+        }
+    }
+    Ok(exprs)
+}
+
+/* the result column the name is the alias of */
+fn aliased<'e>(name: &str, result_columns: &'e [(Expr, Option<String>)]) -> Option<&'e Expr> {
+    result_columns
+        .iter()
+        .find(|(_, alias)| {
+            alias
+                .as_deref()
+                .is_some_and(|alias| alias.eq_ignore_ascii_case(name))
+        })
+        .map(|(expr, _)| expr)
+}
+
+/* names that aren't columns of the tables can be aliases of result columns, like in sqlite */
+fn with_aliases(expr: &Expr, result_columns: &[(Expr, Option<String>)], source: &Source) -> Expr {
+    expr.replace_columns(&|name| {
+        if !matches!(
+            source.resolve(None, name),
+            Err(ReaderError::NoSuchColumn(_))
+        ) {
+            return None;
+        }
+        aliased(name, result_columns).cloned()
+    })
+}
+
+/* an integer GROUP BY or ORDER BY term picks a result column by its 1-based position */
+fn result_column(
+    expr: &Expr,
+    result_columns: &[(Expr, Option<String>)],
+    source: &Source,
+    clause: &str,
+    term: usize,
+) -> Result<Expr> {
+    match expr {
+        Expr::Literal(Value::Integer(n)) => usize::try_from(*n)
+            .ok()
+            .and_then(|n| n.checked_sub(1))
+            .and_then(|pos| result_columns.get(pos))
+            .map(|(expr, _)| expr.clone())
+            .ok_or_else(|| {
+                ReaderError::Parse(format!(
+                    "{} {} term out of range - should be between 1 and {}",
+                    ordinal(term + 1),
+                    clause,
+                    result_columns.len()
+                ))
+            }),
+        expr => Ok(with_aliases(expr, result_columns, source)),
+    }
+}
+
+fn ordinal(n: usize) -> String {
+    let suffix = match (n % 10, n % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{}{}", n, suffix)
+}
+
+/*
+    row_id seeks when the filter pins the INTEGER PRIMARY KEY to some keys, else an index when
+    it pins the index's leading column, else row_id seeks over a range of them. failing those,
+    the table or an index when walking it one way or the other yields the ORDER BY. without
+    ORDER BY, an index that brings DISTINCT duplicates together so they can be skipped. a full
+    table scan otherwise
+*/
+fn plan_scan<'a>(
+    schema: &'a Schema,
+    table: &'a Table,
+    filter: Option<&BoundExpr>,
+    order_by: &[(BoundExpr, SortKey)],
+    distinct: &[(BoundExpr, Collation)],
+) -> Plan<'a> {
+    let rowid_lookup = filter
+        .zip(table.rowid_alias)
+        .and_then(|(filter, alias)| filter.rowid_lookup(alias));
+    if let Some(lookup @ RowIdLookup::Keys(_)) = rowid_lookup {
+        return Plan::ordered(Scan::RowId(lookup), table, order_by);
+    }
+    let lookup_scan = filter.and_then(|filter| {
+        let index_on =
+            |pos: usize, collation| schema.index_on(table, &table.columns[pos].name, collation);
+        let (pos, collation, lookup) =
+            filter.index_lookup(table, &|pos, collation| index_on(pos, collation).is_some())?;
+        let index = index_on(pos, collation)?;
+        Some(Scan::Index(index, lookup))
+    });
+    if let Some(scan) = lookup_scan.or(rowid_lookup.map(Scan::RowId)) {
+        return Plan::ordered(scan, table, order_by);
+    }
+
+    if !order_by.is_empty() && !table.without_rowid {
+        let indexes = schema
+            .indexes_of(table)
+            .filter(|index| !index.partial && !index.columns.is_empty())
+            .map(|index| Scan::Index(index, IndexLookup::All));
+        let ordered = iter::once(Scan::Table)
+            .chain(indexes)
+            .map(|scan| Plan::ordered(scan, table, order_by))
+            .find(|plan| provides_order(&plan.order(table), order_by));
+        if let Some(plan) = ordered {
+            return plan;
+        }
+    }
+
+    if order_by.is_empty()
+        && !distinct.is_empty()
+        && !table.without_rowid
+        && !groups_duplicates(&scan_order(&Scan::Table, table), distinct)
+    {
+        let grouping_index = schema
+            .indexes_of(table)
+            .filter(|index| !index.partial && !index.columns.is_empty())
+            .map(|index| Scan::Index(index, IndexLookup::All))
+            .find(|scan| groups_duplicates(&scan_order(scan, table), distinct));
+        if let Some(scan) = grouping_index {
+            return Plan::forwards(scan);
+        }
+    }
+    Plan::forwards(Scan::Table)
+}
+
+/*
+    the columns a scan's rows come out sorted by. a table is in row_id order, an index in key
+    order with the row_id breaking ties, less the leading column when one key pins it
+*/
+fn scan_order(scan: &Scan, table: &Table) -> Vec<(usize, SortKey)> {
+    let rowid_order = table
+        .rowid_alias
+        .map(|pos| (pos, SortKey::new(false, None, Collation::Binary)));
+    let (index, lookup) = match scan {
+        Scan::Table | Scan::RowId(_) => return rowid_order.into_iter().collect(),
+        Scan::Index(index, lookup) => (index, lookup),
+    };
+
+    let mut order = Vec::new();
+    let mut complete = true;
+    for (i, column) in index.columns.iter().enumerate() {
+        match (
+            table.column_position(&column.name),
+            index.collation(i, table),
+        ) {
+            (Some(pos), Some(collation)) => {
+                order.push((pos, SortKey::new(column.descending, None, collation)))
+            }
+            _ => {
+                complete = false;
+                break;
+            }
+        }
+    }
+    if complete {
+        order.extend(rowid_order);
+    }
+
+    let single_key = match lookup {
+        IndexLookup::Keys(keys) => keys.iter().all(|key| *key == keys[0]),
+        IndexLookup::Prefixes(_) | IndexLookup::All => false,
+    };
+    if single_key && !order.is_empty() {
+        order.remove(0);
+    }
+    order
+}
+
+/* the order a scan's rows come out in when it walks backwards, NULLs moving to the other end */
+fn reversed(order: Vec<(usize, SortKey)>) -> Vec<(usize, SortKey)> {
+    order
+        .into_iter()
+        .map(|(pos, key)| {
+            let key = SortKey {
+                descending: !key.descending,
+                nulls_first: !key.nulls_first,
+                ..key
+            };
+            (pos, key)
+        })
+        .collect()
+}
+
+fn provides_order(order: &[(usize, SortKey)], order_by: &[(BoundExpr, SortKey)]) -> bool {
+    order_by.len() <= order.len()
+        && order_by
+            .iter()
+            .zip(order)
+            .all(|((expr, key), (pos, order_key))| {
+                *expr == BoundExpr::Column(*pos) && key == order_key
+            })
+}
+
+/*
+    whether rows in this order have their DISTINCT duplicates next to each other. they do when
+    the leading columns of the order are exactly the select list's, in any order, each sorted
+    by the collation DISTINCT compares it by
+*/
+fn groups_duplicates(order: &[(usize, SortKey)], distinct: &[(BoundExpr, Collation)]) -> bool {
+    let leading: Vec<&(usize, SortKey)> = order
+        .iter()
+        .take_while(|(pos, key)| {
+            distinct.iter().any(|(expr, collation)| {
+                *expr == BoundExpr::Column(*pos) && *collation == key.collation
+            })
+        })
+        .collect();
+    !distinct.is_empty()
+        && distinct.iter().all(|(expr, collation)| {
+            leading
+                .iter()
+                .any(|(pos, key)| *expr == BoundExpr::Column(*pos) && *collation == key.collation)
+        })
+}
+
+/* scans the first table and extends each of its rows with the joined tables' rows */
+fn run_scan<S: PageSource>(
+    plan: &Plan,
+    bound: &BoundSelect,
+    builder: &mut PageReaderBuilder<S>,
+    options: &QueryOptions,
+    emit: &mut impl FnMut(Vec<Value>) -> Result<ControlFlow<()>>,
+) -> Result<()> {
+    let (table, filter) = (bound.table(), bound.filter.as_ref());
+    let encoding = builder.header().text_encoding;
+    let mut joiner = Joiner::new(&bound.joins, encoding, options.join_memory);
+    let mut emit = |builder: &mut PageReaderBuilder<S>, row: Vec<Value>| {
+        joiner.extend(builder, row, &mut *emit)
+    };
+    let backwards = plan.backwards;
+    match &plan.scan {
+        Scan::Table => data_filter_processor::perform_full_table_scan(
+            table, builder, backwards, filter, &mut emit,
+        ),
+        Scan::RowId(lookup) => data_filter_processor::perform_rowid_scan(
+            table, builder, lookup, backwards, filter, &mut emit,
+        ),
+        Scan::Index(index, lookup) => {
+            let mut columns = bound.columns();
+            columns.retain(|pos| *pos < table.columns.len());
+            let covering = covers(index, table, &columns);
+            data_filter_processor::perform_index_scan(
+                index, table, builder, lookup, backwards, covering, filter, &mut emit,
+            )
+        }
+    }
+}
+
+/* whether the index entries hold every one of the columns, the row_id alias included */
+fn covers(index: &Index, table: &Table, columns: &[usize]) -> bool {
+    columns.iter().all(|pos| {
+        table.rowid_alias == Some(*pos)
+            || index
+                .columns
+                .iter()
+                .any(|column| table.column_position(&column.name) == Some(*pos))
+    })
+}
+
+fn write_row(out: &mut impl Write, row: &[Value]) -> Result<()> {
+    let row: Vec<String> = row.iter().map(Value::to_string).collect();
+    writeln!(out, "{}", row.join("|"))?;
+    Ok(())
+}
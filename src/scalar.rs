@@ -0,0 +1,500 @@
+use crate::ast::BinaryOp;
+use crate::db_header::TextEncoding;
+use crate::error::{ReaderError, Result};
+use crate::printf::printf;
+use crate::value::{Collation, Value};
+use std::cmp::Ordering;
+
+/* the core functions that compute a value from each row, see lang_corefunc.html */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScalarFunction {
+    Length,
+    Lower,
+    Upper,
+    Substr,
+    Trim,
+    LTrim,
+    RTrim,
+    Replace,
+    Instr,
+    Abs,
+    Round,
+    Typeof,
+    Hex,
+    Quote,
+    Printf,
+    Unicode,
+    Char,
+    Coalesce,
+    IfNull,
+    NullIf,
+    /* min and max with several arguments, with one they are aggregates */
+    Min,
+    Max,
+}
+
+impl ScalarFunction {
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name.to_ascii_lowercase().as_str() {
+            "length" => ScalarFunction::Length,
+            "lower" => ScalarFunction::Lower,
+            "upper" => ScalarFunction::Upper,
+            "substr" | "substring" => ScalarFunction::Substr,
+            "trim" => ScalarFunction::Trim,
+            "ltrim" => ScalarFunction::LTrim,
+            "rtrim" => ScalarFunction::RTrim,
+            "replace" => ScalarFunction::Replace,
+            "instr" => ScalarFunction::Instr,
+            "abs" => ScalarFunction::Abs,
+            "round" => ScalarFunction::Round,
+            "typeof" => ScalarFunction::Typeof,
+            "hex" => ScalarFunction::Hex,
+            "quote" => ScalarFunction::Quote,
+            "printf" | "format" => ScalarFunction::Printf,
+            "unicode" => ScalarFunction::Unicode,
+            "char" => ScalarFunction::Char,
+            "coalesce" => ScalarFunction::Coalesce,
+            "ifnull" => ScalarFunction::IfNull,
+            "nullif" => ScalarFunction::NullIf,
+            "min" => ScalarFunction::Min,
+            "max" => ScalarFunction::Max,
+            _ => return None,
+        })
+    }
+
+    pub fn accepts(&self, arg_count: usize) -> bool {
+        match self {
+            ScalarFunction::Substr => (2..=3).contains(&arg_count),
+            ScalarFunction::Trim
+            | ScalarFunction::LTrim
+            | ScalarFunction::RTrim
+            | ScalarFunction::Round => (1..=2).contains(&arg_count),
+            ScalarFunction::Replace => arg_count == 3,
+            ScalarFunction::Instr | ScalarFunction::IfNull | ScalarFunction::NullIf => {
+                arg_count == 2
+            }
+            ScalarFunction::Printf => arg_count >= 1,
+            ScalarFunction::Char => true,
+            ScalarFunction::Coalesce | ScalarFunction::Min | ScalarFunction::Max => arg_count >= 2,
+            _ => arg_count == 1,
+        }
+    }
+
+    /* coalesce and ifnull stop at the first argument that isn't NULL, the rest go unevaluated */
+    pub fn is_short_circuit(&self) -> bool {
+        matches!(self, ScalarFunction::Coalesce | ScalarFunction::IfNull)
+    }
+
+    /* `collation` is how nullif, min and max compare text */
+    pub fn call(
+        &self,
+        args: &[Value],
+        collation: Collation,
+        encoding: TextEncoding,
+    ) -> Result<Value> {
+        let null_arg = args.iter().any(Value::is_null);
+        Ok(match self {
+            ScalarFunction::Coalesce | ScalarFunction::IfNull => args
+                .iter()
+                .find(|arg| !arg.is_null())
+                .cloned()
+                .unwrap_or(Value::Null),
+            ScalarFunction::NullIf => {
+                if args[0].compare_collated(&args[1], collation, encoding) == Ordering::Equal {
+                    Value::Null
+                } else {
+                    args[0].clone()
+                }
+            }
+            ScalarFunction::Typeof => Value::Text(
+                match args[0] {
+                    Value::Null => "null",
+                    Value::Integer(_) => "integer",
+                    Value::Real(_) => "real",
+                    Value::Text(_) => "text",
+                    Value::Blob(_) => "blob",
+                }
+                .to_string(),
+            ),
+            ScalarFunction::Quote => Value::Text(quote(&args[0])),
+            ScalarFunction::Hex => {
+                let bytes = match &args[0] {
+                    Value::Null => Vec::new(),
+                    Value::Blob(blob) => blob.clone(),
+                    value => encoding.encode(&value.to_string()),
+                };
+                Value::Text(hex(&bytes))
+            }
+            ScalarFunction::Char => Value::Text(
+                args.iter()
+                    .map(|arg| {
+                        u32::try_from(arg.to_integer())
+                            .ok()
+                            .and_then(char::from_u32)
+                            .unwrap_or(char::REPLACEMENT_CHARACTER)
+                    })
+                    .collect(),
+            ),
+            ScalarFunction::Printf => match &args[0] {
+                Value::Null => Value::Null,
+                format => Value::Text(printf(&format.to_string(), &args[1..])),
+            },
+            /* min takes the last of equal arguments, max the first */
+            ScalarFunction::Min | ScalarFunction::Max if null_arg => Value::Null,
+            ScalarFunction::Min => args
+                .iter()
+                .reduce(
+                    |min, arg| match arg.compare_collated(min, collation, encoding) {
+                        Ordering::Greater => min,
+                        _ => arg,
+                    },
+                )
+                .cloned()
+                .unwrap_or(Value::Null),
+            ScalarFunction::Max => args
+                .iter()
+                .reduce(
+                    |max, arg| match arg.compare_collated(max, collation, encoding) {
+                        Ordering::Greater => arg,
+                        _ => max,
+                    },
+                )
+                .cloned()
+                .unwrap_or(Value::Null),
+            /* everything below is NULL when any argument is */
+            _ if null_arg => Value::Null,
+            ScalarFunction::Length => Value::Integer(match &args[0] {
+                Value::Blob(blob) => blob.len() as i64,
+                /* text stops at the first NUL, like the C string sqlite counts */
+                value => value.to_string().chars().take_while(|c| *c != '\0').count() as i64,
+            }),
+            ScalarFunction::Lower => Value::Text(args[0].to_string().to_ascii_lowercase()),
+            ScalarFunction::Upper => Value::Text(args[0].to_string().to_ascii_uppercase()),
+            ScalarFunction::Substr => substr(
+                &args[0],
+                args[1].to_integer(),
+                args.get(2).map(Value::to_integer),
+            ),
+            ScalarFunction::Trim | ScalarFunction::LTrim | ScalarFunction::RTrim => {
+                let text = args[0].to_string();
+                let trimmed: Vec<char> = match args.get(1) {
+                    Some(chars) => chars.to_string().chars().collect(),
+                    None => vec![' '],
+                };
+                let pattern = |c: char| trimmed.contains(&c);
+                Value::Text(
+                    match self {
+                        ScalarFunction::LTrim => text.trim_start_matches(pattern),
+                        ScalarFunction::RTrim => text.trim_end_matches(pattern),
+                        _ => text.trim_matches(pattern),
+                    }
+                    .to_string(),
+                )
+            }
+            ScalarFunction::Replace => {
+                let pattern = args[1].to_string();
+                if pattern.is_empty() {
+                    args[0].clone()
+                } else {
+                    Value::Text(args[0].to_string().replace(&pattern, &args[2].to_string()))
+                }
+            }
+            ScalarFunction::Instr => Value::Integer(match (&args[0], &args[1]) {
+                (Value::Blob(haystack), Value::Blob(needle)) => {
+                    if needle.is_empty() {
+                        1
+                    } else {
+                        haystack
+                            .windows(needle.len())
+                            .position(|window| window == needle.as_slice())
+                            .map_or(0, |pos| pos as i64 + 1)
+                    }
+                }
+                (haystack, needle) => {
+                    let haystack = haystack.to_string();
+                    haystack
+                        .find(&needle.to_string())
+                        .map_or(0, |pos| haystack[..pos].chars().count() as i64 + 1)
+                }
+            }),
+            ScalarFunction::Abs => match &args[0] {
+                Value::Integer(int) => {
+                    Value::Integer(int.checked_abs().ok_or(ReaderError::IntegerOverflow)?)
+                }
+                value => Value::Real(value.to_real().abs()),
+            },
+            ScalarFunction::Round => {
+                let digits = args
+                    .get(1)
+                    .map_or(0, |digits| digits.to_integer().clamp(0, 30));
+                Value::Real(round(args[0].to_real(), digits))
+            }
+            ScalarFunction::Unicode => match args[0].to_string().chars().next() {
+                Some(c) => Value::Integer(c as i64),
+                None => Value::Null,
+            },
+        })
+    }
+}
+
+/* substr(X, Y, Z) counts characters in text and bytes in blobs, Y from 1 or back from the end */
+fn substr(value: &Value, start: i64, len: Option<i64>) -> Value {
+    let total = match value {
+        Value::Blob(blob) => blob.len() as i64,
+        value => value.to_string().chars().count() as i64,
+    };
+    /* a negative length takes the characters before the start */
+    let (mut start, mut len, backwards) = match len {
+        Some(len) if len < 0 => (start, len.saturating_neg(), true),
+        Some(len) => (start, len, false),
+        None => (start, i64::from(i32::MAX), false),
+    };
+    if start < 0 {
+        start += total;
+        if start < 0 {
+            len = (len + start).max(0);
+            start = 0;
+        }
+    } else if start > 0 {
+        start -= 1;
+    } else if len > 0 {
+        len -= 1;
+    }
+    if backwards {
+        start -= len;
+        if start < 0 {
+            len += start;
+            start = 0;
+        }
+    }
+    let (start, len) = (start as usize, len.max(0) as usize);
+    match value {
+        Value::Blob(blob) => Value::Blob(blob.iter().skip(start).take(len).copied().collect()),
+        value => Value::Text(value.to_string().chars().skip(start).take(len).collect()),
+    }
+}
+
+/* rounds half away from zero, to whole numbers directly and otherwise on the decimal digits */
+fn round(real: f64, digits: i64) -> f64 {
+    /* reals this large have no fraction left to round */
+    if real.abs() > 4503599627370496.0 {
+        real
+    } else if digits == 0 {
+        (real + if real < 0.0 { -0.5 } else { 0.5 }) as i64 as f64
+    } else {
+        let text = printf("%!.*f", &[Value::Integer(digits), Value::Real(real)]);
+        text.parse().unwrap_or(real)
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02X}", byte)).collect()
+}
+
+/* the value as an SQL literal */
+fn quote(value: &Value) -> String {
+    match value {
+        Value::Null => "NULL".to_string(),
+        Value::Integer(int) => int.to_string(),
+        /* 15 significant digits unless that doesn't read back as the same real */
+        Value::Real(real) => {
+            let short = printf("%!0.15g", &[Value::Real(*real)]);
+            if short.parse::<f64>().ok() == Some(*real) {
+                short
+            } else {
+                printf("%!0.20e", &[Value::Real(*real)])
+            }
+        }
+        Value::Text(text) => format!("'{}'", text.replace('\'', "''")),
+        Value::Blob(blob) => format!("X'{}'", hex(blob)),
+    }
+}
+
+/* the arithmetic, concatenation and bitwise operators, NULL when either operand is */
+pub fn binary(op: BinaryOp, left: &Value, right: &Value) -> Value {
+    if left.is_null() || right.is_null() {
+        return Value::Null;
+    }
+    match op {
+        BinaryOp::Concat => Value::Text(format!("{}{}", left, right)),
+        BinaryOp::BitAnd => Value::Integer(left.to_integer() & right.to_integer()),
+        BinaryOp::BitOr => Value::Integer(left.to_integer() | right.to_integer()),
+        BinaryOp::ShiftLeft | BinaryOp::ShiftRight => {
+            shift(op, left.to_integer(), right.to_integer())
+        }
+        _ => arithmetic(op, left.to_number(), right.to_number()),
+    }
+}
+
+/* integers stay integers until they overflow, NaN results are NULL */
+fn arithmetic(op: BinaryOp, left: Value, right: Value) -> Value {
+    if let (Value::Integer(left), Value::Integer(right)) = (&left, &right) {
+        let (left, right) = (*left, *right);
+        let exact = match op {
+            BinaryOp::Add => left.checked_add(right),
+            BinaryOp::Subtract => left.checked_sub(right),
+            BinaryOp::Multiply => left.checked_mul(right),
+            BinaryOp::Divide if right == 0 => return Value::Null,
+            BinaryOp::Divide => left.checked_div(right),
+            BinaryOp::Remainder if right == 0 => return Value::Null,
+            /* i64::MIN % -1 overflows in Rust, but is 0 like any other x % -1 */
+            BinaryOp::Remainder => Some(left.wrapping_rem(right)),
+            _ => unreachable!("not an arithmetic operator"),
+        };
+        if let Some(int) = exact {
+            return Value::Integer(int);
+        }
+    }
+    let result = match op {
+        /* a real remainder is taken on the integer parts */
+        BinaryOp::Remainder => {
+            let divisor = right.to_integer();
+            if divisor == 0 {
+                return Value::Null;
+            }
+            left.to_integer().wrapping_rem(divisor) as f64
+        }
+        BinaryOp::Divide if right.to_real() == 0.0 => return Value::Null,
+        BinaryOp::Add => left.to_real() + right.to_real(),
+        BinaryOp::Subtract => left.to_real() - right.to_real(),
+        BinaryOp::Multiply => left.to_real() * right.to_real(),
+        BinaryOp::Divide => left.to_real() / right.to_real(),
+        _ => unreachable!("not an arithmetic operator"),
+    };
+    if result.is_nan() {
+        Value::Null
+    } else {
+        Value::Real(result)
+    }
+}
+
+/* a negative amount shifts the other way, and shifting 64 or more bits leaves only the sign */
+fn shift(op: BinaryOp, int: i64, amount: i64) -> Value {
+    let (left, amount) = match (op == BinaryOp::ShiftLeft, amount < 0) {
+        (left, false) => (left, amount),
+        (left, true) => (!left, amount.saturating_neg()),
+    };
+    Value::Integer(if amount >= 64 {
+        if int >= 0 || left {
+            0
+        } else {
+            -1
+        }
+    } else if left {
+        ((int as u64) << amount) as i64
+    } else {
+        int >> amount
+    })
+}
+
+/* unary minus is 0 - x */
+pub fn negate(value: &Value) -> Value {
+    binary(BinaryOp::Subtract, &Value::Integer(0), value)
+}
+
+pub fn bit_not(value: &Value) -> Value {
+    match value {
+        Value::Null => Value::Null,
+        value => Value::Integer(!value.to_integer()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(name: &str, args: &[Value]) -> Value {
+        let function = ScalarFunction::from_name(name).unwrap();
+        assert!(function.accepts(args.len()), "{}/{}", name, args.len());
+        function
+            .call(args, Collation::Binary, TextEncoding::Utf8)
+            .unwrap()
+    }
+
+    fn text(text: &str) -> Value {
+        Value::Text(text.to_string())
+    }
+
+    /* what sqlite3 returns for each function given a NULL argument */
+    #[test]
+    fn null_arguments_match_sqlite() {
+        let null = Value::Null;
+        let one = Value::Integer(1);
+        let cases: Vec<(&str, Vec<Value>, Value)> = vec![
+            ("length", vec![null.clone()], null.clone()),
+            ("lower", vec![null.clone()], null.clone()),
+            ("upper", vec![null.clone()], null.clone()),
+            ("substr", vec![null.clone(), one.clone()], null.clone()),
+            ("substr", vec![text("abc"), null.clone()], null.clone()),
+            (
+                "substr",
+                vec![text("abc"), one.clone(), null.clone()],
+                null.clone(),
+            ),
+            ("trim", vec![null.clone()], null.clone()),
+            ("trim", vec![text("x"), null.clone()], null.clone()),
+            ("ltrim", vec![null.clone()], null.clone()),
+            ("rtrim", vec![text("a"), null.clone()], null.clone()),
+            (
+                "replace",
+                vec![null.clone(), text("a"), text("b")],
+                null.clone(),
+            ),
+            (
+                "replace",
+                vec![text("a"), null.clone(), text("b")],
+                null.clone(),
+            ),
+            (
+                "replace",
+                vec![text("a"), text("a"), null.clone()],
+                null.clone(),
+            ),
+            ("instr", vec![null.clone(), text("a")], null.clone()),
+            ("instr", vec![text("a"), null.clone()], null.clone()),
+            ("abs", vec![null.clone()], null.clone()),
+            ("round", vec![null.clone()], null.clone()),
+            ("round", vec![Value::Real(1.5), null.clone()], null.clone()),
+            ("unicode", vec![null.clone()], null.clone()),
+            ("typeof", vec![null.clone()], text("null")),
+            ("hex", vec![null.clone()], text("")),
+            ("quote", vec![null.clone()], text("NULL")),
+            ("printf", vec![null.clone(), one.clone()], null.clone()),
+            ("printf", vec![text("%d"), null.clone()], text("0")),
+            ("char", vec![null.clone()], text("\0")),
+            ("coalesce", vec![null.clone(), null.clone()], null.clone()),
+            (
+                "coalesce",
+                vec![null.clone(), Value::Integer(2), null.clone()],
+                Value::Integer(2),
+            ),
+            ("ifnull", vec![null.clone(), text("b")], text("b")),
+            ("nullif", vec![null.clone(), one.clone()], null.clone()),
+            ("nullif", vec![one.clone(), null.clone()], one.clone()),
+            ("nullif", vec![null.clone(), null.clone()], null.clone()),
+            ("min", vec![one.clone(), null.clone()], null.clone()),
+            ("max", vec![null.clone(), Value::Integer(2)], null.clone()),
+        ];
+        for (name, args, expected) in cases {
+            assert_eq!(call(name, &args), expected, "{}({:?})", name, args);
+        }
+    }
+
+    #[test]
+    fn empty_and_non_null_edges_match_sqlite() {
+        assert_eq!(call("unicode", &[text("")]), Value::Null);
+        assert_eq!(call("char", &[]), text(""));
+        assert_eq!(
+            call("replace", &[text("abc"), text(""), text("x")]),
+            text("abc")
+        );
+        assert_eq!(
+            call(
+                "min",
+                &[Value::Integer(2), Value::Integer(1), Value::Integer(3)]
+            ),
+            Value::Integer(1)
+        );
+        assert_eq!(call("max", &[text("a"), text("B")]), text("a"));
+    }
+}
@@ -0,0 +1,433 @@
+use crate::ast::{ColumnDef, Expr, IndexedColumn};
+use crate::bound_expr::BoundExpr;
+use crate::cursor::{TableCursor, TableRow};
+use crate::db_header::TextEncoding;
+use crate::error::{ReaderError, Result};
+use crate::join::Source;
+use crate::page_reader::PageReaderBuilder;
+use crate::page_source::PageSource;
+use crate::parser::{identifiers_match, parse_create_index, parse_create_table, parse_expr};
+use crate::value::{Collation, Value};
+
+/* the sqlite_schema table is rooted at page 1 */
+pub const SCHEMA_ROOT_PAGE: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjectType {
+    Table,
+    Index,
+    View,
+    Trigger,
+}
+
+impl ObjectType {
+    fn from_type_name(type_name: &str) -> Option<Self> {
+        match type_name {
+            "table" => Some(ObjectType::Table),
+            "index" => Some(ObjectType::Index),
+            "view" => Some(ObjectType::View),
+            "trigger" => Some(ObjectType::Trigger),
+            _ => None,
+        }
+    }
+}
+
+/* one row of sqlite_schema: type, name, tbl_name, rootpage, sql */
+#[derive(Debug, Clone)]
+pub struct SchemaEntry {
+    pub object_type: ObjectType,
+    pub name: String,
+    pub tbl_name: String,
+    pub root_page: u32,
+    /* NULL for the automatic indexes behind UNIQUE and PRIMARY KEY constraints */
+    pub sql: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Table {
+    pub entry: SchemaEntry,
+    /* empty when the CREATE TABLE statement isn't one the parser understands */
+    pub columns: Vec<ColumnDef>,
+    /* the INTEGER PRIMARY KEY column, which reads from the row_id rather than the record */
+    pub rowid_alias: Option<usize>,
+    /* index into the stored record for each column, None for VIRTUAL generated columns */
+    pub record_positions: Vec<Option<usize>>,
+    pub without_rowid: bool,
+    /* each column's DEFAULT, which rows written before an ALTER TABLE ADD COLUMN read */
+    pub defaults: Vec<Computed>,
+    /* the VIRTUAL generated columns, each after the generated columns its expression reads */
+    pub generated: Vec<(usize, Computed)>,
+    /* names of the indexes on this table, in schema order */
+    pub indexes: Vec<String>,
+    /* the keys of the PRIMARY KEY and UNIQUE indexes, the Nth for sqlite_autoindex_<table>_<N> */
+    pub autoindexes: Vec<Vec<IndexedColumn>>,
+    /* a CREATE VIRTUAL TABLE, whose module computes its rows, so it has no b-tree (rootpage 0) */
+    pub virtual_table: bool,
+}
+
+/* an expression of the table's definition, bound to the table's own row */
+#[derive(Debug, Clone)]
+pub enum Computed {
+    Expr(BoundExpr),
+    /* one the reader can't evaluate, which fails the rows that need it */
+    Unsupported(String),
+}
+
+impl Computed {
+    pub fn eval(&self, row: &[Value], encoding: TextEncoding) -> Result<Value> {
+        match self {
+            Computed::Expr(expr) => expr.eval(row, encoding),
+            Computed::Unsupported(what) => Err(ReaderError::Unsupported(what.clone())),
+        }
+    }
+}
+
+impl Table {
+    pub fn name(&self) -> &str {
+        &self.entry.name
+    }
+
+    pub fn root_page(&self) -> u32 {
+        self.entry.root_page
+    }
+
+    pub fn column_names(&self) -> Vec<String> {
+        self.columns
+            .iter()
+            .map(|column| column.name.clone())
+            .collect()
+    }
+
+    pub fn column_position(&self, name: &str) -> Option<usize> {
+        self.columns
+            .iter()
+            .position(|column| identifiers_match(&column.name, name))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Index {
+    pub entry: SchemaEntry,
+    /* the indexed columns in key order, empty for indexes on expressions */
+    pub columns: Vec<IndexedColumn>,
+    /* a partial index only holds some of the table's rows */
+    pub partial: bool,
+}
+
+impl Index {
+    pub fn name(&self) -> &str {
+        &self.entry.name
+    }
+
+    pub fn table_name(&self) -> &str {
+        &self.entry.tbl_name
+    }
+
+    pub fn root_page(&self) -> u32 {
+        self.entry.root_page
+    }
+
+    /* the collation key column `pos` sorts by, the index's own or else the table column's */
+    pub fn collation(&self, pos: usize, table: &Table) -> Option<Collation> {
+        let column = &self.columns[pos];
+        let name = match &column.collation {
+            Some(name) => Some(name.as_str()),
+            None => table
+                .column_position(&column.name)
+                .and_then(|table_pos| table.columns[table_pos].collation()),
+        };
+        name.map_or(Some(Collation::Binary), Collation::from_name)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct View {
+    pub entry: SchemaEntry,
+}
+
+#[derive(Debug, Clone)]
+pub struct Trigger {
+    pub entry: SchemaEntry,
+}
+
+/* every object in sqlite_schema, read once when the database is opened */
+#[derive(Debug, Clone, Default)]
+pub struct Schema {
+    pub tables: Vec<Table>,
+    pub indexes: Vec<Index>,
+    pub views: Vec<View>,
+    pub triggers: Vec<Trigger>,
+}
+
+impl Schema {
+    /* walks the whole sqlite_schema b-tree, which spans several pages once the schema is large */
+    pub fn load<S: PageSource>(builder: &mut PageReaderBuilder<S>) -> Result<Self> {
+        let mut cursor = TableCursor::new(SCHEMA_ROOT_PAGE);
+        let mut entries = Vec::new();
+        for cell in cursor.iter(builder) {
+            entries.push(parse_entry(&cell?)?);
+        }
+        Ok(Self::from_entries(entries))
+    }
+
+    pub fn from_entries(entries: Vec<SchemaEntry>) -> Self {
+        let mut schema = Schema::default();
+        for entry in entries {
+            match entry.object_type {
+                ObjectType::Table => schema.tables.push(table(entry)),
+                ObjectType::Index => schema.indexes.push(index(entry)),
+                ObjectType::View => schema.views.push(View { entry }),
+                ObjectType::Trigger => schema.triggers.push(Trigger { entry }),
+            }
+        }
+
+        for index in schema.indexes.iter_mut() {
+            if let Some(table) = schema
+                .tables
+                .iter_mut()
+                .find(|table| identifiers_match(&table.entry.name, &index.entry.tbl_name))
+            {
+                table.indexes.push(index.entry.name.clone());
+                if index.entry.sql.is_none() {
+                    index.columns = autoindex_columns(table, &index.entry.name);
+                }
+            }
+        }
+        schema
+    }
+
+    /* views are known but not read, the reader doesn't run their SELECT */
+    pub fn table(&self, name: &str) -> Result<&Table> {
+        if let Some(table) = self
+            .tables
+            .iter()
+            .find(|table| identifiers_match(&table.entry.name, name))
+        {
+            return Ok(table);
+        }
+        if self
+            .views
+            .iter()
+            .any(|view| identifiers_match(&view.entry.name, name))
+        {
+            return Err(ReaderError::Unsupported(format!("view {}", name)));
+        }
+        Err(ReaderError::NoSuchTable(name.to_string()))
+    }
+
+    pub fn index(&self, name: &str) -> Option<&Index> {
+        self.indexes
+            .iter()
+            .find(|index| identifiers_match(&index.entry.name, name))
+    }
+
+    pub fn indexes_of<'a>(&'a self, table: &'a Table) -> impl Iterator<Item = &'a Index> {
+        table.indexes.iter().filter_map(|name| self.index(name))
+    }
+
+    /*
+        a full index whose leading column is `column` sorted by `collation`, so a comparison by
+        that collation can seek it. seeks follow the column's direction, see perform_index_scan
+    */
+    pub fn index_on<'a>(
+        &'a self,
+        table: &'a Table,
+        column: &str,
+        collation: Collation,
+    ) -> Option<&'a Index> {
+        /* a WITHOUT ROWID table's indexes end in its key rather than a row_id */
+        if table.without_rowid {
+            return None;
+        }
+        self.indexes_of(table).find(|index| {
+            !index.partial
+                && index
+                    .columns
+                    .first()
+                    .is_some_and(|first| identifiers_match(&first.name, column))
+                && index.collation(0, table) == Some(collation)
+        })
+    }
+}
+
+fn parse_entry(cell: &TableRow) -> Result<SchemaEntry> {
+    let rows = &cell.record.rows;
+    let corrupt = |reason: &str| {
+        ReaderError::corrupt(
+            SCHEMA_ROOT_PAGE,
+            0,
+            format!("sqlite_schema row {}: {}", cell.row_id, reason),
+        )
+    };
+    let text = |pos: usize| match rows.get(pos) {
+        Some(Value::Text(text)) => Ok(text.clone()),
+        _ => Err(corrupt("expected a text column")),
+    };
+
+    let type_name = text(0)?;
+    let object_type = ObjectType::from_type_name(&type_name)
+        .ok_or_else(|| corrupt(&format!("unknown object type {}", type_name)))?;
+    let root_page = match rows.get(3) {
+        Some(Value::Integer(page)) => u32::try_from(*page).map_err(|_| corrupt("bad rootpage"))?,
+        Some(Value::Null) | None => 0,
+        _ => return Err(corrupt("bad rootpage")),
+    };
+    let sql = match rows.get(4) {
+        Some(Value::Text(sql)) => Some(sql.clone()),
+        _ => None,
+    };
+
+    Ok(SchemaEntry {
+        object_type,
+        name: text(1)?,
+        tbl_name: text(2)?,
+        root_page,
+        sql,
+    })
+}
+
+fn table(entry: SchemaEntry) -> Table {
+    let definition = entry
+        .sql
+        .as_deref()
+        .and_then(|sql| parse_create_table(sql).ok());
+    let virtual_table = entry.root_page == 0;
+    let mut table = match definition {
+        Some(definition) => Table {
+            rowid_alias: definition.rowid_alias(),
+            record_positions: definition.record_positions(),
+            without_rowid: definition.without_rowid,
+            autoindexes: definition.autoindexes(),
+            virtual_table,
+            columns: definition.columns,
+            defaults: Vec::new(),
+            generated: Vec::new(),
+            indexes: Vec::new(),
+            entry,
+        },
+        None => Table {
+            entry,
+            columns: Vec::new(),
+            rowid_alias: None,
+            record_positions: Vec::new(),
+            without_rowid: false,
+            defaults: Vec::new(),
+            generated: Vec::new(),
+            indexes: Vec::new(),
+            autoindexes: Vec::new(),
+            virtual_table,
+        },
+    };
+
+    let source = Source::of_table(&table);
+    let defaults = table
+        .columns
+        .iter()
+        .map(|column| column_default(column, &source))
+        .collect();
+    let generated = generated_columns(&table, &source);
+    table.defaults = defaults;
+    table.generated = generated;
+    table
+}
+
+/* a bare word DEFAULT is text, except for the keywords sqlite gives a value of their own */
+fn column_default(column: &ColumnDef, source: &Source) -> Computed {
+    let Some(text) = column.default_value() else {
+        return Computed::Expr(BoundExpr::Literal(Value::Null));
+    };
+    let unsupported = || Computed::Unsupported(format!("DEFAULT {} of {}", text, column.name));
+    match parse_expr(text) {
+        Ok(Expr::Column { table: None, name }) => {
+            if name.eq_ignore_ascii_case("TRUE") {
+                Computed::Expr(BoundExpr::Literal(Value::Integer(1)))
+            } else if name.eq_ignore_ascii_case("FALSE") {
+                Computed::Expr(BoundExpr::Literal(Value::Integer(0)))
+            } else if name.to_ascii_uppercase().starts_with("CURRENT_") {
+                unsupported()
+            } else {
+                Computed::Expr(BoundExpr::Literal(Value::Text(name)))
+            }
+        }
+        Ok(expr) => BoundExpr::bind(&expr, source).map_or_else(|_| unsupported(), Computed::Expr),
+        Err(_) => unsupported(),
+    }
+}
+
+/* the VIRTUAL columns, ordered so that the ones another reads are computed first */
+fn generated_columns(table: &Table, source: &Source) -> Vec<(usize, Computed)> {
+    let mut pending: Vec<(usize, Computed)> = table
+        .columns
+        .iter()
+        .enumerate()
+        .filter(|(_, column)| column.generated() == Some(false))
+        .map(|(pos, column)| {
+            let text = column.generation().unwrap_or_default();
+            let computed = parse_expr(text)
+                .and_then(|expr| BoundExpr::bind(&expr, source))
+                .map_or_else(
+                    |_| Computed::Unsupported(format!("generated column {}", column.name)),
+                    Computed::Expr,
+                );
+            (pos, computed)
+        })
+        .collect();
+
+    let mut ordered = Vec::with_capacity(pending.len());
+    while !pending.is_empty() {
+        let ready = pending.iter().position(|(_, computed)| match computed {
+            Computed::Expr(expr) => {
+                let mut columns = Vec::new();
+                expr.columns(&mut columns);
+                columns
+                    .iter()
+                    .all(|column| pending.iter().all(|(pos, _)| pos != column))
+            }
+            Computed::Unsupported(_) => true,
+        });
+        match ready {
+            Some(ready) => ordered.push(pending.remove(ready)),
+            /* columns computed from each other, which sqlite refuses to create */
+            None => ordered.extend(pending.drain(..).map(|(pos, _)| {
+                let name = &table.columns[pos].name;
+                (
+                    pos,
+                    Computed::Unsupported(format!("generated column {}", name)),
+                )
+            })),
+        }
+    }
+    ordered
+}
+
+/* an automatic index's key, which its name numbers among the table's constraints */
+fn autoindex_columns(table: &Table, name: &str) -> Vec<IndexedColumn> {
+    let number = name
+        .get(..17)
+        .filter(|prefix| prefix.eq_ignore_ascii_case("sqlite_autoindex_"))
+        .and_then(|_| name.rsplit_once('_'))
+        .and_then(|(_, number)| number.parse::<usize>().ok());
+    number
+        .and_then(|number| table.autoindexes.get(number.checked_sub(1)?))
+        .cloned()
+        .unwrap_or_default()
+}
+
+fn index(entry: SchemaEntry) -> Index {
+    let definition = entry
+        .sql
+        .as_deref()
+        .and_then(|sql| parse_create_index(sql).ok());
+    match definition {
+        Some(definition) => Index {
+            columns: definition.columns,
+            partial: definition.condition.is_some(),
+            entry,
+        },
+        None => Index {
+            entry,
+            columns: Vec::new(),
+            partial: false,
+        },
+    }
+}
@@ -0,0 +1,248 @@
+use crate::db_header::TextEncoding;
+use crate::error::Result;
+use crate::spill::{row_size, SpillFile, SpillReader};
+use crate::value::{Collation, Value};
+use std::cmp::Ordering;
+
+/* how much the sorter holds in memory before it writes a sorted run to disk */
+pub const DEFAULT_SORT_MEMORY: usize = 64 * 1024 * 1024;
+
+/* runs merged at once, more than this are merged in several passes to bound open files */
+const MERGE_FAN_IN: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SortKey {
+    pub descending: bool,
+    pub nulls_first: bool,
+    pub collation: Collation,
+}
+
+impl SortKey {
+    /* NULLs come first ascending and last descending unless NULLS FIRST/LAST says otherwise */
+    pub fn new(descending: bool, nulls_first: Option<bool>, collation: Collation) -> Self {
+        Self {
+            descending,
+            nulls_first: nulls_first.unwrap_or(!descending),
+            collation,
+        }
+    }
+}
+
+/*
+    an external merge sort of rows by their key values. rows are sorted in memory until they
+    outgrow the memory budget, then written out as sorted runs that are merged at the end
+*/
+pub struct Sorter {
+    keys: Vec<SortKey>,
+    encoding: TextEncoding,
+    memory_budget: usize,
+    /* each row is its key values followed by the row itself */
+    rows: Vec<Vec<Value>>,
+    bytes: usize,
+    runs: Vec<SpillFile>,
+}
+
+impl Sorter {
+    pub fn new(keys: Vec<SortKey>, encoding: TextEncoding, memory_budget: usize) -> Self {
+        Self {
+            keys,
+            encoding,
+            memory_budget,
+            rows: Vec::new(),
+            bytes: 0,
+            runs: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, key_values: Vec<Value>, row: Vec<Value>) -> Result<()> {
+        let mut entry = key_values;
+        entry.extend(row);
+        self.bytes += row_size(&entry);
+        self.rows.push(entry);
+        if self.bytes > self.memory_budget {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    fn spill(&mut self) -> Result<()> {
+        self.sort_in_memory();
+        let mut run = SpillFile::create()?;
+        for entry in self.rows.drain(..) {
+            run.write_row(&entry)?;
+        }
+        self.bytes = 0;
+        self.runs.push(run);
+        Ok(())
+    }
+
+    /* a stable sort, so rows with equal keys keep the order they were pushed in */
+    fn sort_in_memory(&mut self) {
+        let comparator = self.comparator();
+        self.rows.sort_by(|a, b| comparator.compare(a, b));
+    }
+
+    fn comparator(&self) -> Comparator {
+        Comparator {
+            keys: self.keys.clone(),
+            encoding: self.encoding,
+        }
+    }
+
+    pub fn finish(mut self) -> Result<SortedRows> {
+        self.sort_in_memory();
+        let comparator = self.comparator();
+        let key_count = self.keys.len();
+        if self.runs.is_empty() {
+            return Ok(SortedRows {
+                key_count,
+                merge: Merge::new(Vec::new(), self.rows, comparator),
+            });
+        }
+
+        /* earlier runs hold earlier rows, merging them in order keeps the sort stable */
+        let mut runs = self.runs;
+        while runs.len() + 1 > MERGE_FAN_IN {
+            let rest = runs.split_off(MERGE_FAN_IN);
+            let readers = runs
+                .into_iter()
+                .map(SpillFile::into_reader)
+                .collect::<Result<Vec<_>>>()?;
+            let mut merged = SpillFile::create()?;
+            for entry in Merge::new(readers, Vec::new(), comparator.clone()) {
+                merged.write_row(&entry?)?;
+            }
+            runs = rest;
+            runs.push(merged);
+        }
+
+        let readers = runs
+            .into_iter()
+            .map(SpillFile::into_reader)
+            .collect::<Result<Vec<_>>>()?;
+        Ok(SortedRows {
+            key_count,
+            merge: Merge::new(readers, self.rows, comparator),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Comparator {
+    keys: Vec<SortKey>,
+    encoding: TextEncoding,
+}
+
+impl Comparator {
+    fn compare(&self, a: &[Value], b: &[Value]) -> Ordering {
+        for (pos, key) in self.keys.iter().enumerate() {
+            let (a, b) = (&a[pos], &b[pos]);
+            let ordering = match (a.is_null(), b.is_null()) {
+                (true, true) => Ordering::Equal,
+                (true, false) if key.nulls_first => Ordering::Less,
+                (true, false) => Ordering::Greater,
+                (false, true) if key.nulls_first => Ordering::Greater,
+                (false, true) => Ordering::Less,
+                (false, false) => {
+                    let ordering = a.compare_collated(b, key.collation, self.encoding);
+                    if key.descending {
+                        ordering.reverse()
+                    } else {
+                        ordering
+                    }
+                }
+            };
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+        Ordering::Equal
+    }
+}
+
+/*
+    a k-way merge of sorted runs and the sorted rows still in memory, which come last since they
+    were pushed last. picking the smallest head by a linear scan is fine for MERGE_FAN_IN runs
+*/
+struct Merge {
+    runs: Vec<SpillReader>,
+    heads: Vec<Option<Vec<Value>>>,
+    memory: std::vec::IntoIter<Vec<Value>>,
+    comparator: Comparator,
+}
+
+impl Merge {
+    fn new(runs: Vec<SpillReader>, memory: Vec<Vec<Value>>, comparator: Comparator) -> Self {
+        Self {
+            heads: Vec::new(),
+            runs,
+            memory: memory.into_iter(),
+            comparator,
+        }
+    }
+
+    fn fill_heads(&mut self) -> Result<()> {
+        if !self.heads.is_empty() {
+            return Ok(());
+        }
+        for run in self.runs.iter_mut() {
+            self.heads.push(run.next().transpose()?);
+        }
+        self.heads.push(self.memory.next());
+        Ok(())
+    }
+
+    fn next_entry(&mut self) -> Result<Option<Vec<Value>>> {
+        self.fill_heads()?;
+        let mut smallest: Option<usize> = None;
+        for (i, head) in self.heads.iter().enumerate() {
+            let head = match head {
+                Some(head) => head,
+                None => continue,
+            };
+            let is_smaller = match smallest {
+                Some(j) => {
+                    let current = self.heads[j].as_ref().unwrap();
+                    self.comparator.compare(head, current) == Ordering::Less
+                }
+                None => true,
+            };
+            if is_smaller {
+                smallest = Some(i);
+            }
+        }
+
+        let i = match smallest {
+            Some(i) => i,
+            None => return Ok(None),
+        };
+        let next = match self.runs.get_mut(i) {
+            Some(run) => run.next().transpose()?,
+            None => self.memory.next(),
+        };
+        Ok(std::mem::replace(&mut self.heads[i], next))
+    }
+}
+
+impl Iterator for Merge {
+    type Item = Result<Vec<Value>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_entry().transpose()
+    }
+}
+
+/* the sorted rows, with their key values stripped off */
+pub struct SortedRows {
+    key_count: usize,
+    merge: Merge,
+}
+
+impl Iterator for SortedRows {
+    type Item = Result<Vec<Value>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entry = self.merge.next()?;
+        Some(entry.map(|mut entry| entry.split_off(self.key_count)))
+    }
+}
@@ -0,0 +1,168 @@
+use crate::error::{ReaderError, Result};
+use crate::value::Value;
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static NEXT_SPILL_ID: AtomicUsize = AtomicUsize::new(0);
+
+const NULL_TAG: u8 = 0;
+const INTEGER_TAG: u8 = 1;
+const REAL_TAG: u8 = 2;
+const TEXT_TAG: u8 = 3;
+const BLOB_TAG: u8 = 4;
+
+/* rows written out to a temporary file once they no longer fit in memory, deleted on drop */
+pub struct SpillFile {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    rows: usize,
+}
+
+impl SpillFile {
+    pub fn create() -> Result<Self> {
+        let path = std::env::temp_dir().join(format!(
+            "sqlite-reader-{}-{}.spill",
+            std::process::id(),
+            NEXT_SPILL_ID.fetch_add(1, Ordering::Relaxed)
+        ));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        Ok(Self {
+            path,
+            writer: BufWriter::new(file),
+            rows: 0,
+        })
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /* a row is its value count followed by each value as a tag byte and its payload */
+    pub fn write_row(&mut self, row: &[Value]) -> Result<()> {
+        self.writer.write_all(&(row.len() as u32).to_le_bytes())?;
+        for value in row {
+            match value {
+                Value::Null => self.writer.write_all(&[NULL_TAG])?,
+                Value::Integer(int) => {
+                    self.writer.write_all(&[INTEGER_TAG])?;
+                    self.writer.write_all(&int.to_le_bytes())?;
+                }
+                Value::Real(real) => {
+                    self.writer.write_all(&[REAL_TAG])?;
+                    self.writer.write_all(&real.to_bits().to_le_bytes())?;
+                }
+                Value::Text(text) => self.write_bytes(TEXT_TAG, text.as_bytes())?,
+                Value::Blob(blob) => self.write_bytes(BLOB_TAG, blob)?,
+            }
+        }
+        self.rows += 1;
+        Ok(())
+    }
+
+    fn write_bytes(&mut self, tag: u8, bytes: &[u8]) -> Result<()> {
+        self.writer.write_all(&[tag])?;
+        self.writer.write_all(&(bytes.len() as u32).to_le_bytes())?;
+        self.writer.write_all(bytes)?;
+        Ok(())
+    }
+
+    /* flushes what was written and reads it back from the start */
+    pub fn into_reader(mut self) -> Result<SpillReader> {
+        self.writer.flush()?;
+        let mut file = self.writer.get_ref().try_clone()?;
+        file.seek(SeekFrom::Start(0))?;
+        Ok(SpillReader {
+            reader: BufReader::new(file),
+            remaining: self.rows,
+            _spill: self,
+        })
+    }
+}
+
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+pub struct SpillReader {
+    reader: BufReader<File>,
+    remaining: usize,
+    /* keeps the file around until the reader is done with it */
+    _spill: SpillFile,
+}
+
+impl SpillReader {
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut bytes = [0; N];
+        self.reader.read_exact(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    fn read_bytes(&mut self) -> Result<Vec<u8>> {
+        let len = u32::from_le_bytes(self.read_array()?) as usize;
+        let mut bytes = vec![0; len];
+        self.reader.read_exact(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    fn read_row(&mut self) -> Result<Vec<Value>> {
+        let len = u32::from_le_bytes(self.read_array()?) as usize;
+        let mut row = Vec::with_capacity(len);
+        for _ in 0..len {
+            let [tag] = self.read_array()?;
+            row.push(match tag {
+                NULL_TAG => Value::Null,
+                INTEGER_TAG => Value::Integer(i64::from_le_bytes(self.read_array()?)),
+                REAL_TAG => Value::Real(f64::from_bits(u64::from_le_bytes(self.read_array()?))),
+                TEXT_TAG => Value::Text(String::from_utf8(self.read_bytes()?).map_err(|_| {
+                    ReaderError::Io(std::io::Error::new(
+                        ErrorKind::InvalidData,
+                        "spill file holds invalid utf-8",
+                    ))
+                })?),
+                BLOB_TAG => Value::Blob(self.read_bytes()?),
+                tag => {
+                    return Err(ReaderError::Io(std::io::Error::new(
+                        ErrorKind::InvalidData,
+                        format!("spill file holds unknown value tag {}", tag),
+                    )))
+                }
+            });
+        }
+        Ok(row)
+    }
+}
+
+impl Iterator for SpillReader {
+    type Item = Result<Vec<Value>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        Some(self.read_row())
+    }
+}
+
+/* a rough count of the heap and inline bytes a row takes, for memory budgets */
+pub fn row_size(row: &[Value]) -> usize {
+    row.iter()
+        .map(|value| {
+            std::mem::size_of::<Value>()
+                + match value {
+                    Value::Text(text) => text.len(),
+                    Value::Blob(blob) => blob.len(),
+                    _ => 0,
+                }
+        })
+        .sum::<usize>()
+        + std::mem::size_of::<Vec<Value>>()
+}
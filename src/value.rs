@@ -0,0 +1,381 @@
+use crate::db_header::TextEncoding;
+use std::cmp::Ordering;
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Integer(i64),
+    Real(f64),
+    Text(String),
+    Blob(Vec<u8>),
+}
+
+impl Value {
+    pub fn as_integer(&self) -> Option<i64> {
+        match self {
+            Value::Integer(int) => Some(*int),
+            _ => None,
+        }
+    }
+
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            Value::Text(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, Value::Null)
+    }
+
+    /* sqlite sort order: NULL < INTEGER/REAL < TEXT < BLOB */
+    pub fn compare(&self, other: &Value, encoding: TextEncoding) -> Ordering {
+        match (self, other) {
+            (Value::Null, Value::Null) => Ordering::Equal,
+            (Value::Null, _) => Ordering::Less,
+            (_, Value::Null) => Ordering::Greater,
+            (Value::Integer(a), Value::Integer(b)) => a.cmp(b),
+            (Value::Integer(a), Value::Real(b)) => compare_int_real(*a, *b),
+            (Value::Real(a), Value::Integer(b)) => compare_int_real(*b, *a).reverse(),
+            (Value::Real(a), Value::Real(b)) => a.partial_cmp(b).unwrap_or(Ordering::Equal),
+            (Value::Integer(_) | Value::Real(_), _) => Ordering::Less,
+            (_, Value::Integer(_) | Value::Real(_)) => Ordering::Greater,
+            (Value::Text(a), Value::Text(b)) => encoding.compare(a, b),
+            (Value::Text(_), Value::Blob(_)) => Ordering::Less,
+            (Value::Blob(_), Value::Text(_)) => Ordering::Greater,
+            (Value::Blob(a), Value::Blob(b)) => a.cmp(b),
+        }
+    }
+}
+
+/* column type affinity, see https://www.sqlite.org/datatype3.html#determination_of_column_affinity */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Affinity {
+    Integer,
+    Text,
+    Blob,
+    Real,
+    Numeric,
+}
+
+impl Affinity {
+    pub fn from_type_name(type_name: Option<&str>) -> Self {
+        let type_name = match type_name {
+            Some(type_name) => type_name.to_ascii_uppercase(),
+            None => return Affinity::Blob,
+        };
+        if type_name.contains("INT") {
+            Affinity::Integer
+        } else if ["CHAR", "CLOB", "TEXT"]
+            .iter()
+            .any(|name| type_name.contains(name))
+        {
+            Affinity::Text
+        } else if type_name.contains("BLOB") {
+            Affinity::Blob
+        } else if ["REAL", "FLOA", "DOUB"]
+            .iter()
+            .any(|name| type_name.contains(name))
+        {
+            Affinity::Real
+        } else {
+            Affinity::Numeric
+        }
+    }
+}
+
+/* the conversions a column's affinity makes to a value, see datatype3 section 3 */
+impl Value {
+    pub fn apply_affinity(self, affinity: Affinity) -> Value {
+        match (affinity, self) {
+            (Affinity::Text, value @ (Value::Integer(_) | Value::Real(_))) => {
+                Value::Text(value.to_string())
+            }
+            (Affinity::Integer | Affinity::Real | Affinity::Numeric, Value::Text(text)) => {
+                parse_numeric(&text).unwrap_or(Value::Text(text))
+            }
+            (_, value) => value,
+        }
+    }
+
+    /* NULL is neither true nor false, anything else is true when it's numerically non-zero */
+    pub fn is_true(&self) -> Option<bool> {
+        match self {
+            Value::Null => None,
+            Value::Integer(int) => Some(*int != 0),
+            Value::Real(real) => Some(*real != 0.0),
+            Value::Text(text) => Some(numeric_prefix(text) != 0.0),
+            Value::Blob(blob) => Some(numeric_prefix(&String::from_utf8_lossy(blob)) != 0.0),
+        }
+    }
+}
+
+/* text that is a well-formed integer or real literal, surrounding spaces allowed */
+pub fn parse_numeric(text: &str) -> Option<Value> {
+    let text = text.trim();
+    let digits = text.strip_prefix(['+', '-']).unwrap_or(text);
+    let well_formed = digits.chars().any(|c| c.is_ascii_digit())
+        && digits
+            .chars()
+            .all(|c| c.is_ascii_digit() || matches!(c, '.' | 'e' | 'E' | '+' | '-'));
+    if !well_formed {
+        return None;
+    }
+    if let Ok(int) = text.parse::<i64>() {
+        return Some(Value::Integer(int));
+    }
+    text.parse::<f64>().ok().map(Value::Real)
+}
+
+/* the number at the start of the text, 0 when there is none, like sqlite's text to real cast */
+pub fn numeric_prefix(text: &str) -> f64 {
+    let text = text.trim_start();
+    let (end, _) = scan_number(text);
+    text[..end].parse().unwrap_or(0.0)
+}
+
+/* the length of the number the text starts with, and whether it's written as an integer */
+fn scan_number(text: &str) -> (usize, bool) {
+    let bytes = text.as_bytes();
+    let digits_from = |mut end: usize| {
+        while bytes.get(end).is_some_and(u8::is_ascii_digit) {
+            end += 1;
+        }
+        end
+    };
+
+    let mut end = digits_from(usize::from(matches!(bytes.first(), Some(b'+' | b'-'))));
+    let mut integer = true;
+    if bytes.get(end) == Some(&b'.') {
+        end = digits_from(end + 1);
+        integer = false;
+    }
+    if matches!(bytes.get(end), Some(b'e' | b'E')) {
+        let sign = usize::from(matches!(bytes.get(end + 1), Some(b'+' | b'-')));
+        let exponent_end = digits_from(end + 1 + sign);
+        if exponent_end > end + 1 + sign {
+            end = exponent_end;
+            integer = false;
+        }
+    }
+    (end, integer)
+}
+
+/* the integer at the start of the text, 0 when there is none and clamped when it doesn't fit */
+pub fn integer_prefix(text: &str) -> i64 {
+    let text = text.trim_start();
+    let (negative, digits) = match text.as_bytes().first() {
+        Some(b'-') => (true, &text[1..]),
+        Some(b'+') => (false, &text[1..]),
+        _ => (false, text),
+    };
+    let mut int: i64 = 0;
+    for digit in digits.bytes().take_while(u8::is_ascii_digit) {
+        let digit = i64::from(digit - b'0');
+        let next = int.checked_mul(10).and_then(|int| {
+            if negative {
+                int.checked_sub(digit)
+            } else {
+                int.checked_add(digit)
+            }
+        });
+        int = match next {
+            Some(next) => next,
+            None if negative => return i64::MIN,
+            None => return i64::MAX,
+        };
+    }
+    int
+}
+
+/* whether the real is a whole number that an i64 holds exactly */
+fn is_integral(real: f64) -> bool {
+    real.fract() == 0.0 && (-9223372036854775808.0..9223372036854775808.0).contains(&real)
+}
+
+/* the conversions arithmetic, functions and CAST make, see lang_expr.html#castexpr */
+impl Value {
+    /*
+        the number a value is as an operand of arithmetic. text reads as far as it looks like a
+        number, as an integer if it's written as one that fits and as a real otherwise
+    */
+    pub fn to_number(&self) -> Value {
+        let text = match self {
+            Value::Null | Value::Integer(_) | Value::Real(_) => return self.clone(),
+            Value::Text(text) => text.trim_start(),
+            Value::Blob(_) => return Value::Text(self.to_string()).to_number(),
+        };
+        let (end, integer) = scan_number(text);
+        if !text[..end].bytes().any(|byte| byte.is_ascii_digit()) {
+            return Value::Integer(0);
+        }
+        match text[..end].parse::<i64>() {
+            Ok(int) if integer => Value::Integer(int),
+            _ => Value::Real(numeric_prefix(text)),
+        }
+    }
+
+    /* the value as an integer, reals truncated toward zero and clamped, NULL as 0 */
+    pub fn to_integer(&self) -> i64 {
+        match self {
+            Value::Null => 0,
+            Value::Integer(int) => *int,
+            Value::Real(real) => *real as i64,
+            Value::Text(text) => integer_prefix(text),
+            Value::Blob(blob) => integer_prefix(&String::from_utf8_lossy(blob)),
+        }
+    }
+
+    /* the value as a real, NULL as 0.0 */
+    pub fn to_real(&self) -> f64 {
+        match self {
+            Value::Null => 0.0,
+            Value::Integer(int) => *int as f64,
+            Value::Real(real) => *real,
+            Value::Text(text) => numeric_prefix(text),
+            Value::Blob(blob) => numeric_prefix(&String::from_utf8_lossy(blob)),
+        }
+    }
+
+    /* CAST(value AS type), where the type's affinity decides the conversion. NULL stays NULL */
+    pub fn cast(self, affinity: Affinity) -> Value {
+        match (affinity, self) {
+            (_, Value::Null) => Value::Null,
+            (Affinity::Integer, value) => Value::Integer(value.to_integer()),
+            (Affinity::Real, value) => Value::Real(value.to_real()),
+            (Affinity::Text, value @ (Value::Integer(_) | Value::Real(_) | Value::Blob(_))) => {
+                Value::Text(value.to_string())
+            }
+            (Affinity::Blob, Value::Text(text)) => Value::Blob(text.into_bytes()),
+            (Affinity::Blob, value @ (Value::Integer(_) | Value::Real(_))) => {
+                Value::Blob(value.to_string().into_bytes())
+            }
+            /* text becomes an integer when the number it starts with is a whole one */
+            (Affinity::Numeric, value @ (Value::Text(_) | Value::Blob(_))) => {
+                match value.to_number() {
+                    Value::Real(real) if is_integral(real) => Value::Integer(real as i64),
+                    number => number,
+                }
+            }
+            (_, value) => value,
+        }
+    }
+}
+
+/* the built-in collating sequences, see https://www.sqlite.org/datatype3.html#collation */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Collation {
+    #[default]
+    Binary,
+    /* folds ASCII letters only */
+    NoCase,
+    /* ignores trailing spaces */
+    RTrim,
+}
+
+impl Collation {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_uppercase().as_str() {
+            "BINARY" => Some(Collation::Binary),
+            "NOCASE" => Some(Collation::NoCase),
+            "RTRIM" => Some(Collation::RTrim),
+            _ => None,
+        }
+    }
+
+    pub fn compare(&self, a: &str, b: &str, encoding: TextEncoding) -> Ordering {
+        match self {
+            Collation::Binary => encoding.compare(a, b),
+            Collation::NoCase => encoding.compare(&a.to_ascii_lowercase(), &b.to_ascii_lowercase()),
+            Collation::RTrim => encoding.compare(a.trim_end_matches(' '), b.trim_end_matches(' ')),
+        }
+    }
+}
+
+impl Value {
+    /* like compare, with text compared under the collation */
+    pub fn compare_collated(
+        &self,
+        other: &Value,
+        collation: Collation,
+        encoding: TextEncoding,
+    ) -> Ordering {
+        match (self, other) {
+            (Value::Text(a), Value::Text(b)) => collation.compare(a, b, encoding),
+            _ => self.compare(other, encoding),
+        }
+    }
+}
+
+fn compare_int_real(int: i64, real: f64) -> Ordering {
+    if real.is_nan() {
+        return Ordering::Greater;
+    }
+    if real >= 9223372036854775808.0 {
+        return Ordering::Less;
+    }
+    if real < -9223372036854775808.0 {
+        return Ordering::Greater;
+    }
+    /* compare on the integer part first so large integers don't lose precision */
+    let real_int = real.floor() as i64;
+    match int.cmp(&real_int) {
+        Ordering::Equal if real > real_int as f64 => Ordering::Less,
+        ordering => ordering,
+    }
+}
+
+/* mirrors the "%!.15g" formatting the sqlite3 shell uses for REAL values */
+pub fn format_real(real: f64) -> String {
+    if real.is_nan() {
+        return String::new();
+    }
+    if real.is_infinite() {
+        return if real > 0.0 { "Inf" } else { "-Inf" }.to_string();
+    }
+    /* negative zero prints without its sign */
+    if real == 0.0 {
+        return "0.0".to_string();
+    }
+
+    let scientific = format!("{:.14e}", real);
+    let (mantissa, exponent) = scientific.split_once('e').unwrap();
+    let exponent: i32 = exponent.parse().unwrap();
+    let mantissa = trim_fraction(mantissa);
+
+    if !(-4..15).contains(&exponent) {
+        let sign = if exponent < 0 { '-' } else { '+' };
+        return format!("{}e{}{:02}", mantissa, sign, exponent.abs());
+    }
+
+    let decimals = (14 - exponent).max(0) as usize;
+    trim_fraction(&format!("{:.*}", decimals, real))
+}
+
+fn trim_fraction(number: &str) -> String {
+    match number.split_once('.') {
+        Some((whole, fraction)) => {
+            let fraction = fraction.trim_end_matches('0');
+            if fraction.is_empty() {
+                format!("{}.0", whole)
+            } else {
+                format!("{}.{}", whole, fraction)
+            }
+        }
+        None => format!("{}.0", number),
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Null => Ok(()),
+            Value::Integer(int) => write!(f, "{}", int),
+            Value::Real(real) => write!(f, "{}", format_real(*real)),
+            Value::Text(text) => write!(f, "{}", text),
+            Value::Blob(blob) => write!(f, "{}", String::from_utf8_lossy(blob)),
+        }
+    }
+}
@@ -1,16 +1,100 @@
 use crate::file_reader::BytesIterator;
 
-pub fn decode(bytes_iterator: &mut BytesIterator) -> (u64, u64) {
+/* the first 8 bytes carry 7 bits each, a 9th byte contributes all 8 of its bits.
+None when the bytes run out before the varint ends */
+pub fn decode(bytes_iterator: &mut BytesIterator) -> Option<(u64, u64)> {
     let mut integer: u64 = 0;
     let mut bytes_read: u64 = 0;
     loop {
-        let val_64: u64 = bytes_iterator.next().unwrap().into();
-        integer = integer << 7 | (val_64 & 0x7F);
+        let val_64: u64 = bytes_iterator.next()?.into();
         bytes_read += 1;
+        if bytes_read == 9 {
+            integer = integer << 8 | val_64;
+            break;
+        }
+        integer = integer << 7 | (val_64 & 0x7F);
         if val_64 >> 7 == 0 {
             break;
         }
     }
 
-    (integer, bytes_read)
+    Some((integer, bytes_read))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    /* sqlite's putVarint: big-endian 7-bit groups, or 8 groups and a full 9th byte */
+    fn encode(value: u64) -> Vec<u8> {
+        if value >> 56 != 0 {
+            let mut bytes: Vec<u8> = (0..8)
+                .map(|i| ((value >> (8 + 7 * (7 - i))) & 0x7F) as u8 | 0x80)
+                .collect();
+            bytes.push(value as u8);
+            return bytes;
+        }
+        let mut groups = vec![(value & 0x7F) as u8];
+        let mut rest = value >> 7;
+        while rest != 0 {
+            groups.push((rest & 0x7F) as u8 | 0x80);
+            rest >>= 7;
+        }
+        groups.reverse();
+        groups
+    }
+
+    fn decode_bytes(bytes: &[u8]) -> Option<(u64, u64)> {
+        decode(&mut BytesIterator::new(bytes.to_vec()))
+    }
+
+    #[test]
+    fn decodes_each_length_at_its_boundaries() {
+        for length in 1..=8_u32 {
+            let largest = (1_u64 << (7 * length)) - 1;
+            assert_eq!(encode(largest).len(), length as usize);
+            assert_eq!(
+                decode_bytes(&encode(largest)),
+                Some((largest, length as u64))
+            );
+            let smallest = 1_u64 << (7 * (length - 1));
+            assert_eq!(
+                decode_bytes(&encode(smallest)),
+                Some((smallest, length as u64))
+            );
+        }
+        for value in [1_u64 << 56, u64::MAX, i64::MIN as u64, -1_i64 as u64] {
+            assert_eq!(decode_bytes(&encode(value)), Some((value, 9)));
+        }
+        assert_eq!(decode_bytes(&[0]), Some((0, 1)));
+    }
+
+    #[test]
+    fn ninth_byte_keeps_all_eight_bits() {
+        assert_eq!(decode_bytes(&[0xFF; 9]), Some((u64::MAX, 9)));
+        /* a 9th byte never continues the varint, even with its high bit set */
+        assert_eq!(decode_bytes(&[0xFF; 10]), Some((u64::MAX, 9)));
+        assert_eq!(decode_bytes(&[0x81, 0, 0x7F]), Some((0x80, 2)));
+    }
+
+    #[test]
+    fn truncated_varint_is_none() {
+        assert_eq!(decode_bytes(&[]), None);
+        assert_eq!(decode_bytes(&[0x80]), None);
+        assert_eq!(decode_bytes(&encode(u64::MAX)[..8]), None);
+    }
+
+    proptest! {
+        #[test]
+        fn round_trips_any_value(value: u64, trailing: Vec<u8>) {
+            let mut bytes = encode(value);
+            let length = bytes.len() as u64;
+            bytes.extend(trailing.iter());
+            let mut iterator = BytesIterator::new(bytes);
+            prop_assert_eq!(decode(&mut iterator), Some((value, length)));
+            /* only the varint is consumed */
+            prop_assert_eq!(iterator.offset() as u64, length);
+        }
+    }
 }
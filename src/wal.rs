@@ -0,0 +1,166 @@
+use crate::file_reader::{BytesIterator, FileReader};
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+
+const WAL_HEADER_SIZE: u64 = 32;
+const FRAME_HEADER_SIZE: u64 = 24;
+const WAL_MAGIC_LE: u32 = 0x377f0682;
+const WAL_MAGIC_BE: u32 = 0x377f0683;
+const WAL_VERSION: u32 = 3007000;
+
+#[derive(Debug, Clone)]
+pub struct WalHeader {
+    pub big_endian_checksum: bool,
+    pub page_size: u32,
+    pub checkpoint_sequence: u32,
+    pub salt: (u32, u32),
+    pub checksum: (u32, u32),
+}
+
+/* the committed frames of a -wal file, keyed by the page they replace */
+pub struct Wal {
+    file_reader: FileReader,
+    pub header: WalHeader,
+    frames: HashMap<u32, u64>,
+    pub frame_count: u32,
+    pub db_page_count: u32,
+}
+
+impl Wal {
+    /* None when there is no wal, or it holds nothing a reader should see */
+    pub fn open(wal_path: &String, page_size: u32) -> io::Result<Option<Self>> {
+        if !Path::new(wal_path).exists() {
+            return Ok(None);
+        }
+        let file_size = std::fs::metadata(wal_path)?.len();
+        if file_size < WAL_HEADER_SIZE {
+            return Ok(None);
+        }
+
+        let mut file_reader = FileReader::new(wal_path)?;
+        let header_bytes = file_reader.read_bytes_from(0, WAL_HEADER_SIZE as usize)?;
+        let header = match parse_header(header_bytes) {
+            Some(header) if header.page_size == page_size => header,
+            _ => return Ok(None),
+        };
+
+        let frame_size = FRAME_HEADER_SIZE + page_size as u64;
+        let mut frames = HashMap::new();
+        let mut pending = Vec::new();
+        let mut checksum = header.checksum;
+        let mut frame_count = 0_u32;
+        let mut db_page_count = 0_u32;
+        let mut frame_offset = WAL_HEADER_SIZE;
+
+        while frame_offset + frame_size <= file_size {
+            let mut frame = file_reader.read_bytes_from(frame_offset, frame_size as usize)?;
+            let frame_header = frame.next_n(FRAME_HEADER_SIZE as usize).unwrap();
+            let page_data = frame.next_n(page_size as usize).unwrap();
+            let be_u32 = |offset: usize| {
+                u32::from_be_bytes(frame_header[offset..offset + 4].try_into().unwrap())
+            };
+
+            /* a frame from an older generation of the wal or a torn write ends the log */
+            if (be_u32(8), be_u32(12)) != header.salt {
+                break;
+            }
+            checksum = wal_checksum(&frame_header[0..8], checksum, header.big_endian_checksum);
+            checksum = wal_checksum(&page_data, checksum, header.big_endian_checksum);
+            if checksum != (be_u32(16), be_u32(20)) {
+                break;
+            }
+
+            let page_number = be_u32(0);
+            if page_number == 0 {
+                break;
+            }
+            pending.push((page_number, frame_offset + FRAME_HEADER_SIZE));
+
+            /* only frames up to the last commit frame are part of the database */
+            let commit_size = be_u32(4);
+            if commit_size != 0 {
+                frames.extend(pending.drain(..));
+                frame_count = ((frame_offset - WAL_HEADER_SIZE) / frame_size) as u32 + 1;
+                db_page_count = commit_size;
+            }
+            frame_offset += frame_size;
+        }
+
+        if frame_count == 0 {
+            return Ok(None);
+        }
+
+        Ok(Some(Self {
+            file_reader,
+            header,
+            frames,
+            frame_count,
+            db_page_count,
+        }))
+    }
+
+    pub fn contains(&self, page_number: u32) -> bool {
+        self.frames.contains_key(&page_number)
+    }
+
+    /* the newest committed copy of the page, if the wal has one */
+    pub fn read_page(&mut self, page_number: u32) -> Option<io::Result<BytesIterator>> {
+        let offset = *self.frames.get(&page_number)?;
+        Some(
+            self.file_reader
+                .read_bytes_from(offset, self.header.page_size as usize),
+        )
+    }
+}
+
+fn parse_header(mut header_bytes: BytesIterator) -> Option<WalHeader> {
+    let bytes = header_bytes.next_n(WAL_HEADER_SIZE as usize)?;
+    let be_u32 = |offset: usize| u32::from_be_bytes(bytes[offset..offset + 4].try_into().unwrap());
+
+    let big_endian_checksum = match be_u32(0) {
+        WAL_MAGIC_LE => false,
+        WAL_MAGIC_BE => true,
+        _ => return None,
+    };
+    if be_u32(4) != WAL_VERSION {
+        return None;
+    }
+
+    let checksum = (be_u32(24), be_u32(28));
+    if wal_checksum(&bytes[0..24], (0, 0), big_endian_checksum) != checksum {
+        return None;
+    }
+
+    let page_size = match be_u32(8) {
+        1 => 65536,
+        size => size,
+    };
+
+    Some(WalHeader {
+        big_endian_checksum,
+        page_size,
+        checkpoint_sequence: be_u32(12),
+        salt: (be_u32(16), be_u32(20)),
+        checksum,
+    })
+}
+
+/* fibonacci-weighted checksum over 32-bit words, see https://www.sqlite.org/fileformat.html#checksum_algorithm */
+fn wal_checksum(bytes: &[u8], initial: (u32, u32), big_endian: bool) -> (u32, u32) {
+    let (mut s0, mut s1) = initial;
+    let word = |chunk: &[u8]| {
+        let chunk: [u8; 4] = chunk.try_into().unwrap();
+        if big_endian {
+            u32::from_be_bytes(chunk)
+        } else {
+            u32::from_le_bytes(chunk)
+        }
+    };
+
+    for pair in bytes.chunks_exact(8) {
+        s0 = s0.wrapping_add(word(&pair[0..4])).wrapping_add(s1);
+        s1 = s1.wrapping_add(word(&pair[4..8])).wrapping_add(s0);
+    }
+    (s0, s1)
+}
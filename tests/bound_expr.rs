@@ -0,0 +1,169 @@
+/*
+    expressions bound against the tables of collate.db and evaluated on rows made up here. c is
+    (id, n text collate nocase, r text collate rtrim, b text) and d is (k text collate nocase, w)
+*/
+use codecrafters_sqlite::aggregate::AggregateCall;
+use codecrafters_sqlite::ast::{Expr, Statement, TableReference};
+use codecrafters_sqlite::bound_expr::BoundExpr;
+use codecrafters_sqlite::db_header::TextEncoding;
+use codecrafters_sqlite::error::Result;
+use codecrafters_sqlite::join::Source;
+use codecrafters_sqlite::page_reader::PageReaderBuilder;
+use codecrafters_sqlite::parser::parse;
+use codecrafters_sqlite::schema::Schema;
+use codecrafters_sqlite::value::{Collation, Value};
+
+fn schema() -> Schema {
+    let path = format!("{}/tests/fixtures/collate.db", env!("CARGO_MANIFEST_DIR"));
+    let mut builder = PageReaderBuilder::open(&path).unwrap();
+    Schema::load(&mut builder).unwrap()
+}
+
+/* the FROM and WHERE of `select * from <table> where <filter>` */
+fn filter(table: &str, filter: &str) -> (Vec<TableReference>, Expr) {
+    let sql = format!("select * from {} where {}", table, filter);
+    let Ok(Statement::Select(select)) = parse(&sql) else {
+        panic!("not a select: {}", sql);
+    };
+    (select.from, select.filter.unwrap())
+}
+
+fn bind(table: &str, expr: &str) -> Result<BoundExpr> {
+    let schema = schema();
+    let (from, expr) = filter(table, expr);
+    let source = Source::new(&schema, &from)?;
+    BoundExpr::bind(&expr, &source)
+}
+
+fn eval(table: &str, expr: &str, row: &[Value]) -> Value {
+    bind(table, expr)
+        .unwrap_or_else(|err| panic!("{}: {}", expr, err))
+        .eval(row, TextEncoding::Utf8)
+        .unwrap_or_else(|err| panic!("{}: {}", expr, err))
+}
+
+fn text(text: &str) -> Value {
+    Value::Text(text.to_string())
+}
+
+fn c_row(n: Value, b: Value) -> Vec<Value> {
+    vec![Value::Integer(1), n, text("x"), b]
+}
+
+#[test]
+fn comparisons_take_the_column_collation() {
+    let collation = |expr| match bind("c", expr).unwrap() {
+        BoundExpr::Compare { collation, .. } => collation,
+        bound => panic!("{} bound as {:?}", expr, bound),
+    };
+    assert_eq!(collation("n = 'x'"), Collation::NoCase);
+    assert_eq!(collation("'x' = n"), Collation::NoCase);
+    assert_eq!(collation("b = n"), Collation::Binary);
+    assert_eq!(collation("n = b"), Collation::NoCase);
+    assert_eq!(collation("cast(n as text) = 'x'"), Collation::NoCase);
+    assert_eq!(collation("lower(n) = 'x'"), Collation::Binary);
+
+    let row = c_row(text("Apple"), text("Apple"));
+    assert_eq!(eval("c", "n = 'APPLE'", &row), Value::Integer(1));
+    assert_eq!(eval("c", "b = 'APPLE'", &row), Value::Integer(0));
+    assert_eq!(eval("c", "n in ('x', 'apple')", &row), Value::Integer(1));
+    assert_eq!(eval("c", "n between 'a' and 'b'", &row), Value::Integer(1));
+}
+
+#[test]
+fn comparisons_apply_the_column_affinity() {
+    let row = [text("1"), Value::Integer(1)];
+    assert_eq!(eval("d", "k = 1", &row), Value::Integer(1));
+    assert_eq!(eval("d", "w = '1'", &row), Value::Integer(0));
+    assert_eq!(eval("d", "w = 1", &row), Value::Integer(1));
+    assert_eq!(eval("d", "k = 'A'", &row), Value::Integer(0));
+}
+
+#[test]
+fn nulls_follow_three_valued_logic() {
+    let row = c_row(Value::Null, text("b"));
+    for (expr, expected) in [
+        ("n = 'x'", Value::Null),
+        ("n is null", Value::Integer(1)),
+        ("n is not b", Value::Integer(1)),
+        ("n = 'x' and b = 'c'", Value::Integer(0)),
+        ("n = 'x' and b = 'b'", Value::Null),
+        ("n = 'x' or b = 'b'", Value::Integer(1)),
+        ("not n = 'x'", Value::Null),
+        ("1 in (2, null)", Value::Null),
+        ("1 not in (2, null)", Value::Null),
+        ("1 in (1, null)", Value::Integer(1)),
+        ("n like 'a%'", Value::Null),
+        ("n || b", Value::Null),
+    ] {
+        assert_eq!(eval("c", expr, &row), expected, "{}", expr);
+    }
+    let bound = bind("c", "n = 'x' or b = 'b'").unwrap();
+    assert!(bound.matches(&row, TextEncoding::Utf8).unwrap());
+    let bound = bind("c", "n = 'x' and b = 'b'").unwrap();
+    assert!(!bound.matches(&row, TextEncoding::Utf8).unwrap());
+}
+
+#[test]
+fn case_only_evaluates_the_branch_taken() {
+    let row = c_row(text("a"), Value::Integer(i64::MIN));
+    let expr = "case when n = 'A' then 'first' else abs(b) end";
+    assert_eq!(eval("c", expr, &row), text("first"));
+    let expr = "case when n = 'b' then 'first' else abs(b) end";
+    let err = bind("c", expr)
+        .unwrap()
+        .eval(&row, TextEncoding::Utf8)
+        .unwrap_err();
+    assert_eq!(err.to_string(), "integer overflow");
+    assert_eq!(
+        eval("c", "case n when 'A' then 1 when 'a' then 2 end", &row),
+        Value::Integer(1)
+    );
+    assert_eq!(eval("c", "coalesce(null, n, abs(b))", &row), text("a"));
+}
+
+#[test]
+fn columns_lists_every_column_read() {
+    let mut columns = Vec::new();
+    bind("c", "case when n like b then r end || upper(id)")
+        .unwrap()
+        .columns(&mut columns);
+    assert_eq!(columns, [1, 3, 2, 0]);
+}
+
+#[test]
+fn aggregates_read_their_slot_after_the_columns() {
+    let schema = schema();
+    let (from, expr) = filter("c", "count(*) + sum(id) > count(*)");
+    let source = Source::new(&schema, &from).unwrap();
+    let mut aggregates: Vec<AggregateCall> = Vec::new();
+    let bound = BoundExpr::bind_aggregate(&expr, &source, &mut aggregates).unwrap();
+    /* the same call written twice shares a slot */
+    assert_eq!(aggregates.len(), 2);
+    let mut columns = Vec::new();
+    bound.columns(&mut columns);
+    assert_eq!(columns, [4, 5, 4]);
+
+    let err = BoundExpr::bind(&expr, &source).unwrap_err();
+    assert_eq!(err.to_string(), "parse error: misuse of aggregate: count()");
+}
+
+#[test]
+fn binding_errors() {
+    for (expr, expected) in [
+        ("nope = 1", "no such column: nope"),
+        ("x.n = 1", "no such column: x.n"),
+        ("soundex(n)", "unsupported: function soundex()"),
+        (
+            "substr(n)",
+            "parse error: wrong number of arguments to function substr()",
+        ),
+        (
+            "n like 'a' escape 'ab'",
+            "parse error: ESCAPE expression must be a single character",
+        ),
+    ] {
+        let err = bind("c", expr).unwrap_err();
+        assert_eq!(err.to_string(), expected, "{}", expr);
+    }
+}
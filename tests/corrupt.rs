@@ -0,0 +1,455 @@
+/*
+    corrupt databases must come back as errors, never as a panic or a hang. sample.db is the
+    template: its schema on page 1 points apples at page 2, sqlite_sequence at 3 and oranges
+    at 4, and the tests swap in broken pages from there
+*/
+use codecrafters_sqlite::ast::Statement;
+use codecrafters_sqlite::page_reader::PageReaderBuilder;
+use codecrafters_sqlite::page_source::MemorySource;
+use codecrafters_sqlite::parser::parse;
+use codecrafters_sqlite::query::{execute_select, QueryOptions};
+use codecrafters_sqlite::schema::Schema;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+const SAMPLE: &[u8] = include_bytes!("../sample.db");
+const INDEXES: &[u8] = include_bytes!("fixtures/indexes.db");
+const PAGE_SIZE: usize = 4096;
+const TIME_LIMIT: Duration = Duration::from_secs(10);
+
+/* loads the schema and reads every table in full, returning the output or the error */
+fn read_everything(bytes: Vec<u8>) -> Result<String, String> {
+    let mut builder =
+        PageReaderBuilder::from_source(MemorySource::new(bytes)).map_err(|err| err.to_string())?;
+    let schema = Schema::load(&mut builder).map_err(|err| err.to_string())?;
+    let mut out = Vec::new();
+    for table in schema.tables.iter() {
+        let mut queries = vec![
+            format!("select count(*) from {}", table.name()),
+            format!("select * from {}", table.name()),
+        ];
+        if let Some(column) = table.columns.first() {
+            queries.push(format!(
+                "select * from {} where {} = 2",
+                table.name(),
+                column.name
+            ));
+        }
+        for sql in queries {
+            run_query(&mut builder, &schema, &sql, &mut out)?;
+        }
+    }
+    Ok(String::from_utf8_lossy(&out).into_owned())
+}
+
+fn run_query(
+    builder: &mut PageReaderBuilder<MemorySource>,
+    schema: &Schema,
+    sql: &str,
+    out: &mut Vec<u8>,
+) -> Result<(), String> {
+    let Statement::Select(select) = parse(sql).map_err(|err| err.to_string())? else {
+        unreachable!("not a select");
+    };
+    execute_select(&select, schema, builder, &QueryOptions::default(), out)
+        .map_err(|err| err.to_string())
+}
+
+/* runs read_everything on its own thread so a panic or a loop fails the test instead of it */
+fn read_within_limit(bytes: Vec<u8>) -> Result<String, String> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let _ = sender.send(read_everything(bytes));
+    });
+    match receiver.recv_timeout(TIME_LIMIT) {
+        Ok(result) => result,
+        Err(mpsc::RecvTimeoutError::Timeout) => panic!("still reading after {:?}", TIME_LIMIT),
+        Err(mpsc::RecvTimeoutError::Disconnected) => panic!("reader panicked"),
+    }
+}
+
+fn assert_corrupt(bytes: Vec<u8>, reason: &str) {
+    match read_within_limit(bytes) {
+        Ok(output) => panic!("expected an error about {:?}, read {:?}", reason, output),
+        Err(err) => assert!(
+            err.contains(reason),
+            "expected an error about {:?}, got {:?}",
+            reason,
+            err
+        ),
+    }
+}
+
+fn encode_varint(mut value: u64) -> Vec<u8> {
+    let mut groups = vec![(value & 0x7F) as u8];
+    value >>= 7;
+    while value != 0 {
+        groups.push((value & 0x7F) as u8 | 0x80);
+        value >>= 7;
+    }
+    groups.reverse();
+    groups
+}
+
+/* sample.db with `pages` written over or appended after its own, and the page count updated */
+fn sample_with(pages: &[(u32, Vec<u8>)]) -> Vec<u8> {
+    let mut bytes = SAMPLE.to_vec();
+    for (page_no, page) in pages {
+        let start = (*page_no as usize - 1) * PAGE_SIZE;
+        if bytes.len() < start + PAGE_SIZE {
+            bytes.resize(start + PAGE_SIZE, 0);
+        }
+        bytes[start..start + page.len()].copy_from_slice(page);
+    }
+    let page_count = (bytes.len() / PAGE_SIZE) as u32;
+    bytes[28..32].copy_from_slice(&page_count.to_be_bytes());
+    bytes
+}
+
+/* a b-tree page with the cells packed at the end, in the order given */
+fn btree_page(page_type: u8, cells: &[Vec<u8>], right_pointer: Option<u32>) -> Vec<u8> {
+    let mut page = vec![0_u8; PAGE_SIZE];
+    let mut content_start = PAGE_SIZE;
+    let mut pointers = Vec::new();
+    for cell in cells {
+        content_start -= cell.len();
+        page[content_start..content_start + cell.len()].copy_from_slice(cell);
+        pointers.extend((content_start as u16).to_be_bytes());
+    }
+    page[0] = page_type;
+    page[3..5].copy_from_slice(&(cells.len() as u16).to_be_bytes());
+    page[5..7].copy_from_slice(&(content_start as u16).to_be_bytes());
+    let header_size = match right_pointer {
+        Some(right_pointer) => {
+            page[8..12].copy_from_slice(&right_pointer.to_be_bytes());
+            12
+        }
+        None => 8,
+    };
+    page[header_size..header_size + pointers.len()].copy_from_slice(&pointers);
+    page
+}
+
+fn interior(left_child: u32, row_id: u64, right_pointer: u32) -> Vec<u8> {
+    let mut cell = left_child.to_be_bytes().to_vec();
+    cell.extend(encode_varint(row_id));
+    btree_page(0x05, &[cell], Some(right_pointer))
+}
+
+fn empty_leaf() -> Vec<u8> {
+    btree_page(0x0D, &[], None)
+}
+
+/* a leaf holding one apple whose name is a blob that spills onto `first_overflow` */
+fn leaf_with_overflow(first_overflow: u32) -> Vec<u8> {
+    btree_page(0x0D, &[spilled_cell(1, first_overflow)], None)
+}
+
+fn spilled_cell(row_id: u64, first_overflow: u32) -> Vec<u8> {
+    let payload_size = 20_000_u64;
+    /* the id is NULL, it reads the row id */
+    let mut record = vec![5, 0];
+    record.extend(encode_varint((payload_size - 5) * 2 + 12));
+    assert_eq!(record.len(), 5);
+    record.resize(payload_size as usize, b'x');
+
+    /* the local part of a spilled payload, by the formula in sqlite's file format docs */
+    let usable = PAGE_SIZE as u64;
+    let min_local = (usable - 12) * 32 / 255 - 23;
+    let max_local = usable - 35;
+    let spill = min_local + (payload_size - min_local) % (usable - 4);
+    let local = if spill <= max_local { spill } else { min_local };
+
+    let mut cell = encode_varint(payload_size);
+    cell.extend(encode_varint(row_id));
+    cell.extend(&record[..local as usize]);
+    cell.extend(first_overflow.to_be_bytes());
+    cell
+}
+
+fn overflow_page(next: u32) -> Vec<u8> {
+    let mut page = vec![b'x'; PAGE_SIZE];
+    page[0..4].copy_from_slice(&next.to_be_bytes());
+    page
+}
+
+#[test]
+fn sample_reads_cleanly() {
+    let output = read_within_limit(SAMPLE.to_vec()).unwrap();
+    assert!(output.contains("Granny Smith"), "{}", output);
+}
+
+#[test]
+fn page_that_is_its_own_child() {
+    assert_corrupt(sample_with(&[(2, interior(2, 1, 3))]), "b-tree page");
+    assert_corrupt(sample_with(&[(2, interior(3, 1, 2))]), "b-tree page");
+}
+
+#[test]
+fn pages_that_point_at_each_other() {
+    assert_corrupt(
+        sample_with(&[
+            (2, interior(5, 1, 5)),
+            (5, interior(6, 1, 2)),
+            (6, empty_leaf()),
+        ]),
+        "b-tree page",
+    );
+}
+
+#[test]
+fn child_reachable_twice() {
+    /* not a cycle, but the same leaf would be read twice */
+    assert_corrupt(
+        sample_with(&[(2, interior(5, 1, 5)), (5, empty_leaf())]),
+        "reachable twice",
+    );
+}
+
+/* the first query's output, or its error, without the count(*) that read_everything starts with */
+fn query_bytes(bytes: Vec<u8>, sql: &str) -> Result<String, String> {
+    let mut builder =
+        PageReaderBuilder::from_source(MemorySource::new(bytes)).map_err(|err| err.to_string())?;
+    let schema = Schema::load(&mut builder).map_err(|err| err.to_string())?;
+    let mut out = Vec::new();
+    run_query(&mut builder, &schema, sql, &mut out)?;
+    Ok(String::from_utf8_lossy(&out).into_owned())
+}
+
+fn assert_query_corrupt(bytes: Vec<u8>, sql: &str, reason: &str) {
+    match query_bytes(bytes, sql) {
+        Ok(output) => panic!(
+            "{}: expected an error about {:?}, read {:?}",
+            sql, reason, output
+        ),
+        Err(err) => assert!(err.contains(reason), "{}: got {:?}", sql, err),
+    }
+}
+
+/* indexes.db with the left child of cell 1 of interior page `page_no` pointed at cell 0's */
+fn indexes_with_shared_child(page_no: usize) -> Vec<u8> {
+    let mut bytes = INDEXES.to_vec();
+    let page = &mut bytes[(page_no - 1) * PAGE_SIZE..page_no * PAGE_SIZE];
+    let cell =
+        |page: &[u8], i: usize| u16::from_be_bytes([page[12 + 2 * i], page[13 + 2 * i]]) as usize;
+    let (first, second) = (cell(page, 0), cell(page, 1));
+    let child = page[first..first + 4].to_vec();
+    page[second..second + 4].copy_from_slice(&child);
+    bytes
+}
+
+#[test]
+fn scans_refuse_a_child_reachable_twice() {
+    /* apples' leaf under both children of its root would give every row twice */
+    let leaf = SAMPLE[PAGE_SIZE..2 * PAGE_SIZE].to_vec();
+    let bytes = sample_with(&[(2, interior(5, 1, 5)), (5, leaf)]);
+    assert_query_corrupt(bytes, "select * from apples", "reachable twice");
+
+    /* f is rooted at page 2 and its index f_k at page 8, both interior pages */
+    assert_query_corrupt(
+        indexes_with_shared_child(2),
+        "select * from f",
+        "reachable twice",
+    );
+    let sql = "select k from f order by k desc";
+    assert_query_corrupt(indexes_with_shared_child(8), sql, "reachable twice");
+    assert!(query_bytes(INDEXES.to_vec(), sql).is_ok());
+}
+
+#[test]
+fn overflow_chain_that_loops() {
+    assert_corrupt(
+        sample_with(&[(2, leaf_with_overflow(5)), (5, overflow_page(5))]),
+        "overflow chain loops",
+    );
+    assert_corrupt(
+        sample_with(&[
+            (2, leaf_with_overflow(5)),
+            (5, overflow_page(6)),
+            (6, overflow_page(5)),
+        ]),
+        "overflow chain loops",
+    );
+}
+
+#[test]
+fn overflow_chain_that_ends_early() {
+    assert_corrupt(
+        sample_with(&[(2, leaf_with_overflow(5)), (5, overflow_page(0))]),
+        "overflow chain ends",
+    );
+    assert_corrupt(sample_with(&[(2, leaf_with_overflow(9))]), "past the end");
+}
+
+#[test]
+fn spilled_payload_reads_back() {
+    let output = read_within_limit(sample_with(&[
+        (2, leaf_with_overflow(5)),
+        (5, overflow_page(6)),
+        (6, overflow_page(7)),
+        (7, overflow_page(8)),
+        (8, overflow_page(0)),
+    ]))
+    .unwrap();
+    assert!(output.contains(&"x".repeat(19_995)), "{}", output.len());
+}
+
+#[test]
+fn overflow_is_only_read_for_the_rows_read() {
+    /* the second apple's chain loops, which a query stopping at the first never follows */
+    let mut first = encode_varint(8);
+    first.extend(encode_varint(1));
+    first.extend([4, 0, 21, 0]);
+    first.extend(b"Gala");
+    let leaf = btree_page(0x0D, &[first, spilled_cell(2, 5)], None);
+    let bytes = sample_with(&[(2, leaf), (5, overflow_page(5))]);
+
+    let mut builder = PageReaderBuilder::from_source(MemorySource::new(bytes.clone())).unwrap();
+    let schema = Schema::load(&mut builder).unwrap();
+    let mut out = Vec::new();
+    run_query(
+        &mut builder,
+        &schema,
+        "select name from apples limit 1",
+        &mut out,
+    )
+    .unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), "Gala\n");
+    assert_corrupt(bytes, "overflow chain loops");
+}
+
+#[test]
+fn tree_deeper_than_the_limit() {
+    /* a spine of interior pages, each with an empty leaf on the left and the next level on the right */
+    let depth = 30;
+    let spine: Vec<u32> = std::iter::once(2).chain(5..4 + depth).collect();
+    let mut pages = Vec::new();
+    for (level, page_no) in spine.iter().enumerate() {
+        let leaf = 100 + level as u32;
+        let next = spine.get(level + 1).copied().unwrap_or(99);
+        pages.push((*page_no, interior(leaf, 1, next)));
+        pages.push((leaf, empty_leaf()));
+    }
+    pages.push((99, empty_leaf()));
+    assert_corrupt(sample_with(&pages), "deeper than");
+}
+
+#[test]
+fn bad_page_header_fields() {
+    /* each case overwrites header bytes of apples' leaf page at the given offset */
+    let cases: [(&str, usize, &[u8]); 8] = [
+        ("not a b-tree page", 0, &[0x07]),
+        ("don't fit in the page", 3, &3000_u16.to_be_bytes()),
+        ("cell content area starts", 5, &4_u16.to_be_bytes()),
+        ("first free block", 1, &10_u16.to_be_bytes()),
+        ("first free block", 1, &4094_u16.to_be_bytes()),
+        ("fragmented bytes", 7, &[200]),
+        ("outside the cell content area", 8, &4096_u16.to_be_bytes()),
+        ("outside the cell content area", 8, &20_u16.to_be_bytes()),
+    ];
+    for (reason, offset, bytes) in cases {
+        let mut page = SAMPLE[PAGE_SIZE..2 * PAGE_SIZE].to_vec();
+        page[offset..offset + bytes.len()].copy_from_slice(bytes);
+        assert_corrupt(sample_with(&[(2, page)]), reason);
+    }
+
+    let mut no_right_child = interior(5, 1, 5);
+    no_right_child[8..12].copy_from_slice(&0_u32.to_be_bytes());
+    assert_corrupt(sample_with(&[(2, no_right_child)]), "no right child");
+
+    assert_corrupt(
+        sample_with(&[(2, interior(0, 1, 5)), (5, empty_leaf())]),
+        "page number 0",
+    );
+    assert_corrupt(sample_with(&[(2, interior(5, 1, 500))]), "past the end");
+}
+
+#[test]
+fn index_page_in_a_table() {
+    assert_corrupt(
+        sample_with(&[(2, btree_page(0x0A, &[], None))]),
+        "non-table page",
+    );
+}
+
+/* xorshift, so the corpus is the same on every run and a failing seed can be replayed */
+struct Mutator(u64);
+
+impl Mutator {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+
+    /* mostly aims at the headers and cell pointers, where sample.db's structure lives */
+    fn offset(&mut self, length: usize) -> usize {
+        let page_start = self.below(length.div_ceil(PAGE_SIZE)) * PAGE_SIZE;
+        let offset = match self.below(4) {
+            0 => self.below(length),
+            1 => page_start + self.below(112),
+            2 => self.below(140),
+            _ => page_start + PAGE_SIZE - 1 - self.below(400),
+        };
+        offset.min(length - 1)
+    }
+
+    fn mutate(&mut self, bytes: &mut Vec<u8>) {
+        for _ in 0..1 + self.below(4) {
+            let offset = self.offset(bytes.len());
+            match self.below(6) {
+                0 => bytes[offset] ^= 1 << self.below(8),
+                1 => bytes[offset] = self.next() as u8,
+                2 => bytes[offset] = [0x00, 0x7F, 0x80, 0xFF][self.below(4)],
+                3 => {
+                    /* a small page number, so pointers land on real pages */
+                    let page_no = (self.below(6) as u32).to_be_bytes();
+                    let end = (offset + 4).min(bytes.len());
+                    bytes[offset..end].copy_from_slice(&page_no[..end - offset]);
+                }
+                4 => {
+                    let offset = offset.min(bytes.len() - 2);
+                    let value = (self.below(PAGE_SIZE + 8) as u16).to_be_bytes();
+                    bytes[offset..offset + 2].copy_from_slice(&value);
+                }
+                _ => bytes.truncate(offset.max(1)),
+            }
+            if bytes.len() < 2 {
+                return;
+            }
+        }
+    }
+}
+
+#[test]
+fn mutated_samples_never_panic_or_hang() {
+    let mut mutator = Mutator(0x5EED_CAFE);
+    let mut errors = 0;
+    for case in 0..1000 {
+        let mut bytes = SAMPLE.to_vec();
+        mutator.mutate(&mut bytes);
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let _ = sender.send(read_everything(bytes).is_err());
+        });
+        match receiver.recv_timeout(TIME_LIMIT) {
+            Ok(failed) => errors += failed as usize,
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                panic!("case {} still reading after {:?}", case, TIME_LIMIT)
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => panic!("case {} panicked", case),
+        }
+    }
+    /* a corpus that never trips an error isn't reaching the checks */
+    assert!(
+        errors > 100,
+        "only {} of the mutations were rejected",
+        errors
+    );
+}
@@ -0,0 +1,9 @@
+-- sqlite3 tests/fixtures/collate.db < tests/fixtures/collate.sql
+create table c(id integer primary key, n text collate nocase, r text collate rtrim, b text);
+insert into c(n, r, b) values
+    ('apple', 'x  ', 'AbC'), ('APPLE', 'x', 'Apple'), ('Banana', 'y ', 'ABC'),
+    ('cherry', 'Y', 'apple'), ('b', 'z', 'b'), ('B', 'z ', 'Ba'), (NULL, NULL, NULL),
+    ('Apple ', 'x', 'apricot');
+create table d(k text collate nocase, w);
+insert into d values ('apple', 1), ('BANANA', 2), ('Cherry', 3);
+create index d_k on d(k);
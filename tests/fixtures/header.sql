@@ -0,0 +1,9 @@
+-- sqlite3 tests/fixtures/header.db < tests/fixtures/header.sql
+pragma page_size = 1024;
+pragma auto_vacuum = incremental;
+pragma user_version = 42;
+pragma application_id = 1399612225;
+create table t(id integer primary key, v text);
+with recursive n(i) as (select 1 union all select i + 1 from n where i < 50)
+insert into t select i, printf('%0500d', i) from n;
+delete from t where id > 10;
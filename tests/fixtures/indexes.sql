@@ -0,0 +1,22 @@
+-- sqlite3 tests/fixtures/indexes.db < tests/fixtures/indexes.sql
+create table f(id integer primary key, k int, s text, m text);
+with recursive n(i) as (select 1 union all select i + 1 from n where i < 1000)
+insert into f(k, s, m)
+    select i % 97, 'w' || (i % 250),
+        case i % 7
+            when 0 then 'abc' when 1 then 'AbC' when 2 then 'ABC' when 3 then 'apple'
+            when 4 then 'Apple' when 5 then 'b' else 'a'
+        end
+    from n;
+create index f_k on f(k desc);
+create index f_s on f(s desc);
+create index f_m on f(m collate nocase);
+create table p(id integer primary key, v int);
+insert into p(v) values (5), (50), (96), (200), (NULL), (1);
+create table r(id integer primary key, v text);
+with recursive n(i) as (select 1 union all select i + 1 from n where i < 3000)
+insert into r select i, printf('%0200d', i) from n;
+create table u(id integer primary key, code text unique, name text collate nocase, pad text, unique(name));
+with recursive n(i) as (select 1 union all select i + 1 from n where i < 2000)
+insert into u select i, printf('c%05d', i), printf('Name%d', i), printf('%0200d', i) from n;
+create table v(w int unique, k text, primary key(k collate nocase desc));
@@ -0,0 +1,28 @@
+-- sqlite3 tests/fixtures/tables.db < tests/fixtures/tables.sql
+create table alt(id integer primary key, a text);
+insert into alt(a) values ('x'), ('y');
+alter table alt add column c text default 'dflt';
+alter table alt add column n int default -5;
+alter table alt add column r real default 2;
+alter table alt add column s int default '42';
+alter table alt add column b default true;
+alter table alt add column w default abc;
+alter table alt add column z default null;
+alter table alt add column q default 'it''s';
+insert into alt(a, c) values ('z', null);
+
+create table g(a int, b int, f as (b * 2), s text as (a || '-' || b) stored, v int as (f + 1),
+    u as (upper(w)), w text default 'q');
+insert into g(a, b, w) values (1, 5, 'ab'), (2, null, null), (3, 7, 'Cd');
+
+create table wr(k text primary key, v int, w text) without rowid;
+insert into wr values ('b', 2, 'two'), ('a', 1, 'one'), ('c', 3, null);
+create index wr_v on wr(v);
+
+create table wr2(a, b, c, primary key (c, a)) without rowid;
+insert into wr2 values (1, 'x', 'p'), (2, 'y', 'o'), (3, 'z', 'p');
+
+create virtual table docs using fts5(body);
+insert into docs values ('hello world');
+create virtual table boxes using rtree(id, x0, x1);
+create view alt_view as select id, a from alt;
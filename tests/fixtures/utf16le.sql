@@ -0,0 +1,5 @@
+-- sqlite3 tests/fixtures/utf16le.db < tests/fixtures/utf16le.sql
+pragma encoding = 'UTF-16le';
+create table t(id integer primary key, name text);
+insert into t(name) values ('b'), ('ā'), ('Ａ'), ('😀'), ('a'), ('ÿ'), ('ĀB'), (''), ('z😀z');
+create index t_name on t(name);
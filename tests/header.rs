@@ -0,0 +1,61 @@
+/*
+    the database header of tests/fixtures/header.db, whose pragmas set most of its fields. the
+    expected values are what sqlite3's pragmas report for the file
+*/
+use codecrafters_sqlite::db_header::{DatabaseHeader, TextEncoding, HEADER_SIZE};
+use codecrafters_sqlite::page_reader::PageReaderBuilder;
+
+const HEADER: &[u8] = include_bytes!("fixtures/header.db");
+
+#[test]
+fn header_fields_match_the_pragmas() {
+    let header = DatabaseHeader::parse(&HEADER[..HEADER_SIZE]).unwrap();
+    assert_eq!(header.page_size, 1024);
+    assert_eq!((header.write_version, header.read_version), (1, 1));
+    assert_eq!(header.reserved_bytes, 0);
+    assert_eq!(
+        (
+            header.max_payload_fraction,
+            header.min_payload_fraction,
+            header.leaf_payload_fraction
+        ),
+        (64, 32, 32)
+    );
+    assert_eq!(header.page_count, 53);
+    assert_eq!(header.freelist_page_count, 40);
+    assert_eq!(header.freelist_trunk_page, 15);
+    assert_eq!(header.schema_cookie, 1);
+    assert_eq!(header.schema_format, 4);
+    assert_eq!(header.default_cache_size, 0);
+    /* auto_vacuum = incremental */
+    assert_eq!(header.largest_root_page, 3);
+    assert_eq!(header.incremental_vacuum, 1);
+    assert_eq!(header.text_encoding, TextEncoding::Utf8);
+    assert_eq!(header.user_version, 42);
+    assert_eq!(header.application_id, 1399612225);
+    assert_eq!(header.version_valid_for, header.file_change_counter);
+    /* the sqlite3 that wrote the fixture */
+    assert_eq!(header.sqlite_version_number, 3051002);
+    assert_eq!(header.usable_size(), 1024);
+}
+
+#[test]
+fn header_rejects_other_files() {
+    let mut bytes = HEADER[..HEADER_SIZE].to_vec();
+    assert!(DatabaseHeader::parse(&bytes[..99]).is_err());
+    bytes[0] = b's';
+    let err = DatabaseHeader::parse(&bytes).unwrap_err();
+    assert!(
+        err.to_string().contains("file is not a database"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn builder_reads_the_header_of_the_file() {
+    let path = format!("{}/tests/fixtures/header.db", env!("CARGO_MANIFEST_DIR"));
+    let builder = PageReaderBuilder::open(&path).unwrap();
+    assert_eq!(builder.header().user_version, 42);
+    assert_eq!(builder.header().page_count, 53);
+}
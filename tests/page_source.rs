@@ -0,0 +1,46 @@
+/* the sources a PageReaderBuilder reads the database bytes from */
+use codecrafters_sqlite::page_source::{MemorySource, MmapSource, PageSource};
+use std::io::{ErrorKind, Write};
+
+fn read(source: &mut impl PageSource, offset: u64, size: usize) -> std::io::Result<Vec<u8>> {
+    let mut bytes = source.read_bytes_from(offset, size)?;
+    Ok(bytes.next_n(size).unwrap().to_vec())
+}
+
+#[test]
+fn mmap_maps_again_once_the_file_grows() {
+    let path = std::env::temp_dir().join(format!("mmap-{}.db", std::process::id()));
+    std::fs::write(&path, [1_u8; 100]).unwrap();
+    let mut source = MmapSource::open(&path.to_str().unwrap().to_string()).unwrap();
+    assert_eq!(read(&mut source, 90, 10).unwrap(), [1; 10]);
+    let err = read(&mut source, 90, 20).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+
+    let mut file = std::fs::OpenOptions::new()
+        .append(true)
+        .open(&path)
+        .unwrap();
+    file.write_all(&[2_u8; 100]).unwrap();
+    drop(file);
+    let mut expected = vec![1_u8; 10];
+    expected.extend([2_u8; 10]);
+    assert_eq!(read(&mut source, 90, 20).unwrap(), expected);
+    let err = read(&mut source, 190, 20).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn memory_sources_read_their_buffer() {
+    let buffer: Vec<u8> = (0..=255).collect();
+    let mut copied = MemorySource::copy_from_slice(&buffer);
+    let mut owned = MemorySource::from(buffer.clone());
+    for source in [&mut copied, &mut owned] {
+        assert_eq!(
+            read(source, 250, 6).unwrap(),
+            [250, 251, 252, 253, 254, 255]
+        );
+        let err = read(source, 250, 7).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+    }
+}
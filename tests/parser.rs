@@ -0,0 +1,106 @@
+/*
+    sql the parser refuses, and the column each error points at. columns are 1-based and
+    counted in characters, so text before the error that isn't ascii still lines up
+*/
+use codecrafters_sqlite::ast::{BinaryOp, Expr, ResultColumn, Statement, UnaryOp};
+use codecrafters_sqlite::parser::parse;
+
+fn parse_error(sql: &str) -> String {
+    match parse(sql) {
+        Ok(statement) => panic!("{:?} parsed as {:?}", sql, statement),
+        Err(err) => err.to_string(),
+    }
+}
+
+#[test]
+fn errors_name_the_column_they_start_at() {
+    for (sql, expected) in [
+        (
+            "select 'abc from t",
+            "parse error: unterminated quoted text at column 8",
+        ),
+        (
+            "select \"abc from t",
+            "parse error: unterminated quoted text at column 8",
+        ),
+        (
+            "select a /* b from t",
+            "parse error: unterminated comment at column 10",
+        ),
+        (
+            "select a from t where ) = 1",
+            "parse error: unexpected token \")\" at column 23",
+        ),
+        (
+            "select a, from t",
+            "parse error: unexpected token \"from\" at column 11",
+        ),
+        (
+            "select 'é', a b c from t",
+            "parse error: unexpected token \"c\" at column 17",
+        ),
+        (
+            "select 12abc from t",
+            "parse error: unrecognized token \"12abc\" at column 8",
+        ),
+        (
+            "select 1e+ from t",
+            "parse error: unrecognized token \"1e\" at column 8",
+        ),
+        (
+            "select 0x1ffffffffffffffff from t",
+            "parse error: hex literal too big: 0x1ffffffffffffffff at column 8",
+        ),
+        ("select a from", "parse error: unexpected end of input"),
+    ] {
+        assert_eq!(parse_error(sql), expected, "{}", sql);
+    }
+}
+
+#[test]
+fn only_ascii_whitespace_separates_tokens() {
+    for sql in ["select\ta\nfrom\rt\x0cwhere a = 1", " select a from t ;\n"] {
+        assert!(parse(sql).is_ok(), "{:?}", sql);
+    }
+    /*
+        sqlite reads other whitespace, like a no-break space, as part of a name. the second
+        reads as table "t\u{2003}where" with the alias a
+    */
+    assert_eq!(
+        parse_error("select\u{a0}a from t"),
+        "parse error: unexpected token \"select\u{a0}a\" at column 1"
+    );
+    assert_eq!(
+        parse_error("select a from t\u{2003}where a = 1"),
+        "parse error: unexpected token \"=\" at column 25"
+    );
+}
+
+#[test]
+fn collate_binds_tighter_than_concat_and_looser_than_a_sign() {
+    let Ok(Statement::Select(select)) = parse("select -a collate nocase || b from t") else {
+        panic!("not a select");
+    };
+    let column = |name: &str| Expr::Column {
+        table: None,
+        name: name.to_string(),
+    };
+    let expected = Expr::Binary {
+        op: BinaryOp::Concat,
+        left: Box::new(Expr::Collate {
+            expr: Box::new(Expr::Unary {
+                op: UnaryOp::Negate,
+                expr: Box::new(column("a")),
+            }),
+            collation: "nocase".to_string(),
+        }),
+        right: Box::new(column("b")),
+    };
+    assert_eq!(
+        select.columns,
+        [ResultColumn::Expr {
+            expr: expected,
+            alias: None
+        }]
+    );
+}
@@ -0,0 +1,618 @@
+/*
+    queries against the databases in tests/fixtures, each built by the .sql file beside it. the
+    expected rows are what sqlite3 returns for the same query
+*/
+use codecrafters_sqlite::ast::Statement;
+use codecrafters_sqlite::cursor::{IndexCursor, TableCursor};
+use codecrafters_sqlite::page_reader::PageReaderBuilder;
+use codecrafters_sqlite::parser::parse;
+use codecrafters_sqlite::query::{execute_select, QueryOptions};
+use codecrafters_sqlite::schema::Schema;
+use codecrafters_sqlite::value::{Collation, Value};
+
+fn query(database: &str, sql: &str) -> Result<Vec<String>, String> {
+    query_reads(database, sql).map(|(rows, _)| rows)
+}
+
+/* the rows, and how many pages the query read past the schema */
+fn query_reads(database: &str, sql: &str) -> Result<(Vec<String>, u64), String> {
+    query_with(database, sql, &QueryOptions::default())
+}
+
+fn query_with(
+    database: &str,
+    sql: &str,
+    options: &QueryOptions,
+) -> Result<(Vec<String>, u64), String> {
+    let path = format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), database);
+    let mut builder = PageReaderBuilder::open(&path).map_err(|err| err.to_string())?;
+    let schema = Schema::load(&mut builder).map_err(|err| err.to_string())?;
+    let Statement::Select(select) = parse(sql).map_err(|err| err.to_string())? else {
+        panic!("not a select: {}", sql);
+    };
+    let schema_reads = builder.cache_stats().misses;
+    let mut out = Vec::new();
+    execute_select(&select, &schema, &mut builder, options, &mut out)
+        .map_err(|err| err.to_string())?;
+    let rows = String::from_utf8(out)
+        .unwrap()
+        .lines()
+        .map(str::to_string)
+        .collect();
+    Ok((rows, builder.cache_stats().misses - schema_reads))
+}
+
+/* the rows in any order, for queries without ORDER BY */
+fn assert_rows(database: &str, sql: &str, expected: &[&str]) {
+    let mut rows = query(database, sql).unwrap_or_else(|err| panic!("{}: {}", sql, err));
+    rows.sort();
+    let mut expected: Vec<String> = expected.iter().map(|row| row.to_string()).collect();
+    expected.sort();
+    assert_eq!(rows, expected, "{}", sql);
+}
+
+#[test]
+fn comparisons_use_the_column_collation() {
+    let rows = |sql, expected| assert_rows("collate.db", sql, expected);
+    rows("select id from c where n = 'APPLE'", &["1", "2"]);
+    rows("select id from c where 'APPLE' = n", &["1", "2"]);
+    rows("select id from c where n > 'b'", &["3", "4"]);
+    rows(
+        "select id from c where n between 'a' and 'b'",
+        &["1", "2", "5", "6", "8"],
+    );
+    rows(
+        "select id from c where n in ('APPLE', 'CHERRY')",
+        &["1", "2", "4"],
+    );
+    rows("select id from c where r = 'x'", &["1", "2", "8"]);
+    rows("select id from c where r = 'Y'", &["4"]);
+    rows(
+        "select id, case n when 'APPLE' then 1 else 0 end from c where id < 4",
+        &["1|1", "2|1", "3|0"],
+    );
+}
+
+#[test]
+fn left_column_collation_wins() {
+    assert_rows("collate.db", "select id from c where b = n", &["5"]);
+    assert_rows("collate.db", "select id from c where n = b", &["2", "5"]);
+}
+
+#[test]
+fn joins_use_the_equality_collation() {
+    let rows = |sql, expected| assert_rows("collate.db", sql, expected);
+    rows("select count(*) from c join d on c.n = d.k", &["4"]);
+    rows("select count(*) from c join d on c.b = d.k", &["1"]);
+    rows("select count(*) from c join d on d.k = c.b", &["2"]);
+}
+
+#[test]
+fn index_only_seeks_by_its_own_collation() {
+    /* f_m sorts m by NOCASE, but m = ... compares by the column's BINARY */
+    let rows = |sql, expected| assert_rows("indexes.db", sql, expected);
+    rows("select count(*) from f where m = 'AbC'", &["143"]);
+    rows("select count(*) from f where m in ('abc', 'B')", &["142"]);
+    rows("select count(*) from f where m glob 'a*'", &["428"]);
+    rows("select count(*) from f where m like 'ap%'", &["286"]);
+}
+
+#[test]
+fn nocase_index_seeks_nocase_equality() {
+    assert_rows(
+        "collate.db",
+        "select id, w from c join d on d.k = c.n",
+        &["1|1", "2|1", "3|2", "4|3"],
+    );
+    assert_rows("collate.db", "select w from d where k = 'CHERRY'", &["3"]);
+}
+
+#[test]
+fn descending_index_seeks_in_its_own_order() {
+    let rows = |sql, expected| assert_rows("indexes.db", sql, expected);
+    rows("select count(*) from f where k = 5", &["11"]);
+    rows("select count(*) from f where k in (1, 50, 96)", &["31"]);
+    rows("select count(*) from f where s like 'w1%'", &["444"]);
+    rows("select count(*) from f where s glob 'w24*'", &["44"]);
+    rows("select count(*) from f where s = 'w17'", &["4"]);
+}
+
+#[test]
+fn descending_index_keeps_its_order() {
+    let ordered = |sql, expected: &[&str]| assert_eq!(query("indexes.db", sql).unwrap(), expected);
+    ordered(
+        "select id, k from f where k in (0, 96) order by k desc limit 3",
+        &["96|96", "193|96", "290|96"],
+    );
+    ordered(
+        "select id, s from f where s like 'w1%' order by s desc limit 5",
+        &["199|w199", "449|w199", "699|w199", "949|w199", "198|w198"],
+    );
+}
+
+#[test]
+fn joins_seek_descending_indexes() {
+    let rows = |sql, expected| assert_rows("indexes.db", sql, expected);
+    rows("select count(*) from p join f on f.k = p.v", &["42"]);
+    rows("select count(*) from p left join f on f.k = p.v", &["44"]);
+    rows("select count(*) from p join f on f.s = 'w' || p.v", &["20"]);
+    rows(
+        "select count(*) from p join f on f.m = 'abc' and f.k = p.v",
+        &["6"],
+    );
+    rows(
+        "select count(*) from f a join f b on b.k = a.k and b.s = a.s where a.id < 100",
+        &["99"],
+    );
+}
+
+#[test]
+fn joins_hash_on_an_index_of_another_collation() {
+    /* f_m is NOCASE, the equality on m is BINARY */
+    assert_rows(
+        "indexes.db",
+        "select count(*) from f a join f b on b.m = a.m where a.id < 20",
+        &["2715"],
+    );
+}
+
+#[test]
+fn added_columns_read_their_default() {
+    assert_rows(
+        "tables.db",
+        "select * from alt",
+        &[
+            "1|x|dflt|-5|2.0|42|1|abc||it's",
+            "2|y|dflt|-5|2.0|42|1|abc||it's",
+            "3|z||-5|2.0|42|1|abc||it's",
+        ],
+    );
+    assert_rows(
+        "tables.db",
+        "select typeof(n), typeof(r), typeof(s), typeof(z) from alt where id = 1",
+        &["integer|real|integer|null"],
+    );
+}
+
+#[test]
+fn virtual_columns_are_computed() {
+    assert_rows(
+        "tables.db",
+        "select * from g",
+        &["1|5|10|1-5|11|AB|ab", "2||||||", "3|7|14|3-7|15|CD|Cd"],
+    );
+    assert_rows("tables.db", "select a from g where f > 11", &["3"]);
+}
+
+#[test]
+fn without_rowid_tables_scan_their_key_order() {
+    let ordered = |sql, expected: &[&str]| assert_eq!(query("tables.db", sql).unwrap(), expected);
+    ordered("select * from wr", &["a|1|one", "b|2|two", "c|3|"]);
+    ordered("select * from wr2", &["2|y|o", "1|x|p", "3|z|p"]);
+    ordered("select count(*) from wr", &["3"]);
+    assert_rows("tables.db", "select k from wr where v = 2", &["b"]);
+    assert_rows(
+        "tables.db",
+        "select g.a, wr.k from g join wr on wr.v = g.a",
+        &["1|a", "2|b", "3|c"],
+    );
+}
+
+#[test]
+fn row_id_terms_seek_instead_of_scanning() {
+    let (_, scan_reads) = query_reads("indexes.db", "select count(v) from r").unwrap();
+    let reads = |sql: &str, expected: &[&str]| {
+        let (rows, reads) = query_reads("indexes.db", sql).unwrap();
+        assert_eq!(rows, expected, "{}", sql);
+        reads
+    };
+    let v = |id: i64| format!("{:0200}", id);
+
+    assert!(reads("select id from r where id = 1500", &["1500"]) <= 3);
+    assert!(reads("select id from r where 1500.0 = id", &["1500"]) <= 3);
+    assert!(reads("select id from r where id = 'x'", &[]) <= 1);
+    assert!(
+        reads(
+            "select id from r where id in (2999, 7, 7, 4000)",
+            &["7", "2999"]
+        ) <= 6
+    );
+    assert!(reads("select id from r where id = 9 or id = 8", &["8", "9"]) <= 6);
+    assert!(
+        reads(
+            "select id from r where id > 2997",
+            &["2998", "2999", "3000"]
+        ) <= 4
+    );
+    assert!(reads("select id from r where id <= 2", &["1", "2"]) <= 3);
+    assert!(reads("select id from r where 2.5 > id", &["1", "2"]) <= 3);
+    assert!(
+        reads(
+            "select id from r where id between 10 and 1000 and id > 997.5",
+            &["998", "999", "1000"],
+        ) <= 4
+    );
+    assert!(
+        reads(
+            &format!("select id from r where id >= 1001 and v < '{}'", v(1003)),
+            &["1001", "1002"]
+        ) < scan_reads
+    );
+    assert!(scan_reads > 100);
+}
+
+#[test]
+fn unique_constraints_seek_their_automatic_index() {
+    let (_, scan_reads) = query_reads("indexes.db", "select count(pad) from u").unwrap();
+    let reads = |sql: &str, expected: &[&str]| {
+        let (rows, reads) = query_reads("indexes.db", sql).unwrap();
+        assert_eq!(rows, expected, "{}", sql);
+        reads
+    };
+    assert!(reads("select id from u where code = 'c01234'", &["1234"]) <= 4);
+    assert!(reads("select id from u where name = 'NAME77'", &["77"]) <= 4);
+    assert!(scan_reads > 100);
+
+    let path = format!("{}/tests/fixtures/indexes.db", env!("CARGO_MANIFEST_DIR"));
+    let mut builder = PageReaderBuilder::open(&path).unwrap();
+    let schema = Schema::load(&mut builder).unwrap();
+    let key = |name: &str| {
+        let index = schema.index(name).unwrap();
+        let table = schema.table(index.table_name()).unwrap();
+        (0..index.columns.len())
+            .map(|pos| {
+                let column = &index.columns[pos];
+                (
+                    column.name.clone(),
+                    index.collation(pos, table).unwrap(),
+                    column.descending,
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        key("sqlite_autoindex_u_1"),
+        [("code".into(), Collation::Binary, false)]
+    );
+    assert_eq!(
+        key("sqlite_autoindex_u_2"),
+        [("name".into(), Collation::NoCase, false)]
+    );
+    assert_eq!(
+        key("sqlite_autoindex_v_1"),
+        [("w".into(), Collation::Binary, false)]
+    );
+    assert_eq!(
+        key("sqlite_autoindex_v_2"),
+        [("k".into(), Collation::NoCase, true)]
+    );
+}
+
+#[test]
+fn descending_orders_walk_backwards_and_stop_early() {
+    let (_, scan_reads) = query_reads("indexes.db", "select count(v) from r").unwrap();
+    let reads = |sql: &str, expected: &[&str]| {
+        let (rows, reads) = query_reads("indexes.db", sql).unwrap();
+        assert_eq!(rows, expected, "{}", sql);
+        reads
+    };
+    assert!(reads("select id from r order by id desc limit 1", &["3000"]) <= 3);
+    assert!(
+        reads(
+            "select id from r where id between 10 and 2000 order by id desc limit 2",
+            &["2000", "1999"],
+        ) <= 3
+    );
+    assert!(
+        reads(
+            "select code from u order by code desc limit 2",
+            &["c02000", "c01999"]
+        ) <= 3
+    );
+    assert!(scan_reads > 100);
+
+    reads(
+        "select id, k from f order by k desc limit 5",
+        &["96|96", "193|96", "290|96", "387|96", "484|96"],
+    );
+    /* walking f_s backwards, ties come out in descending row_id order */
+    reads(
+        "select id, s from f order by s limit 4",
+        &["1000|w0", "750|w0", "500|w0", "250|w0"],
+    );
+    reads(
+        "select id, v from p order by v desc",
+        &["4|200", "3|96", "2|50", "1|5", "6|1", "5|"],
+    );
+}
+
+#[test]
+fn cursors_step_backwards_over_every_entry() {
+    let path = format!("{}/tests/fixtures/indexes.db", env!("CARGO_MANIFEST_DIR"));
+    let mut builder = PageReaderBuilder::open(&path).unwrap();
+    let schema = Schema::load(&mut builder).unwrap();
+
+    let root = schema.table("f").unwrap().root_page();
+    let forwards: Vec<i64> = TableCursor::new(root)
+        .iter(&mut builder)
+        .map(|row| row.unwrap().row_id)
+        .collect();
+    let mut cursor = TableCursor::new(root);
+    let mut backwards = Vec::new();
+    while let Some(row) = cursor.prev(&mut builder).unwrap() {
+        backwards.push(row.row_id);
+    }
+    backwards.reverse();
+    assert_eq!(forwards.len(), 1000);
+    assert_eq!(backwards, forwards);
+    let mut cursor = TableCursor::new(root);
+    assert_eq!(
+        cursor.seek_back(&mut builder, 500).unwrap().unwrap().row_id,
+        500
+    );
+    assert_eq!(cursor.prev(&mut builder).unwrap().unwrap().row_id, 499);
+    assert!(cursor.seek_back(&mut builder, 0).unwrap().is_none());
+
+    /* an index b-tree keeps entries in its interior cells too */
+    let root = schema.index("f_k").unwrap().root_page();
+    let forwards: Vec<Vec<Value>> = IndexCursor::new(root)
+        .iter(&mut builder)
+        .map(|record| record.unwrap().rows)
+        .collect();
+    let mut cursor = IndexCursor::new(root);
+    let mut backwards = Vec::new();
+    while let Some(record) = cursor.prev(&mut builder).unwrap() {
+        backwards.push(record.rows);
+    }
+    backwards.reverse();
+    assert_eq!(forwards.len(), 1000);
+    assert_eq!(backwards, forwards);
+}
+
+#[test]
+fn virtual_tables_and_views_are_unsupported() {
+    let error = |sql| query("tables.db", sql).unwrap_err();
+    assert_eq!(
+        error("select * from docs"),
+        "unsupported: virtual table docs"
+    );
+    assert_eq!(
+        error("select count(*) from boxes"),
+        "unsupported: virtual table boxes"
+    );
+    assert_eq!(
+        error("select a.id from alt a join docs"),
+        "unsupported: virtual table docs"
+    );
+    assert_eq!(
+        error("select * from alt_view"),
+        "unsupported: view alt_view"
+    );
+    assert_eq!(error("select * from nope"), "no such table: nope");
+    /* the tables a module keeps its data in are ordinary ones */
+    assert_rows(
+        "tables.db",
+        "select * from docs_content",
+        &["1|hello world"],
+    );
+}
+
+#[test]
+fn utf16_text_decodes_and_compares_as_sqlite_stores_it() {
+    for database in ["utf16le.db", "utf16be.db"] {
+        let rows = query(database, "select id, name, length(name) from t order by id").unwrap();
+        assert_eq!(
+            rows,
+            [
+                "1|b|1",
+                "2|ā|1",
+                "3|Ａ|1",
+                "4|😀|1",
+                "5|a|1",
+                "6|ÿ|1",
+                "7|ĀB|2",
+                "8||0",
+                "9|z😀z|3"
+            ],
+            "{}",
+            database
+        );
+        assert_rows(database, "select id from t where name = '😀'", &["4"]);
+        assert_rows(database, "select id from t where name like 'z%'", &["9"]);
+    }
+
+    /* BINARY compares the stored bytes, so the byte order changes the order */
+    let ordered = |database, sql, expected: &[&str]| {
+        assert_eq!(
+            query(database, sql).unwrap(),
+            expected,
+            "{}: {}",
+            database,
+            sql
+        )
+    };
+    let sql = "select id from t order by name";
+    ordered(
+        "utf16le.db",
+        sql,
+        &["8", "7", "2", "3", "4", "5", "1", "9", "6"],
+    );
+    ordered(
+        "utf16be.db",
+        sql,
+        &["8", "5", "1", "9", "6", "7", "2", "4", "3"],
+    );
+    let sql = "select id from t where name > 'b' order by id";
+    ordered("utf16le.db", sql, &["6", "9"]);
+    ordered("utf16be.db", sql, &["2", "3", "4", "6", "7", "9"]);
+    let sql = "select hex(name) from t where id in (2, 4) order by id";
+    ordered("utf16le.db", sql, &["0101", "3DD800DE"]);
+    ordered("utf16be.db", sql, &["0101", "D83DDE00"]);
+}
+
+/* budgets too small to hold a second row, so every sort, group and distinct spills */
+const TINY: QueryOptions = QueryOptions {
+    sort_memory: 1,
+    group_memory: 1,
+    join_memory: 1,
+};
+
+fn spilled(sql: &str) -> Vec<String> {
+    let (rows, _) =
+        query_with("indexes.db", sql, &TINY).unwrap_or_else(|err| panic!("{}: {}", sql, err));
+    assert_eq!(query("indexes.db", sql).unwrap(), rows, "{}", sql);
+    rows
+}
+
+#[test]
+fn sorts_merge_runs_spilled_past_the_budget() {
+    let expected: Vec<String> = (0..10)
+        .rev()
+        .flat_map(|k| (1..=3000).filter(move |id| id % 10 == k))
+        .map(|id| id.to_string())
+        .collect();
+    assert_eq!(
+        spilled("select id from r order by id % 10 desc, v"),
+        expected
+    );
+    assert_eq!(
+        spilled("select id % 10, id from r order by id % 10 desc, v limit 4 offset 1498"),
+        ["5|2985", "5|2995", "4|4", "4|14"]
+    );
+}
+
+#[test]
+fn groups_past_the_budget_spill_and_still_aggregate_once() {
+    let mut rows =
+        spilled("select id % 100, count(*), sum(id), min(id), max(id) from r group by 1");
+    rows.sort();
+    let mut expected: Vec<String> = (0..100)
+        .map(|k| {
+            let ids: Vec<i64> = (1..=3000).filter(|id| id % 100 == k).collect();
+            format!(
+                "{}|{}|{}|{}|{}",
+                k,
+                ids.len(),
+                ids.iter().sum::<i64>(),
+                ids[0],
+                ids[ids.len() - 1]
+            )
+        })
+        .collect();
+    expected.sort();
+    assert_eq!(rows, expected);
+
+    assert_eq!(
+        spilled(
+            "select substr(v, 199) as k, count(*), sum(id), min(id), max(id), avg(id), \
+             group_concat(id / 1000, '') from r group by k having sum(id) % 4 = 0 \
+             order by k limit 5"
+        ),
+        [
+            "00|30|46500|100|3000|1550.0|000000000111111111122222222223",
+            "02|30|43560|2|2902|1452.0|000000000011111111112222222222",
+            "04|30|43620|4|2904|1454.0|000000000011111111112222222222",
+            "06|30|43680|6|2906|1456.0|000000000011111111112222222222",
+            "08|30|43740|8|2908|1458.0|000000000011111111112222222222",
+        ]
+    );
+    assert_eq!(
+        spilled("select count(*) from r group by id % 7 having count(*) > 428"),
+        ["429", "429", "429", "429"]
+    );
+    let mut rows = spilled(
+        "select id % 3, count(distinct id % 5), \
+         total(case when id % 2 = 0 then null else 1 end) from r group by 1",
+    );
+    rows.sort();
+    assert_eq!(rows, ["0|5|500.0", "1|5|500.0", "2|5|500.0"]);
+}
+
+#[test]
+fn distinct_past_the_budget_spills_and_keeps_one_of_each() {
+    let mut rows = spilled("select distinct id % 250 from r");
+    rows.sort_by_key(|row| row.parse::<i64>().unwrap());
+    let expected: Vec<String> = (0..250).map(|k| k.to_string()).collect();
+    assert_eq!(rows, expected);
+
+    /* NULLs are one value to DISTINCT */
+    let mut rows =
+        spilled("select distinct case when id % 3 = 0 then null else id % 5 end, id % 2 from r");
+    rows.sort();
+    assert_eq!(
+        rows,
+        ["0|0", "0|1", "1|0", "1|1", "2|0", "2|1", "3|0", "3|1", "4|0", "4|1", "|0", "|1"]
+    );
+
+    assert_eq!(
+        spilled("select distinct substr(v, 200) from r order by 1 desc limit 3"),
+        ["9", "8", "7"]
+    );
+}
+
+#[test]
+fn collate_on_an_expression_decides_how_it_compares() {
+    let rows = |sql, expected| assert_rows("collate.db", sql, expected);
+    rows(
+        "select id from c where b = 'abc' collate nocase",
+        &["1", "3"],
+    );
+    rows("select id from c where n = 'apple' collate binary", &["1"]);
+    /* the left operand's COLLATE wins over the right's, and both over the columns' */
+    rows(
+        "select id from c where b collate nocase = n collate binary",
+        &["2", "5"],
+    );
+    rows(
+        "select id from c where lower(b) = 'ABC' collate nocase",
+        &["1", "3"],
+    );
+    rows(
+        "select c.id, d.w from c join d on c.b = d.k collate binary",
+        &["4|1"],
+    );
+    rows(
+        "select c.id, d.w from c join d on c.b collate nocase = d.k",
+        &["2|1", "4|1"],
+    );
+    rows(
+        "select distinct b collate nocase from c",
+        &["AbC", "Apple", "b", "Ba", "", "apricot"],
+    );
+    rows(
+        "select b collate nocase, count(*) from c group by 1",
+        &["|1", "AbC|2", "Apple|2", "apricot|1", "b|1", "Ba|1"],
+    );
+    assert_eq!(
+        query(
+            "collate.db",
+            "select b from c order by b collate nocase desc, id"
+        )
+        .unwrap(),
+        ["Ba", "b", "apricot", "Apple", "apple", "AbC", "ABC", ""]
+    );
+    assert_eq!(
+        query("collate.db", "select id from c where b = 'x' collate foo").unwrap_err(),
+        "parse error: no such collation sequence: foo"
+    );
+
+    /* the nocase index on name only serves comparisons that are nocase */
+    let (rows, seek_reads) = query_reads(
+        "indexes.db",
+        "select id from u where name = 'NAME7' collate nocase",
+    )
+    .unwrap();
+    assert_eq!(rows, ["7"]);
+    let (rows, scan_reads) = query_reads(
+        "indexes.db",
+        "select id from u where name = 'Name7' collate binary",
+    )
+    .unwrap();
+    assert_eq!(rows, ["7"]);
+    assert!(
+        seek_reads * 10 < scan_reads,
+        "{} {}",
+        seek_reads,
+        scan_reads
+    );
+}
@@ -0,0 +1,204 @@
+/*
+    -wal files built frame by frame next to a copy of sample.db. the frames replace the apples
+    page (2) with the oranges page (4), so the apples a query reads say which frames were used
+*/
+use codecrafters_sqlite::ast::Statement;
+use codecrafters_sqlite::page_reader::PageReaderBuilder;
+use codecrafters_sqlite::parser::parse;
+use codecrafters_sqlite::query::{execute_select, QueryOptions};
+use codecrafters_sqlite::schema::Schema;
+
+const SAMPLE: &[u8] = include_bytes!("../sample.db");
+const PAGE_SIZE: usize = 4096;
+const SALT: (u32, u32) = (0x1234_5678, 0x9abc_def0);
+
+const APPLES: &[&str] = &["Granny Smith", "Fuji", "Honeycrisp", "Golden Delicious"];
+const ORANGES: &[&str] = &[
+    "Mandarin",
+    "Tangelo",
+    "Tangerine",
+    "Clementine",
+    "Valencia Orange",
+    "Navel Orange",
+];
+
+fn page(page_no: usize) -> Vec<u8> {
+    SAMPLE[(page_no - 1) * PAGE_SIZE..page_no * PAGE_SIZE].to_vec()
+}
+
+struct Frame {
+    page_no: u32,
+    /* the database size in pages for a commit frame, 0 otherwise */
+    commit: u32,
+    data: Vec<u8>,
+    salt: (u32, u32),
+}
+
+impl Frame {
+    fn new(page_no: u32, commit: u32, data: Vec<u8>) -> Self {
+        Self {
+            page_no,
+            commit,
+            data,
+            salt: SALT,
+        }
+    }
+}
+
+fn checksum(bytes: &[u8], initial: (u32, u32), big_endian: bool) -> (u32, u32) {
+    let (mut s0, mut s1) = initial;
+    let word = |chunk: &[u8]| {
+        let chunk: [u8; 4] = chunk.try_into().unwrap();
+        if big_endian {
+            u32::from_be_bytes(chunk)
+        } else {
+            u32::from_le_bytes(chunk)
+        }
+    };
+    for pair in bytes.chunks_exact(8) {
+        s0 = s0.wrapping_add(word(&pair[0..4])).wrapping_add(s1);
+        s1 = s1.wrapping_add(word(&pair[4..8])).wrapping_add(s0);
+    }
+    (s0, s1)
+}
+
+/* a wal whose checksums are the ones the magic number says, chained frame to frame */
+fn wal(frames: &[Frame], big_endian: bool) -> Vec<u8> {
+    wal_checksummed(frames, big_endian, big_endian)
+}
+
+fn wal_checksummed(frames: &[Frame], big_endian: bool, frames_big_endian: bool) -> Vec<u8> {
+    let magic: u32 = if big_endian { 0x377f0683 } else { 0x377f0682 };
+    let mut bytes = Vec::new();
+    for word in [magic, 3007000, PAGE_SIZE as u32, 0, SALT.0, SALT.1] {
+        bytes.extend(word.to_be_bytes());
+    }
+    let mut sum = checksum(&bytes, (0, 0), big_endian);
+    bytes.extend(sum.0.to_be_bytes());
+    bytes.extend(sum.1.to_be_bytes());
+
+    for frame in frames {
+        let mut header = Vec::new();
+        for word in [frame.page_no, frame.commit, frame.salt.0, frame.salt.1] {
+            header.extend(word.to_be_bytes());
+        }
+        sum = checksum(&header[0..8], sum, frames_big_endian);
+        sum = checksum(&frame.data, sum, frames_big_endian);
+        header.extend(sum.0.to_be_bytes());
+        header.extend(sum.1.to_be_bytes());
+        bytes.extend(header);
+        bytes.extend(&frame.data);
+    }
+    bytes
+}
+
+/* the offset of frame `index`'s page data in a wal */
+fn frame_data_offset(index: usize) -> usize {
+    32 + index * (24 + PAGE_SIZE) + 24
+}
+
+/* the apple names sample.db shows with this -wal beside it, and the frames it used */
+fn apples_with(name: &str, wal: &[u8]) -> (Vec<String>, u32) {
+    let path = std::env::temp_dir().join(format!("wal-{}-{}.db", std::process::id(), name));
+    let path = path.to_str().unwrap().to_string();
+    let wal_path = format!("{}-wal", path);
+    std::fs::write(&path, SAMPLE).unwrap();
+    std::fs::write(&wal_path, wal).unwrap();
+
+    let mut builder = PageReaderBuilder::open(&path).unwrap();
+    let frame_count = builder.wal().map_or(0, |wal| wal.frame_count);
+    let schema = Schema::load(&mut builder).unwrap();
+    let Ok(Statement::Select(select)) = parse("select name from apples") else {
+        unreachable!();
+    };
+    let mut out = Vec::new();
+    execute_select(
+        &select,
+        &schema,
+        &mut builder,
+        &QueryOptions::default(),
+        &mut out,
+    )
+    .unwrap();
+    std::fs::remove_file(&path).unwrap();
+    std::fs::remove_file(&wal_path).unwrap();
+
+    let names = String::from_utf8(out)
+        .unwrap()
+        .lines()
+        .map(str::to_string)
+        .collect();
+    (names, frame_count)
+}
+
+#[test]
+fn committed_frames_replace_pages() {
+    for big_endian in [false, true] {
+        let bytes = wal(&[Frame::new(2, 4, page(4))], big_endian);
+        assert_eq!(apples_with("committed", &bytes), (names(ORANGES), 1));
+    }
+}
+
+#[test]
+fn checksums_follow_the_magic_number() {
+    /* frames checksummed the other way than the magic number says */
+    for big_endian in [false, true] {
+        let bytes = wal_checksummed(&[Frame::new(2, 4, page(4))], big_endian, !big_endian);
+        assert_eq!(apples_with("wrong-endian", &bytes), (names(APPLES), 0));
+    }
+
+    /* the header's own checksum is taken the same way */
+    let mut bytes = wal(&[Frame::new(2, 4, page(4))], false);
+    bytes[3] = 0x83;
+    assert_eq!(apples_with("wrong-magic", &bytes), (names(APPLES), 0));
+}
+
+#[test]
+fn frames_of_another_salt_end_the_log() {
+    let stale = Frame {
+        salt: (SALT.0 + 1, SALT.1),
+        ..Frame::new(2, 4, page(2))
+    };
+    let bytes = wal(&[Frame::new(2, 4, page(4)), stale], false);
+    assert_eq!(apples_with("salt", &bytes), (names(ORANGES), 1));
+
+    let bytes = wal(
+        &[Frame {
+            salt: (SALT.0, SALT.1 ^ 1),
+            ..Frame::new(2, 4, page(4))
+        }],
+        false,
+    );
+    assert_eq!(apples_with("salt-first", &bytes), (names(APPLES), 0));
+}
+
+#[test]
+fn a_broken_checksum_ends_the_log() {
+    /* the third frame's checksum is right for the chain, but follows a broken frame */
+    let mut bytes = wal(
+        &[
+            Frame::new(2, 4, page(4)),
+            Frame::new(2, 4, page(2)),
+            Frame::new(4, 4, page(2)),
+        ],
+        true,
+    );
+    bytes[frame_data_offset(1) + 100] ^= 0xff;
+    assert_eq!(apples_with("checksum", &bytes), (names(ORANGES), 1));
+}
+
+#[test]
+fn frames_after_the_last_commit_are_ignored() {
+    let bytes = wal(
+        &[Frame::new(2, 4, page(4)), Frame::new(2, 0, page(2))],
+        false,
+    );
+    assert_eq!(apples_with("uncommitted", &bytes), (names(ORANGES), 1));
+
+    let bytes = wal(&[Frame::new(2, 0, page(4))], false);
+    assert_eq!(apples_with("none-committed", &bytes), (names(APPLES), 0));
+}
+
+fn names(names: &[&str]) -> Vec<String> {
+    names.iter().map(|name| name.to_string()).collect()
+}